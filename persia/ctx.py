@@ -4,13 +4,13 @@ import socket
 
 from enum import Enum
 from queue import Queue
-from typing import List, Tuple, Optional, Union
+from typing import Dict, List, Tuple, Optional, Union
 
 import torch
 
 from persia import env
 from persia.logger import get_default_logger
-from persia.embedding.optim import Optimizer
+from persia.embedding.optim import Optimizer, LRSchedule
 from persia.embedding import EmbeddingConfig, get_default_embedding_config
 from persia.embedding.data import PersiaBatch
 from persia.prelude import (
@@ -428,6 +428,8 @@ class EmbeddingCtx(BaseCtx):
             embedding_config.admit_probability,
             embedding_config.weight_bound > 0,
             embedding_config.weight_bound,
+            embedding_config.admit_frequency,
+            embedding_config.feature_group_hyperparameters(),
         )
 
     def forward(
@@ -761,6 +763,8 @@ class TrainCtx(EmbeddingCtx):
         lookup_emb_directly: bool = True,
         mixed_precision: bool = True,
         distributed_option: Optional[DistributedBaseOption] = None,
+        feature_group_optimizers: Optional[Dict[str, Optimizer]] = None,
+        embedding_lr_schedule: Optional[LRSchedule] = None,
         *args,
         **kwargs,
     ):
@@ -780,6 +784,11 @@ class TrainCtx(EmbeddingCtx):
             lookup_emb_directly (bool, optional): lookup embedding directly without a separate data loader.
             mixed_precision (bool): whether to enable mixed_precision.
             distributed_option (DistributedBaseOption, optional): option for distributed training.
+            feature_group_optimizers (Dict[str, persia.embedding.optim.Optimizer], optional): optimizers
+                of feature groups replacing ``embedding_optimizer`` for the embedding parameters of them.
+            embedding_lr_schedule (persia.embedding.optim.LRSchedule, optional): learning rate schedule
+                of the embedding optimizers, driven by the global step the embedding servers
+                advance once per gradient update, see :meth:`set_embedding_global_step`.
         """
         super(TrainCtx, self).__init__(PreprocessMode.TRAIN, *args, **kwargs)
 
@@ -837,6 +846,8 @@ class TrainCtx(EmbeddingCtx):
 
         self.dense_optimizer = dense_optimizer
         self.embedding_optimizer = embedding_optimizer
+        self.feature_group_optimizers = feature_group_optimizers or {}
+        self.embedding_lr_schedule = embedding_lr_schedule
 
         self.wait_servers_ready()
 
@@ -855,6 +866,10 @@ class TrainCtx(EmbeddingCtx):
         super()._enter()
 
         self.embedding_optimizer.apply()
+        for feature_group_name, optimizer in self.feature_group_optimizers.items():
+            optimizer.apply([feature_group_name])
+        if self.embedding_lr_schedule is not None:
+            self.embedding_lr_schedule.apply()
         self.backward_engine.launch(self.backward_workers_size)
 
     def _exit(self):
@@ -890,6 +905,36 @@ class TrainCtx(EmbeddingCtx):
         self.common_context.init_nats_publisher(self.world_size)
         self.common_context.wait_servers_ready()
 
+    def set_embedding_learning_rate(self, lr: float):
+        """Set the learning rate of the embedding optimizers, e.g. to follow the learning rate of
+        ``dense_optimizer``. This replaces ``embedding_lr_schedule``.
+
+        Arguments:
+            lr (float): learning rate of all embedding optimizers.
+        """
+        self.common_context.set_embedding_learning_rate(lr)
+
+    def set_embedding_global_step(self, global_step: int):
+        """Move ``embedding_lr_schedule`` to the global step of the training. The embedding servers
+        keep advancing the global step by one per gradient update from there.
+
+        Arguments:
+            global_step (int): global step of the training.
+        """
+        self.common_context.set_embedding_global_step(global_step)
+
+    def reload_embedding_config(self, embedding_config_file: str):
+        """Reload the embedding config of the embedding workers and embedding servers without
+        restarting them. Only new feature groups and slots can be added, the existing ones
+        must be left unchanged.
+
+        Arguments:
+            embedding_config_file (str): path of the updated embedding config file.
+        """
+        with open(embedding_config_file, "r") as file:
+            content = file.read()
+        self.common_context.reload_embedding_config(content)
+
     def backward(
         self, loss: torch.Tensor, embedding_gradient_check_frequency: int = 20
     ) -> torch.Tensor:
@@ -1,4 +1,4 @@
-from typing import Tuple
+from typing import Dict, Optional, Tuple
 
 
 class EmbeddingConfig:
@@ -9,12 +9,57 @@ class EmbeddingConfig:
         emb_initialization: Tuple[float, float] = (-0.01, 0.01),
         admit_probability: float = 1.0,
         weight_bound: float = 10,
+        admit_frequency: Optional[Dict[str, int]] = None,
+        feature_group_config: Optional[Dict[str, "FeatureGroupConfig"]] = None,
     ):
         """
         Arguments:
             emb_initialization (Tuple[float, float], optional): lower and upper bound of embedding uniform initialization.
             admit_probability (float, optional): the probability (0<=, <=1) of admitting a new embedding.
             weight_bound (float, optional): restrict each element value of an embedding in [-weight_bound, weight_bound].
+            admit_frequency (Dict[str, int], optional): the number of times a new embedding of each feature group
+                has to be seen before it is admitted. Feature groups not listed are admitted at first sight.
+            feature_group_config (Dict[str, FeatureGroupConfig], optional): hyperparameters of each feature group
+                overriding the ones above and the ones in the slot configs of the embedding config file.
+        """
+        self.emb_initialization = emb_initialization
+        self.admit_probability = admit_probability
+        self.weight_bound = weight_bound
+        self.admit_frequency = admit_frequency or {}
+        self.feature_group_config = feature_group_config or {}
+
+    def feature_group_hyperparameters(
+        self,
+    ) -> Dict[
+        str, Tuple[Optional[Tuple[float, float]], Optional[float], Optional[float]]
+    ]:
+        """Feature group hyperparameters in the form accepted by the embedding servers."""
+        return {
+            name: (
+                config.emb_initialization,
+                config.admit_probability,
+                config.weight_bound,
+            )
+            for name, config in self.feature_group_config.items()
+        }
+
+
+class FeatureGroupConfig:
+    r"""Embedding hyperparameters of a feature group, argument of :class:`.EmbeddingConfig`.
+    Hyperparameters left unset fall back to the slot configs and then to :class:`.EmbeddingConfig`."""
+
+    def __init__(
+        self,
+        emb_initialization: Optional[Tuple[float, float]] = None,
+        admit_probability: Optional[float] = None,
+        weight_bound: Optional[float] = None,
+    ):
+        """
+        Arguments:
+            emb_initialization (Tuple[float, float], optional): lower and upper bound of embedding uniform initialization.
+            admit_probability (float, optional): the probability (0<=, <=1) of admitting a new embedding.
+            weight_bound (float, optional): restrict each element value of an embedding in [-weight_bound, weight_bound],
+                even if the weight bound is disabled in :class:`.EmbeddingConfig`.
         """
         self.emb_initialization = emb_initialization
         self.admit_probability = admit_probability
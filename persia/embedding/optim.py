@@ -1,7 +1,7 @@
 from abc import ABC
-from typing import Tuple
+from typing import List, Optional, Tuple
 
-from persia.prelude import OptimizerBase
+from persia.prelude import OptimizerBase, LearningRateScheduleBase
 
 
 class Optimizer(ABC):
@@ -10,9 +10,14 @@ class Optimizer(ABC):
     def __init__(self):
         self.optimizer_base = OptimizerBase()
 
-    def apply(self):
-        """Register sparse optimizer to embedding server."""
-        self.optimizer_base.apply()
+    def apply(self, feature_groups: Optional[List[str]] = None):
+        """Register sparse optimizer to embedding server.
+
+        Arguments:
+            feature_groups (List[str], optional): feature groups updated by the optimizer. The optimizer
+                updates all feature groups without an optimizer of their own if not set.
+        """
+        self.optimizer_base.apply(feature_groups)
 
 
 class SGD(Optimizer):
@@ -22,7 +27,8 @@ class SGD(Optimizer):
         """
         Arguments:
             lr(float): learning rate.
-            momentum(float, optional): momentum factor.
+            momentum(float, optional): momentum factor, unused, see :class:`MomentumSGD` for SGD with
+                momentum.
             weight_decay(float, optional): parameters L2 penalty factor.
         """
         super(SGD, self).__init__()
@@ -46,7 +52,8 @@ class Adam(Optimizer):
         Arguments:
             lr(float): learning rate.
             betas(tuple[float,float], optional): calculate the running averages of gradient and its square.
-            weight_decay(float, optional): parameters L2 penalty factor.
+            weight_decay(float, optional): parameters L2 penalty factor, see :class:`AdamW` for the decoupled
+                weight decay.
             eps(float, optional): epsilon to avoid div zero.
         """
         super(Adam, self).__init__()
@@ -54,7 +61,9 @@ class Adam(Optimizer):
         self.betas = betas
         self.weight_decay = weight_decay
         self.eps = eps
-        self.optimizer_base.init_adam(self.lr, self.betas, self.eps)
+        self.optimizer_base.init_adam(
+            self.lr, self.betas, self.eps, self.weight_decay
+        )
 
 
 class Adagrad(Optimizer):
@@ -94,3 +103,221 @@ class Adagrad(Optimizer):
             self.eps,
             self.vectorwise_shared,
         )
+
+
+class FTRL(Optimizer):
+    r"""A wrapper to config the embedding-server FTRL-Proximal optimizer."""
+
+    def __init__(
+        self,
+        lr: float = 5e-2,
+        beta: float = 1.0,
+        l1: float = 0.0,
+        l2: float = 0.0,
+        initial_accumulator_value: float = 0.1,
+    ):
+        """
+        Arguments:
+            lr (float): learning rate.
+            beta (float, optional): smoothing term of the per coordinate learning rate.
+            l1 (float, optional): L1 regularization strength, larger values make embeddings sparser.
+            l2 (float, optional): L2 regularization strength.
+            initial_accumulator_value (float, optional): initialization value of the squared gradient accumulator.
+        """
+        super(FTRL, self).__init__()
+        self.lr = lr
+        self.beta = beta
+        self.l1 = l1
+        self.l2 = l2
+        self.initial_accumulator_value = initial_accumulator_value
+        self.optimizer_base.init_ftrl(
+            self.lr,
+            self.beta,
+            self.l1,
+            self.l2,
+            self.initial_accumulator_value,
+        )
+
+
+class AdamW(Optimizer):
+    r"""A wrapper to config the embedding-server AdamW optimizer."""
+
+    def __init__(
+        self,
+        lr: float = 1e-3,
+        betas: Tuple[float, float] = (0.9, 0.999),
+        weight_decay: float = 1e-2,
+        eps: float = 1e-8,
+    ):
+        """
+        Arguments:
+            lr(float): learning rate.
+            betas(tuple[float,float], optional): calculate the running averages of gradient and its square.
+            weight_decay(float, optional): decoupled weight decay factor.
+            eps(float, optional): epsilon to avoid div zero.
+        """
+        super(AdamW, self).__init__()
+        self.lr = lr
+        self.betas = betas
+        self.weight_decay = weight_decay
+        self.eps = eps
+        self.optimizer_base.init_adamw(self.lr, self.betas, self.eps, self.weight_decay)
+
+
+class RMSprop(Optimizer):
+    r"""A wrapper to config the embedding-server RMSprop optimizer."""
+
+    def __init__(
+        self,
+        lr: float = 1e-2,
+        alpha: float = 0.99,
+        eps: float = 1e-8,
+        initial_accumulator_value: float = 0,
+    ):
+        """
+        Arguments:
+            lr(float): learning rate.
+            alpha(float, optional): smoothing constant of the squared gradient average.
+            eps(float, optional): epsilon to avoid div zero.
+            initial_accumulator_value(float, optional): initialization value of the squared gradient average.
+        """
+        super(RMSprop, self).__init__()
+        self.lr = lr
+        self.alpha = alpha
+        self.eps = eps
+        self.initial_accumulator_value = initial_accumulator_value
+        self.optimizer_base.init_rmsprop(
+            self.lr, self.alpha, self.eps, self.initial_accumulator_value
+        )
+
+
+class LAMB(Optimizer):
+    r"""A wrapper to config the embedding-server LAMB optimizer, each embedding is scaled by its own trust ratio."""
+
+    def __init__(
+        self,
+        lr: float = 1e-3,
+        betas: Tuple[float, float] = (0.9, 0.999),
+        weight_decay: float = 0,
+        eps: float = 1e-6,
+    ):
+        """
+        Arguments:
+            lr(float): learning rate.
+            betas(tuple[float,float], optional): calculate the running averages of gradient and its square.
+            weight_decay(float, optional): decoupled weight decay factor.
+            eps(float, optional): epsilon to avoid div zero.
+        """
+        super(LAMB, self).__init__()
+        self.lr = lr
+        self.betas = betas
+        self.weight_decay = weight_decay
+        self.eps = eps
+        self.optimizer_base.init_lamb(self.lr, self.betas, self.eps, self.weight_decay)
+
+
+class MomentumSGD(Optimizer):
+    r"""A wrapper to config the embedding-server SGD optimizer with momentum, which keeps a momentum
+    buffer of the embedding size for each embedding. Embeddings trained by :class:`SGD` keep no
+    optimizer state, their momentum buffer starts from zero when switching to this optimizer.
+    """
+
+    def __init__(
+        self,
+        lr: float,
+        momentum: float = 0.9,
+        weight_decay: float = 0.0,
+        nesterov: bool = False,
+    ):
+        """
+        Arguments:
+            lr(float): learning rate.
+            momentum(float, optional): momentum factor.
+            weight_decay(float, optional): parameters L2 penalty factor.
+            nesterov(bool, optional): whether to use Nesterov momentum.
+        """
+        super(MomentumSGD, self).__init__()
+        assert momentum > 0, "MomentumSGD requires a momentum > 0, use SGD otherwise"
+        self.lr = lr
+        self.momentum = momentum
+        self.weight_decay = weight_decay
+        self.nesterov = nesterov
+        self.optimizer_base.init_momentum_sgd(
+            self.lr, self.weight_decay, self.momentum, self.nesterov
+        )
+
+
+class LRSchedule(ABC):
+    r"""Base learning rate schedule of the embedding optimizers, driven by the global step of the
+    embedding servers.
+
+    Each embedding server advances the global step by one per gradient update it receives,
+    i.e. once per batch of each trainer. :meth:`persia.ctx.TrainCtx.set_embedding_global_step`
+    moves the global step to a given one, e.g. when resuming a training.
+
+    The schedule scales the learning rate each optimizer is registered with.
+    """
+
+    def __init__(self):
+        self.schedule_base = LearningRateScheduleBase()
+
+    def apply(self):
+        """Register the learning rate schedule to embedding server."""
+        self.schedule_base.apply()
+
+
+class StepLR(LRSchedule):
+    r"""Decays the learning rate by ``gamma`` every ``step_size`` steps."""
+
+    def __init__(self, step_size: int, gamma: float = 0.1):
+        """
+        Arguments:
+            step_size(int): period of the learning rate decay.
+            gamma(float, optional): multiplicative factor of the learning rate decay.
+        """
+        super(StepLR, self).__init__()
+        self.step_size = step_size
+        self.gamma = gamma
+        self.schedule_base.init_step_decay(self.step_size, self.gamma)
+
+
+class CosineAnnealingLR(LRSchedule):
+    r"""Anneals the learning rate to ``min_lr`` in ``total_steps`` steps along a half cosine."""
+
+    def __init__(self, total_steps: int, min_lr: float = 0.0):
+        """
+        Arguments:
+            total_steps(int): number of steps to anneal the learning rate in.
+            min_lr(float, optional): learning rate at the end of the annealing.
+        """
+        super(CosineAnnealingLR, self).__init__()
+        self.total_steps = total_steps
+        self.min_lr = min_lr
+        self.schedule_base.init_cosine(self.total_steps, self.min_lr)
+
+
+class LinearWarmupLR(LRSchedule):
+    r"""Increases the learning rate linearly from zero in ``warmup_steps`` steps."""
+
+    def __init__(self, warmup_steps: int):
+        """
+        Arguments:
+            warmup_steps(int): number of warmup steps.
+        """
+        super(LinearWarmupLR, self).__init__()
+        self.warmup_steps = warmup_steps
+        self.schedule_base.init_linear_warmup(self.warmup_steps)
+
+
+class InverseSqrtLR(LRSchedule):
+    r"""Linear warmup in ``warmup_steps`` steps, then decays the learning rate with the inverse square
+    root of the step."""
+
+    def __init__(self, warmup_steps: int):
+        """
+        Arguments:
+            warmup_steps(int): number of warmup steps.
+        """
+        super(InverseSqrtLR, self).__init__()
+        self.warmup_steps = warmup_steps
+        self.schedule_base.init_inverse_sqrt(self.warmup_steps)
@@ -408,6 +408,8 @@ if __name__ == "__main__":
                 embedding_config.admit_probability,
                 embedding_config.weight_bound > 0,
                 embedding_config.weight_bound,
+                embedding_config.admit_frequency,
+                embedding_config.feature_group_hyperparameters(),
             )
             ctx.common_context.wait_servers_ready()
 
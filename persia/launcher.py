@@ -93,11 +93,32 @@ config filepath to cli command.
     persia-launcher embedding-parameter-server \
         --replica-index 0 --replica-size 1
 
+=================
+Overriding the Global Config
+=================
+
+The values of the global config file can be overridden without editing the file. The
+environment variables prefixed with ``PERSIA__`` override the values of the config file, with
+the nested keys separated by ``__``. The ``--set`` options of ``embedding-worker`` and
+``embedding-parameter-server`` take precedence over both. The effective global config is
+printed when the ``embedding-worker`` and ``embedding-parameter-server`` start.
+
+.. code-block::
+
+    export PERSIA__EMBEDDING_PARAMETER_SERVER_CONFIG__CAPACITY=1000000
+
+    # Launch the embedding-worker
+    persia-launcher embedding-worker \
+        --replica-index 0 --replica-size 1 \
+        --set embedding_worker_config.forward_buffer_size=1000
+
 """
 
 import os
 import click
 
+from typing import Tuple
+
 from persia.utils import run_command, resolve_binary_execute_path
 
 _ENV = os.environ.copy()
@@ -177,12 +198,20 @@ def data_loader(filepath: str, replica_index: int, replica_size: int):
 @click.option(
     "--replica-size", type=str, default=1, help="Replica num of embedding worker"
 )
+@click.option(
+    "--set",
+    "global_config_overrides",
+    multiple=True,
+    help="Override a value of the global config with KEY=VALUE, e.g. \
+        embedding_worker_config.forward_buffer_size=1000. Takes precedence over the PERSIA__ prefixed environment variables",
+)
 def embedding_worker(
     port: int,
     embedding_config: str,
     global_config: str,
     replica_index: int,
     replica_size: int,
+    global_config_overrides: Tuple[str],
 ):
     executable_path = resolve_binary_execute_path("persia-embedding-worker")
     cmd = [
@@ -198,6 +227,8 @@ def embedding_worker(
         "--replica-size",
         replica_size,
     ]
+    for global_config_override in global_config_overrides:
+        cmd.extend(["--set", global_config_override])
     run_command(cmd, _ENV)
 
 
@@ -223,12 +254,20 @@ def embedding_worker(
 @click.option(
     "--replica-size", type=str, default=1, help="Replica num of embedding server"
 )
+@click.option(
+    "--set",
+    "global_config_overrides",
+    multiple=True,
+    help="Override a value of the global config with KEY=VALUE, e.g. \
+        embedding_parameter_server_config.capacity=1000000. Takes precedence over the PERSIA__ prefixed environment variables",
+)
 def embedding_parameter_server(
     port: int,
     embedding_config: str,
     global_config: str,
     replica_index: int,
     replica_size: int,
+    global_config_overrides: Tuple[str],
 ):
     executable_path = resolve_binary_execute_path("persia-embedding-parameter-server")
     cmd = [
@@ -244,6 +283,8 @@ def embedding_parameter_server(
         "--replica-size",
         replica_size,
     ]
+    for global_config_override in global_config_overrides:
+        cmd.extend(["--set", global_config_override])
     run_command(cmd, _ENV)
 
 
@@ -34,7 +34,7 @@ register_submodule(
 # pytype: disable=import-error
 from persia_core import PersiaCommonContext, is_cuda_feature_available  # noqa
 
-from persia_core.optim import OptimizerBase
+from persia_core.optim import OptimizerBase, LearningRateScheduleBase
 from persia_core.data import PersiaBatch as _PersiaBatch, check_pyarray_dtype_valid
 from persia_core.utils import (
     PersiaMessageQueueServer,
@@ -1,10 +1,15 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use persia_libs::{hashbrown::HashMap, ndarray, parking_lot::RwLock};
+use persia_libs::{
+    hashbrown::HashMap,
+    parking_lot::{RwLock, RwLockReadGuard},
+};
 
 use persia_embedding_config::EmbeddingConfig;
 use persia_simd::{
-    adam_avx2, decayed_adagrad_avx2, decayed_adagrad_vectorwise_shared_avx2, decayed_sgd_avx2,
+    adam, adamw, decayed_adagrad, decayed_adagrad_vectorwise_shared, decayed_sgd, ftrl, lamb,
+    momentum_sgd, rmsprop,
 };
 use persia_speedy::{Readable, Writable};
 
@@ -13,6 +18,26 @@ pub enum OptimizerConfig {
     Adam(AdamConfig),
     SGD(NaiveSGDConfig),
     Adagrad(AdagradConfig),
+    FTRL(FTRLConfig),
+    AdamW(AdamWConfig),
+    RMSProp(RMSPropConfig),
+    MomentumSGD(MomentumSGDConfig),
+    LAMB(LAMBConfig),
+}
+
+impl OptimizerConfig {
+    pub fn lr(&self) -> f32 {
+        match self {
+            OptimizerConfig::Adam(config) => config.lr,
+            OptimizerConfig::SGD(config) => config.lr,
+            OptimizerConfig::Adagrad(config) => config.lr,
+            OptimizerConfig::FTRL(config) => config.lr,
+            OptimizerConfig::AdamW(config) => config.lr,
+            OptimizerConfig::RMSProp(config) => config.lr,
+            OptimizerConfig::MomentumSGD(config) => config.lr,
+            OptimizerConfig::LAMB(config) => config.lr,
+        }
+    }
 }
 
 #[derive(Readable, Writable, Debug, Clone)]
@@ -21,6 +46,8 @@ pub struct AdamConfig {
     pub beta1: f32,
     pub beta2: f32,
     pub eps: f32,
+    // L2 penalty added to the gradients, see AdamW for the decoupled one
+    pub weight_decay: f32,
 }
 
 #[derive(Readable, Writable, Debug, Clone)]
@@ -39,10 +66,62 @@ pub struct AdagradConfig {
     pub vectorwise_shared: bool,
 }
 
+#[derive(Readable, Writable, Debug, Clone)]
+pub struct FTRLConfig {
+    pub lr: f32,
+    pub beta: f32,
+    // L1 regularization strength, weights with accumulated gradients below it are zeroed
+    pub lambda1: f32,
+    pub lambda2: f32,
+    // initial value of the squared gradient accumulator
+    pub initialization: f32,
+}
+
+#[derive(Readable, Writable, Debug, Clone)]
+pub struct AdamWConfig {
+    pub lr: f32,
+    pub beta1: f32,
+    pub beta2: f32,
+    pub eps: f32,
+    // decoupled from the gradient moments
+    pub weight_decay: f32,
+}
+
+#[derive(Readable, Writable, Debug, Clone)]
+pub struct RMSPropConfig {
+    pub lr: f32,
+    // smoothing constant of the squared gradient average
+    pub alpha: f32,
+    pub eps: f32,
+    pub initialization: f32,
+}
+
+#[derive(Readable, Writable, Debug, Clone)]
+pub struct MomentumSGDConfig {
+    pub lr: f32,
+    pub wd: f32,
+    pub momentum: f32,
+    pub nesterov: bool,
+}
+
+#[derive(Readable, Writable, Debug, Clone)]
+pub struct LAMBConfig {
+    pub lr: f32,
+    pub beta1: f32,
+    pub beta2: f32,
+    pub eps: f32,
+    pub weight_decay: f32,
+}
+
 pub enum Optimizer {
     Adam(Adam),
     SGD(NaiveSGD),
     Adagrad(Adagrad),
+    FTRL(FTRL),
+    AdamW(AdamW),
+    RMSProp(RMSProp),
+    MomentumSGD(MomentumSGD),
+    LAMB(LAMB),
 }
 
 impl Optimizer {
@@ -51,6 +130,11 @@ impl Optimizer {
             OptimizerConfig::SGD(config) => Self::SGD(NaiveSGD { config }),
             OptimizerConfig::Adagrad(config) => Self::Adagrad(Adagrad { config }),
             OptimizerConfig::Adam(config) => Self::Adam(Adam::new(config)),
+            OptimizerConfig::FTRL(config) => Self::FTRL(FTRL { config }),
+            OptimizerConfig::AdamW(config) => Self::AdamW(AdamW::new(config)),
+            OptimizerConfig::RMSProp(config) => Self::RMSProp(RMSProp { config }),
+            OptimizerConfig::MomentumSGD(config) => Self::MomentumSGD(MomentumSGD { config }),
+            OptimizerConfig::LAMB(config) => Self::LAMB(LAMB::new(config)),
         }
     }
 
@@ -59,8 +143,304 @@ impl Optimizer {
             Optimizer::Adagrad(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
             Optimizer::SGD(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
             Optimizer::Adam(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
+            Optimizer::FTRL(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
+            Optimizer::AdamW(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
+            Optimizer::RMSProp(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
+            Optimizer::MomentumSGD(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
+            Optimizer::LAMB(val) => Box::new(val) as Box<dyn Optimizable + Send + Sync>,
+        }
+    }
+}
+
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub struct StepDecaySchedule {
+    pub step_size: u64,
+    pub gamma: f32,
+}
+
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub struct CosineSchedule {
+    pub total_steps: u64,
+    pub min_lr: f32,
+}
+
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub struct WarmupSchedule {
+    pub warmup_steps: u64,
+}
+
+/// Learning rate of the optimizers as a function of their base learning rate and the global
+/// step of the training.
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub enum LearningRateSchedule {
+    // decays the learning rate by gamma every step_size steps
+    StepDecay(StepDecaySchedule),
+    // anneals the learning rate to min_lr in total_steps steps along a half cosine
+    Cosine(CosineSchedule),
+    // increases the learning rate linearly from zero in warmup_steps steps
+    LinearWarmup(WarmupSchedule),
+    // linear warmup, then decays the learning rate with the inverse square root of the step
+    InverseSqrt(WarmupSchedule),
+}
+
+impl LearningRateSchedule {
+    pub fn lr(&self, base_lr: f32, step: u64) -> f32 {
+        match self {
+            LearningRateSchedule::StepDecay(schedule) => {
+                let num_decays = step / schedule.step_size.max(1);
+                base_lr * schedule.gamma.powi(num_decays.min(i32::MAX as u64) as i32)
+            }
+            LearningRateSchedule::Cosine(schedule) => {
+                let progress =
+                    step.min(schedule.total_steps) as f32 / schedule.total_steps.max(1) as f32;
+                schedule.min_lr
+                    + (base_lr - schedule.min_lr)
+                        * 0.5
+                        * (1.0 + (std::f32::consts::PI * progress).cos())
+            }
+            LearningRateSchedule::LinearWarmup(schedule) => {
+                if step >= schedule.warmup_steps {
+                    base_lr
+                } else {
+                    base_lr * step as f32 / schedule.warmup_steps as f32
+                }
+            }
+            LearningRateSchedule::InverseSqrt(schedule) => {
+                if step < schedule.warmup_steps {
+                    base_lr * step as f32 / schedule.warmup_steps as f32
+                } else {
+                    base_lr * (schedule.warmup_steps.max(1) as f32 / step.max(1) as f32).sqrt()
+                }
+            }
+        }
+    }
+}
+
+/// Changes the learning rate of the embedding optimizers during training.
+#[derive(Readable, Writable, Debug, Clone)]
+pub enum LearningRateUpdate {
+    // sets the learning rate of all optimizers and drops the schedule, for trainers driving
+    // the learning rate on their own
+    LearningRate(f32),
+    // schedules the learning rates from the global steps, starting at the current one
+    Schedule(LearningRateSchedule),
+    // moves the schedule to the given global step, which then keeps advancing by one per
+    // gradient update received
+    GlobalStep(u64),
+}
+
+#[derive(Clone)]
+struct RegisteredOptimizer {
+    // learning rate the optimizer is registered with, which the schedule scales
+    base_lr: f32,
+    optimizer: Arc<RwLock<Box<dyn Optimizable + Send + Sync>>>,
+}
+
+/// The optimizers of the feature groups, keyed by feature group index prefix. Feature groups
+/// without their own optimizer are updated by the default one.
+#[derive(Clone)]
+pub struct FeatureGroupOptimizers {
+    prefix_mask: u64,
+    default: Option<RegisteredOptimizer>,
+    groups: HashMap<u64, RegisteredOptimizer>,
+    schedule: Option<LearningRateSchedule>,
+    // shared with the clones replacing these optimizers on updates, so that the steps advanced
+    // on a replaced one are not lost
+    global_step: Arc<AtomicU64>,
+    // restored states of the optimizers not registered yet, keyed like the optimizers
+    pending_states: HashMap<Option<u64>, OptimizerGlobalState>,
+}
+
+impl FeatureGroupOptimizers {
+    pub fn new(prefix_mask: u64) -> Self {
+        Self {
+            prefix_mask,
+            default: None,
+            groups: HashMap::new(),
+            schedule: None,
+            global_step: Arc::new(AtomicU64::new(0)),
+            pending_states: HashMap::new(),
+        }
+    }
+
+    /// Registers the optimizer of a feature group, or the default one if `index_prefix` is None.
+    /// The learning rate of the optimizer follows the schedule if there is one.
+    pub fn register(&mut self, index_prefix: Option<u64>, config: OptimizerConfig) {
+        let base_lr = config.lr();
+        self.register_optimizable(
+            index_prefix,
+            base_lr,
+            Optimizer::new(config).to_optimizable(),
+        );
+    }
+
+    fn register_optimizable(
+        &mut self,
+        index_prefix: Option<u64>,
+        base_lr: f32,
+        mut optimizer: Box<dyn Optimizable + Send + Sync>,
+    ) {
+        if let Some(schedule) = self.schedule.as_ref() {
+            optimizer.update_lr(schedule.lr(base_lr, self.global_step()));
+        }
+        if let Some(state) = self.pending_states.remove(&index_prefix) {
+            optimizer.load_global_state(&state);
+        }
+        let registered = RegisteredOptimizer {
+            base_lr,
+            optimizer: Arc::new(RwLock::new(optimizer)),
+        };
+        match index_prefix {
+            Some(index_prefix) => {
+                self.groups.insert(index_prefix, registered);
+            }
+            None => self.default = Some(registered),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.groups.is_empty()
+    }
+
+    fn registered(&self) -> impl Iterator<Item = &RegisteredOptimizer> {
+        self.default.iter().chain(self.groups.values())
+    }
+
+    pub fn update_learning_rate(&mut self, update: LearningRateUpdate) {
+        match update {
+            LearningRateUpdate::LearningRate(lr) => {
+                self.schedule = None;
+                self.registered()
+                    .for_each(|registered| registered.optimizer.write().update_lr(lr));
+            }
+            LearningRateUpdate::Schedule(schedule) => {
+                self.schedule = Some(schedule);
+                self.apply_schedule();
+            }
+            LearningRateUpdate::GlobalStep(global_step) => {
+                self.global_step.store(global_step, Ordering::Release);
+                self.apply_schedule();
+            }
+        }
+    }
+
+    fn global_step(&self) -> u64 {
+        self.global_step.load(Ordering::Acquire)
+    }
+
+    /// Advances the global step of the schedule by one, called once per gradient update
+    /// received.
+    pub fn advance_step(&self) {
+        self.global_step.fetch_add(1, Ordering::AcqRel);
+        self.apply_schedule();
+    }
+
+    pub fn get_state(&self) -> FeatureGroupOptimizersState {
+        let global_state =
+            |registered: &RegisteredOptimizer| registered.optimizer.read().get_global_state();
+        FeatureGroupOptimizersState {
+            global_step: self.global_step(),
+            schedule: self.schedule.clone(),
+            default: self.default.as_ref().and_then(global_state),
+            groups: self
+                .groups
+                .iter()
+                .filter_map(|(prefix, registered)| {
+                    global_state(registered).map(|state| (*prefix, state))
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the state dumped by [`get_state`](Self::get_state). Optimizers registered later
+    /// take over their states on registration.
+    pub fn load_state(&mut self, state: FeatureGroupOptimizersState) {
+        self.global_step.store(state.global_step, Ordering::Release);
+        self.schedule = state.schedule;
+        let states = state.default.map(|state| (None, state)).into_iter().chain(
+            state
+                .groups
+                .into_iter()
+                .map(|(prefix, state)| (Some(prefix), state)),
+        );
+        for (key, state) in states {
+            let registered = match key {
+                Some(index_prefix) => self.groups.get(&index_prefix),
+                None => self.default.as_ref(),
+            };
+            match registered {
+                Some(registered) => registered.optimizer.read().load_global_state(&state),
+                None => {
+                    self.pending_states.insert(key, state);
+                }
+            }
+        }
+        self.apply_schedule();
+    }
+
+    fn apply_schedule(&self) {
+        if let Some(schedule) = self.schedule.as_ref() {
+            self.registered().for_each(|registered| {
+                let lr = schedule.lr(registered.base_lr, self.global_step());
+                registered.optimizer.write().update_lr(lr);
+            });
         }
     }
+
+    // index prefix of the feature group of `sign` if it has its own optimizer
+    fn group_key(&self, sign: u64) -> Option<u64> {
+        let index_prefix = sign & self.prefix_mask;
+        self.groups.get(&index_prefix).map(|_| index_prefix)
+    }
+
+    fn get_by_key(
+        &self,
+        key: Option<u64>,
+    ) -> Option<RwLockReadGuard<'_, Box<dyn Optimizable + Send + Sync>>> {
+        let registered = match key {
+            Some(index_prefix) => self.groups.get(&index_prefix),
+            None => self.default.as_ref(),
+        };
+        registered.map(|registered| registered.optimizer.read())
+    }
+
+    pub fn get(
+        &self,
+        sign: u64,
+    ) -> Option<RwLockReadGuard<'_, Box<dyn Optimizable + Send + Sync>>> {
+        self.get_by_key(self.group_key(sign))
+    }
+
+    /// Optimizer states of each of `signs`, the batch level state of an optimizer only covers
+    /// the signs it updates. Signs without an optimizer get None.
+    pub fn get_emb_states(&self, signs: &[u64]) -> Vec<Option<Vec<f32>>> {
+        let mut signs_by_optimizer: HashMap<Option<u64>, Vec<u64>> = HashMap::new();
+        let positions: Vec<(Option<u64>, usize)> = signs
+            .iter()
+            .map(|sign| {
+                let key = self.group_key(*sign);
+                let optimizer_signs = signs_by_optimizer.entry(key).or_default();
+                optimizer_signs.push(*sign);
+                (key, optimizer_signs.len() - 1)
+            })
+            .collect();
+
+        let batch_level_states: HashMap<Option<u64>, Option<Vec<f32>>> = signs_by_optimizer
+            .iter()
+            .filter_map(|(key, optimizer_signs)| {
+                self.get_by_key(*key)
+                    .map(|optimizer| (*key, optimizer.get_batch_level_state(optimizer_signs)))
+            })
+            .collect();
+
+        positions
+            .into_iter()
+            .map(|(key, position)| {
+                let optimizer = self.get_by_key(key)?;
+                optimizer.get_emb_state(batch_level_states.get(&key)?, position)
+            })
+            .collect()
+    }
 }
 
 pub trait Optimizable {
@@ -89,6 +469,30 @@ pub trait Optimizable {
     fn update_lr(&mut self, _lr: f32) {}
 
     fn state_initialization(&self, _state: &mut [f32], _dim: usize) {}
+
+    /// State shared by all embeddings that has to be restored along with them, e.g. the step
+    /// counters of the feature groups.
+    fn get_global_state(&self) -> Option<OptimizerGlobalState> {
+        None
+    }
+
+    fn load_global_state(&self, _state: &OptimizerGlobalState) {}
+}
+
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub struct OptimizerGlobalState {
+    // (index prefix, beta1 power, beta2 power) of each feature group
+    pub beta_powers: Vec<(u64, f32, f32)>,
+}
+
+/// Global state of the optimizers and the learning rate schedule, dumped with the embeddings.
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub struct FeatureGroupOptimizersState {
+    pub global_step: u64,
+    pub schedule: Option<LearningRateSchedule>,
+    pub default: Option<OptimizerGlobalState>,
+    // (index prefix, state) of the optimizers of feature groups
+    pub groups: Vec<(u64, OptimizerGlobalState)>,
 }
 
 struct AdamPowerOfBetas {
@@ -96,85 +500,81 @@ struct AdamPowerOfBetas {
     beta2: f32,
 }
 
-pub struct Adam {
-    config: AdamConfig,
-    embedding_config: Arc<EmbeddingConfig>,
-    accum_betas: HashMap<u64, RwLock<AdamPowerOfBetas>>,
+/// Powers of beta1 and beta2 of each feature group for the bias correction of Adam like
+/// optimizers, stepped once per batch containing the feature group.
+struct BetaPowers {
+    beta1: f32,
+    beta2: f32,
+    prefix_mask: u64,
+    accum_betas: RwLock<HashMap<u64, RwLock<AdamPowerOfBetas>>>,
 }
 
-impl Adam {
-    pub fn new(config: AdamConfig) -> Self {
+impl BetaPowers {
+    fn new(beta1: f32, beta2: f32) -> Self {
         let embedding_config = EmbeddingConfig::get().expect("embedding config not found");
-        let mut accum_betas = HashMap::with_capacity(embedding_config.feature_groups.len());
+        let index_prefixes =
+            embedding_config
+                .feature_groups
+                .iter()
+                .map(|(_group_name, slots_name)| {
+                    embedding_config
+                        .slots_config
+                        .get(slots_name.first().expect("slot not found"))
+                        .expect("slot not found")
+                        .index_prefix
+                });
 
-        embedding_config
-            .feature_groups
-            .iter()
-            .for_each(|(_group_name, slots_name)| {
-                let prefix = embedding_config
-                    .slots_config
-                    .get(slots_name.first().expect("slot not found"))
-                    .expect("slot not found")
-                    .index_prefix;
-
-                let initially_betas = AdamPowerOfBetas {
-                    beta1: config.beta1.clone(),
-                    beta2: config.beta2.clone(),
-                };
-                accum_betas.insert(prefix.clone(), RwLock::new(initially_betas));
-            });
+        Self::with_index_prefixes(
+            beta1,
+            beta2,
+            embedding_config.feature_index_prefix_mask(),
+            index_prefixes,
+        )
+    }
+
+    fn with_index_prefixes(
+        beta1: f32,
+        beta2: f32,
+        prefix_mask: u64,
+        index_prefixes: impl Iterator<Item = u64>,
+    ) -> Self {
+        let accum_betas = index_prefixes
+            .map(|prefix| {
+                let initially_betas = AdamPowerOfBetas { beta1, beta2 };
+                (prefix, RwLock::new(initially_betas))
+            })
+            .collect();
 
         Self {
-            config,
-            embedding_config,
-            accum_betas,
+            beta1,
+            beta2,
+            prefix_mask,
+            accum_betas: RwLock::new(accum_betas),
         }
     }
-}
-
-impl Optimizable for Adam {
-    #[inline]
-    fn require_space(&self, dim: usize) -> usize {
-        dim * 2
-    }
 
     #[inline]
-    fn get_emb_state(&self, opt_state: &Option<Vec<f32>>, idx: usize) -> Option<Vec<f32>> {
-        if let Some(opt_state) = opt_state {
-            Some(vec![opt_state[idx], opt_state[opt_state.len() / 2 + idx]])
-        } else {
-            None
-        }
+    fn get_emb_state(opt_state: &Option<Vec<f32>>, idx: usize) -> Option<Vec<f32>> {
+        opt_state
+            .as_ref()
+            .map(|opt_state| vec![opt_state[idx], opt_state[opt_state.len() / 2 + idx]])
     }
 
-    #[inline]
     fn get_batch_level_state(&self, signs: &[u64]) -> Option<Vec<f32>> {
         let mut betas_power = vec![0.0_f32; signs.len() * 2];
         let (beta1_power, beta2_power) = betas_power.as_mut_slice().split_at_mut(signs.len());
 
-        let mask =
-            !((1u64 << (u64::BITS - self.embedding_config.feature_index_prefix_bit as u32)) - 1);
         let mut steped: HashMap<u64, AdamPowerOfBetas> = HashMap::with_capacity(signs.len());
 
         signs.iter().enumerate().for_each(|(idx, sign)| {
-            let masked_sign: u64 = sign & mask;
+            let masked_sign: u64 = sign & self.prefix_mask;
             if let Some(betas) = steped.get(&masked_sign) {
                 beta1_power[idx] = betas.beta1;
                 beta2_power[idx] = betas.beta2;
             } else {
-                {
-                    let mut accum_betas = self
-                        .accum_betas
-                        .get(&masked_sign)
-                        .expect("feature group not found")
-                        .write();
-
-                    accum_betas.beta1 = accum_betas.beta1 * self.config.beta1;
-                    accum_betas.beta2 = accum_betas.beta2 * self.config.beta2;
-
-                    beta1_power[idx] = accum_betas.beta1;
-                    beta2_power[idx] = accum_betas.beta2;
-                }
+                let (beta1, beta2) = self.step(masked_sign);
+                beta1_power[idx] = beta1;
+                beta2_power[idx] = beta2;
 
                 steped.insert(
                     masked_sign,
@@ -189,6 +589,99 @@ impl Optimizable for Adam {
         Some(betas_power)
     }
 
+    // steps the powers of betas of a feature group, a feature group added after the optimizer
+    // is created starts from the initial powers of betas
+    fn step(&self, index_prefix: u64) -> (f32, f32) {
+        let step_betas = |betas: &RwLock<AdamPowerOfBetas>| {
+            let mut betas = betas.write();
+            betas.beta1 *= self.beta1;
+            betas.beta2 *= self.beta2;
+            (betas.beta1, betas.beta2)
+        };
+
+        if let Some(betas) = self.accum_betas.read().get(&index_prefix) {
+            return step_betas(betas);
+        }
+        let mut accum_betas = self.accum_betas.write();
+        let betas = accum_betas.entry(index_prefix).or_insert_with(|| {
+            RwLock::new(AdamPowerOfBetas {
+                beta1: self.beta1,
+                beta2: self.beta2,
+            })
+        });
+        step_betas(betas)
+    }
+
+    fn get_global_state(&self) -> OptimizerGlobalState {
+        let mut beta_powers: Vec<(u64, f32, f32)> = self
+            .accum_betas
+            .read()
+            .iter()
+            .map(|(prefix, betas)| {
+                let betas = betas.read();
+                (*prefix, betas.beta1, betas.beta2)
+            })
+            .collect();
+        beta_powers.sort_by_key(|(prefix, _, _)| *prefix);
+        OptimizerGlobalState { beta_powers }
+    }
+
+    fn load_global_state(&self, state: &OptimizerGlobalState) {
+        let mut accum_betas = self.accum_betas.write();
+        state
+            .beta_powers
+            .iter()
+            .for_each(|(prefix, beta1_power, beta2_power)| {
+                accum_betas.insert(
+                    *prefix,
+                    RwLock::new(AdamPowerOfBetas {
+                        beta1: *beta1_power,
+                        beta2: *beta2_power,
+                    }),
+                );
+            });
+    }
+}
+
+pub struct Adam {
+    config: AdamConfig,
+    beta_powers: BetaPowers,
+}
+
+impl Adam {
+    pub fn new(config: AdamConfig) -> Self {
+        let beta_powers = BetaPowers::new(config.beta1, config.beta2);
+        Self {
+            config,
+            beta_powers,
+        }
+    }
+}
+
+impl Optimizable for Adam {
+    #[inline]
+    fn require_space(&self, dim: usize) -> usize {
+        dim * 2
+    }
+
+    #[inline]
+    fn get_emb_state(&self, opt_state: &Option<Vec<f32>>, idx: usize) -> Option<Vec<f32>> {
+        BetaPowers::get_emb_state(opt_state, idx)
+    }
+
+    #[inline]
+    fn get_batch_level_state(&self, signs: &[u64]) -> Option<Vec<f32>> {
+        self.beta_powers.get_batch_level_state(signs)
+    }
+
+    fn get_global_state(&self) -> Option<OptimizerGlobalState> {
+        Some(self.beta_powers.get_global_state())
+    }
+
+    fn load_global_state(&self, state: &OptimizerGlobalState) {
+        self.beta_powers.load_global_state(state)
+    }
+
     #[inline]
     fn update(
         &self,
@@ -203,20 +696,23 @@ impl Optimizable for Adam {
         let (emb, opt) = emb_entry.split_at_mut(dim);
         let (adam_m, adam_v) = opt.split_at_mut(dim);
 
-        unsafe {
-            adam_avx2(
-                adam_m,
-                adam_v,
-                beta1_power,
-                beta2_power,
-                emb,
-                grad,
-                self.config.lr,
-                self.config.beta1,
-                self.config.beta2,
-                self.config.eps,
-            )
-        }
+        adam(
+            adam_m,
+            adam_v,
+            beta1_power,
+            beta2_power,
+            emb,
+            grad,
+            self.config.lr,
+            self.config.beta1,
+            self.config.beta2,
+            self.config.eps,
+            self.config.weight_decay,
+        )
+    }
+
+    fn update_lr(&mut self, lr: f32) {
+        self.config.lr = lr;
     }
 }
 
@@ -233,9 +729,7 @@ impl Optimizable for NaiveSGD {
         _dim: usize,
         _batch_level_status: &Option<Vec<f32>>,
     ) {
-        unsafe {
-            decayed_sgd_avx2(emb_entry, grad, self.config.wd, self.config.lr);
-        }
+        decayed_sgd(emb_entry, grad, self.config.wd, self.config.lr);
     }
 
     fn update_lr(&mut self, lr: f32) {
@@ -267,31 +761,26 @@ impl Optimizable for Adagrad {
         let (emb, adagrad_state) = emb_entry.split_at_mut(dim);
         if self.config.vectorwise_shared {
             let adagrad_state = adagrad_state.first_mut().expect("adagrad state is empty");
-            unsafe {
-                decayed_adagrad_vectorwise_shared_avx2(
-                    *adagrad_state,
-                    emb,
-                    grad,
-                    self.config.lr,
-                    self.config.eps,
-                )
-            }
-
-            let gradients = ndarray::ArrayView1::<f32>::from(grad);
+            let gradient_squares = decayed_adagrad_vectorwise_shared(
+                *adagrad_state,
+                emb,
+                grad,
+                self.config.lr,
+                self.config.eps,
+                self.config.wd,
+            ) / grad.len() as f32;
 
-            let gradient_squares = gradients.dot(&gradients) / gradients.len() as f32;
             *adagrad_state = *adagrad_state * self.config.g_square_momentum + gradient_squares;
         } else {
-            unsafe {
-                decayed_adagrad_avx2(
-                    adagrad_state,
-                    emb,
-                    grad,
-                    self.config.g_square_momentum,
-                    self.config.lr,
-                    self.config.eps,
-                )
-            }
+            decayed_adagrad(
+                adagrad_state,
+                emb,
+                grad,
+                self.config.g_square_momentum,
+                self.config.lr,
+                self.config.eps,
+                self.config.wd,
+            )
         }
     }
 
@@ -306,42 +795,316 @@ impl Optimizable for Adagrad {
     }
 }
 
-#[cfg(test)]
-mod embedding_optimizer_tests {
-    // importing names from outer (for mod tests) scope.
-    use super::*;
-
-    fn get_grads() -> Vec<Vec<f32>> {
-        vec![
-            vec![
-                0.6039, 0.2480, 0.8303, 0.8006, 0.6830, 0.4730, 0.0381, 0.8375, 0.5836, 0.8673,
-                0.2224, 0.4040,
-            ],
-            vec![
-                0.4478, 0.9670, 0.5724, 0.3074, 0.5760, 0.2937, 0.0995, 0.6640, 0.7718, 0.3016,
-                0.0246, 0.6975,
-            ],
-            vec![
-                0.2304, 0.9627, 0.3126, 0.8667, 0.6767, 0.6441, 0.0131, 0.1702, 0.8901, 0.4696,
-                0.2655, 0.0545,
-            ],
-        ]
-    }
-
-    fn get_init_embedding() -> Vec<f32> {
-        vec![
-            0.7306, 0.0340, 0.1331, 0.4355, 0.0305, 0.6968, 0.1528, 0.7074, 0.5598, 0.0271, 0.7671,
-            0.8731,
-        ]
-    }
+/// FTRL-Proximal, keeps the linear accumulator z and the squared gradient accumulator n of
+/// each coordinate after the embedding.
+pub struct FTRL {
+    config: FTRLConfig,
+}
 
-    fn get_embedding_dim() -> usize {
-        12
+impl Optimizable for FTRL {
+    #[inline]
+    fn require_space(&self, dim: usize) -> usize {
+        dim * 2
     }
 
-    fn execute_test(optimizer: Box<dyn Optimizable>) -> Vec<f32> {
-        let embedding_dim = get_embedding_dim();
-        let mut embedding_entry = get_init_embedding();
+    #[inline]
+    fn update(
+        &self,
+        emb_entry: &mut [f32],
+        grad: &[f32],
+        dim: usize,
+        _batch_level_status: &Option<Vec<f32>>,
+    ) {
+        let (emb, opt) = emb_entry.split_at_mut(dim);
+        let (ftrl_z, ftrl_n) = opt.split_at_mut(dim);
+
+        ftrl(
+            ftrl_z,
+            ftrl_n,
+            emb,
+            grad,
+            self.config.lr,
+            self.config.beta,
+            self.config.lambda1,
+            self.config.lambda2,
+        )
+    }
+
+    #[inline]
+    fn state_initialization(&self, state: &mut [f32], dim: usize) {
+        let (ftrl_z, ftrl_n) = state[dim..].split_at_mut(dim);
+        ftrl_z.iter_mut().for_each(|x| *x = 0.0_f32);
+        ftrl_n
+            .iter_mut()
+            .for_each(|x| *x = self.config.initialization);
+    }
+
+    fn update_lr(&mut self, lr: f32) {
+        self.config.lr = lr;
+    }
+}
+
+pub struct AdamW {
+    config: AdamWConfig,
+    beta_powers: BetaPowers,
+}
+
+impl AdamW {
+    pub fn new(config: AdamWConfig) -> Self {
+        let beta_powers = BetaPowers::new(config.beta1, config.beta2);
+        Self {
+            config,
+            beta_powers,
+        }
+    }
+}
+
+impl Optimizable for AdamW {
+    #[inline]
+    fn require_space(&self, dim: usize) -> usize {
+        dim * 2
+    }
+
+    #[inline]
+    fn get_emb_state(&self, opt_state: &Option<Vec<f32>>, idx: usize) -> Option<Vec<f32>> {
+        BetaPowers::get_emb_state(opt_state, idx)
+    }
+
+    #[inline]
+    fn get_batch_level_state(&self, signs: &[u64]) -> Option<Vec<f32>> {
+        self.beta_powers.get_batch_level_state(signs)
+    }
+
+    fn get_global_state(&self) -> Option<OptimizerGlobalState> {
+        Some(self.beta_powers.get_global_state())
+    }
+
+    fn load_global_state(&self, state: &OptimizerGlobalState) {
+        self.beta_powers.load_global_state(state)
+    }
+
+    #[inline]
+    fn update(
+        &self,
+        emb_entry: &mut [f32],
+        grad: &[f32],
+        dim: usize,
+        emb_opt_state: &Option<Vec<f32>>,
+    ) {
+        let emb_opt_state = emb_opt_state.as_deref().unwrap();
+        let beta1_power = emb_opt_state[0];
+        let beta2_power = emb_opt_state[1];
+        let (emb, opt) = emb_entry.split_at_mut(dim);
+        let (adam_m, adam_v) = opt.split_at_mut(dim);
+
+        adamw(
+            adam_m,
+            adam_v,
+            beta1_power,
+            beta2_power,
+            emb,
+            grad,
+            self.config.lr,
+            self.config.beta1,
+            self.config.beta2,
+            self.config.eps,
+            self.config.weight_decay,
+        )
+    }
+
+    fn update_lr(&mut self, lr: f32) {
+        self.config.lr = lr;
+    }
+}
+
+pub struct RMSProp {
+    config: RMSPropConfig,
+}
+
+impl Optimizable for RMSProp {
+    #[inline]
+    fn require_space(&self, dim: usize) -> usize {
+        dim
+    }
+
+    #[inline]
+    fn update(
+        &self,
+        emb_entry: &mut [f32],
+        grad: &[f32],
+        dim: usize,
+        _batch_level_status: &Option<Vec<f32>>,
+    ) {
+        let (emb, rms) = emb_entry.split_at_mut(dim);
+
+        rmsprop(
+            rms,
+            emb,
+            grad,
+            self.config.lr,
+            self.config.alpha,
+            self.config.eps,
+        )
+    }
+
+    #[inline]
+    fn state_initialization(&self, state: &mut [f32], dim: usize) {
+        state[dim..]
+            .iter_mut()
+            .for_each(|x| *x = self.config.initialization);
+    }
+
+    fn update_lr(&mut self, lr: f32) {
+        self.config.lr = lr;
+    }
+}
+
+pub struct MomentumSGD {
+    config: MomentumSGDConfig,
+}
+
+impl Optimizable for MomentumSGD {
+    #[inline]
+    fn require_space(&self, dim: usize) -> usize {
+        dim
+    }
+
+    #[inline]
+    fn update(
+        &self,
+        emb_entry: &mut [f32],
+        grad: &[f32],
+        dim: usize,
+        _batch_level_status: &Option<Vec<f32>>,
+    ) {
+        let (emb, momentum_buffer) = emb_entry.split_at_mut(dim);
+
+        momentum_sgd(
+            momentum_buffer,
+            emb,
+            grad,
+            self.config.lr,
+            self.config.wd,
+            self.config.momentum,
+            self.config.nesterov,
+        )
+    }
+
+    fn update_lr(&mut self, lr: f32) {
+        self.config.lr = lr;
+    }
+}
+
+/// Layer-wise adaptive moments, every embedding is a layer of its own.
+pub struct LAMB {
+    config: LAMBConfig,
+    beta_powers: BetaPowers,
+}
+
+impl LAMB {
+    pub fn new(config: LAMBConfig) -> Self {
+        let beta_powers = BetaPowers::new(config.beta1, config.beta2);
+        Self {
+            config,
+            beta_powers,
+        }
+    }
+}
+
+impl Optimizable for LAMB {
+    #[inline]
+    fn require_space(&self, dim: usize) -> usize {
+        dim * 2
+    }
+
+    #[inline]
+    fn get_emb_state(&self, opt_state: &Option<Vec<f32>>, idx: usize) -> Option<Vec<f32>> {
+        BetaPowers::get_emb_state(opt_state, idx)
+    }
+
+    #[inline]
+    fn get_batch_level_state(&self, signs: &[u64]) -> Option<Vec<f32>> {
+        self.beta_powers.get_batch_level_state(signs)
+    }
+
+    fn get_global_state(&self) -> Option<OptimizerGlobalState> {
+        Some(self.beta_powers.get_global_state())
+    }
+
+    fn load_global_state(&self, state: &OptimizerGlobalState) {
+        self.beta_powers.load_global_state(state)
+    }
+
+    #[inline]
+    fn update(
+        &self,
+        emb_entry: &mut [f32],
+        grad: &[f32],
+        dim: usize,
+        emb_opt_state: &Option<Vec<f32>>,
+    ) {
+        let emb_opt_state = emb_opt_state.as_deref().unwrap();
+        let beta1_power = emb_opt_state[0];
+        let beta2_power = emb_opt_state[1];
+        let (emb, opt) = emb_entry.split_at_mut(dim);
+        let (adam_m, adam_v) = opt.split_at_mut(dim);
+
+        lamb(
+            adam_m,
+            adam_v,
+            beta1_power,
+            beta2_power,
+            emb,
+            grad,
+            self.config.lr,
+            self.config.beta1,
+            self.config.beta2,
+            self.config.eps,
+            self.config.weight_decay,
+        )
+    }
+
+    fn update_lr(&mut self, lr: f32) {
+        self.config.lr = lr;
+    }
+}
+
+#[cfg(test)]
+mod embedding_optimizer_tests {
+    // importing names from outer (for mod tests) scope.
+    use super::*;
+
+    fn get_grads() -> Vec<Vec<f32>> {
+        vec![
+            vec![
+                0.6039, 0.2480, 0.8303, 0.8006, 0.6830, 0.4730, 0.0381, 0.8375, 0.5836, 0.8673,
+                0.2224, 0.4040,
+            ],
+            vec![
+                0.4478, 0.9670, 0.5724, 0.3074, 0.5760, 0.2937, 0.0995, 0.6640, 0.7718, 0.3016,
+                0.0246, 0.6975,
+            ],
+            vec![
+                0.2304, 0.9627, 0.3126, 0.8667, 0.6767, 0.6441, 0.0131, 0.1702, 0.8901, 0.4696,
+                0.2655, 0.0545,
+            ],
+        ]
+    }
+
+    fn get_init_embedding() -> Vec<f32> {
+        vec![
+            0.7306, 0.0340, 0.1331, 0.4355, 0.0305, 0.6968, 0.1528, 0.7074, 0.5598, 0.0271, 0.7671,
+            0.8731,
+        ]
+    }
+
+    fn get_embedding_dim() -> usize {
+        12
+    }
+
+    fn execute_test(optimizer: Box<dyn Optimizable>) -> Vec<f32> {
+        let embedding_dim = get_embedding_dim();
+        let mut embedding_entry = get_init_embedding();
 
         embedding_entry.resize(
             embedding_dim + optimizer.require_space(embedding_dim),
@@ -359,6 +1122,39 @@ mod embedding_optimizer_tests {
         embedding_entry
     }
 
+    // runs the updates with the beta powers of the steps as optimizer state
+    fn execute_test_with_beta_powers(
+        optimizer: Box<dyn Optimizable>,
+        beta1: f32,
+        beta2: f32,
+    ) -> Vec<f32> {
+        let embedding_dim = get_embedding_dim();
+        let mut embedding_entry = get_init_embedding();
+
+        embedding_entry.resize(
+            embedding_dim + optimizer.require_space(embedding_dim),
+            0.0_f32,
+        );
+
+        optimizer.state_initialization(&mut embedding_entry, embedding_dim);
+
+        let grads = get_grads();
+        let beta_powers = BetaPowers::with_index_prefixes(beta1, beta2, 0, std::iter::once(0));
+
+        grads.iter().for_each(|g| {
+            let batch_level_state = beta_powers.get_batch_level_state(&[0]);
+            let emb_opt_state = BetaPowers::get_emb_state(&batch_level_state, 0);
+            optimizer.update(
+                &mut embedding_entry,
+                g.as_slice(),
+                embedding_dim,
+                &emb_opt_state,
+            );
+        });
+
+        embedding_entry
+    }
+
     #[test]
     fn test_adagrad() {
         let optimizer = Adagrad {
@@ -443,4 +1239,745 @@ mod embedding_optimizer_tests {
             .zip(adagrad_result.iter())
             .for_each(|(x, y)| assert_eq!(x, y));
     }
+
+    #[test]
+    fn test_adagrad_weight_decay() {
+        let optimizer = Adagrad {
+            config: AdagradConfig {
+                lr: 0.01_f32,
+                wd: 0.1_f32,
+                g_square_momentum: 1.0_f32,
+                initialization: 0.01_f32,
+                eps: 1e-10_f32,
+                vectorwise_shared: false,
+            },
+        };
+
+        let embedding_entry = execute_test(Box::new(optimizer));
+
+        let adagrad_result: Vec<f32> = vec![
+            0.65193504,
+            -0.036462005,
+            0.038859043,
+            0.33722043,
+            -0.053835202,
+            0.6252868,
+            0.13571742,
+            0.60665375,
+            0.4741806,
+            -0.068288766,
+            0.72381264,
+            0.8069544,
+            0.8201438,
+            1.9316224,
+            1.1552814,
+            1.6524332,
+            1.2597357,
+            0.9334778,
+            0.026621923,
+            1.4148691,
+            1.974358,
+            1.0689238,
+            0.22399147,
+            0.87794197,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(adagrad_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_adagrad_vectorwise_shared_weight_decay() {
+        let optimizer = Adagrad {
+            config: AdagradConfig {
+                lr: 0.01_f32,
+                wd: 0.1_f32,
+                g_square_momentum: 1.0_f32,
+                initialization: 0.01_f32,
+                eps: 1e-10_f32,
+                vectorwise_shared: true,
+            },
+        };
+
+        let embedding_entry = execute_test(Box::new(optimizer));
+
+        let adagrad_result: Vec<f32> = vec![
+            0.65167725,
+            -0.016906312,
+            0.036311634,
+            0.33555645,
+            -0.05451751,
+            0.6289794,
+            0.14540243,
+            0.60286576,
+            0.4725826,
+            -0.06970573,
+            0.73181283,
+            0.8105191,
+            1.1119324,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(adagrad_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_adam() {
+        let optimizer = Adam {
+            config: AdamConfig {
+                lr: 0.01_f32,
+                beta1: 0.9_f32,
+                beta2: 0.999_f32,
+                eps: 1e-8_f32,
+                weight_decay: 0.0_f32,
+            },
+            beta_powers: BetaPowers::with_index_prefixes(0.9, 0.999, 0, std::iter::empty()),
+        };
+
+        let embedding_entry = execute_test_with_beta_powers(Box::new(optimizer), 0.9, 0.999);
+
+        let adam_result: Vec<f32> = vec![
+            0.70643026,
+            0.010417566,
+            0.10903255,
+            0.41185406,
+            0.005473283,
+            0.6722826,
+            0.13010488,
+            0.68371075,
+            0.53469676,
+            0.003986869,
+            0.74540627,
+            0.8499823,
+            0.11225793,
+            0.20338804,
+            0.15003033,
+            0.17918463,
+            0.17483303,
+            0.12915604,
+            0.013351103,
+            0.14461753,
+            0.20574364,
+            0.14435533,
+            0.04677841,
+            0.10094902,
+            0.0006173667,
+            0.0019223015,
+            0.0011130385,
+            0.0014852291,
+            0.0012549075,
+            0.0007243107,
+            1.15105195e-5,
+            0.001169412,
+            0.0017272434,
+            0.0010620877,
+            0.00012045614,
+            0.0006518713,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(adam_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_adam_weight_decay() {
+        let optimizer = Adam {
+            config: AdamConfig {
+                lr: 0.01_f32,
+                beta1: 0.9_f32,
+                beta2: 0.999_f32,
+                eps: 1e-8_f32,
+                weight_decay: 0.1_f32,
+            },
+            beta_powers: BetaPowers::with_index_prefixes(0.9, 0.999, 0, std::iter::empty()),
+        };
+
+        let embedding_entry = execute_test_with_beta_powers(Box::new(optimizer), 0.9, 0.999);
+
+        let adam_result: Vec<f32> = vec![
+            0.7062382,
+            0.010400845,
+            0.10900394,
+            0.41169125,
+            0.0054741534,
+            0.672131,
+            0.12933531,
+            0.6834893,
+            0.5346808,
+            0.003980227,
+            0.7437384,
+            0.84946287,
+            0.13183126,
+            0.20409179,
+            0.15341228,
+            0.19076768,
+            0.17543304,
+            0.1478146,
+            0.01726836,
+            0.16356179,
+            0.22068712,
+            0.144873,
+            0.06735055,
+            0.12438398,
+            0.00081872405,
+            0.001932783,
+            0.0011572725,
+            0.0016594545,
+            0.001263704,
+            0.0009325068,
+            1.6553653e-5,
+            0.0014187308,
+            0.0019835636,
+            0.0010691148,
+            0.00021547664,
+            0.0008746976,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(adam_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_ftrl() {
+        let optimizer = FTRL {
+            config: FTRLConfig {
+                lr: 0.05_f32,
+                beta: 1.0_f32,
+                lambda1: 0.5_f32,
+                lambda2: 0.1_f32,
+                initialization: 0.1_f32,
+            },
+        };
+
+        let embedding_entry = execute_test(Box::new(optimizer));
+
+        let ftrl_result: Vec<f32> = vec![
+            0.10706014,
+            -0.035830874,
+            0.0,
+            0.06510928,
+            -0.02821283,
+            0.055222847,
+            0.0,
+            0.16069373,
+            0.0461619,
+            -0.019893486,
+            0.0029519137,
+            0.06332795,
+            -4.4666386,
+            2.2395582,
+            0.17479226,
+            -3.448946,
+            1.7241855,
+            -2.6130629,
+            0.1437111,
+            -7.3536077,
+            -2.6762898,
+            1.3290598,
+            -0.5870599,
+            -2.8717313,
+            0.7183042,
+            2.0233843,
+            1.2147585,
+            1.586624,
+            1.3561878,
+            0.82485354,
+            0.11152347,
+            1.2712703,
+            1.8285422,
+            1.163696,
+            0.22055718,
+            0.7526925,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(ftrl_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_adamw() {
+        let optimizer = AdamW {
+            config: AdamWConfig {
+                lr: 0.01_f32,
+                beta1: 0.9_f32,
+                beta2: 0.999_f32,
+                eps: 1e-8_f32,
+                weight_decay: 0.1_f32,
+            },
+            beta_powers: BetaPowers::with_index_prefixes(0.9, 0.999, 0, std::iter::empty()),
+        };
+
+        let embedding_entry = execute_test_with_beta_powers(Box::new(optimizer), 0.9, 0.999);
+
+        let adamw_result: Vec<f32> = vec![
+            0.704264,
+            0.010338159,
+            0.10865688,
+            0.41057143,
+            0.0054052672,
+            0.67021745,
+            0.12966985,
+            0.681614,
+            0.53304243,
+            0.003928056,
+            0.74312854,
+            0.8473889,
+            0.11225793,
+            0.20338804,
+            0.15003033,
+            0.17918463,
+            0.17483303,
+            0.12915604,
+            0.013351103,
+            0.14461753,
+            0.20574364,
+            0.14435533,
+            0.04677841,
+            0.10094902,
+            0.0006173667,
+            0.0019223015,
+            0.0011130385,
+            0.0014852291,
+            0.0012549075,
+            0.0007243107,
+            1.15105195e-5,
+            0.001169412,
+            0.0017272434,
+            0.0010620877,
+            0.00012045614,
+            0.0006518713,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(adamw_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_rmsprop() {
+        let optimizer = RMSProp {
+            config: RMSPropConfig {
+                lr: 0.01_f32,
+                alpha: 0.99_f32,
+                eps: 1e-8_f32,
+                initialization: 0.0_f32,
+            },
+        };
+
+        let embedding_entry = execute_test(Box::new(optimizer));
+
+        let rmsprop_result: Vec<f32> = vec![
+            0.54132116,
+            -0.23250248,
+            -0.05368747,
+            0.22808471,
+            -0.19483973,
+            0.46792525,
+            -0.052918818,
+            0.5292305,
+            0.31193835,
+            -0.15176883,
+            0.5792692,
+            0.6796701,
+            0.00609041,
+            0.019128077,
+            0.010977621,
+            0.014729225,
+            0.012435858,
+            0.0071953805,
+            0.000113955706,
+            0.011529022,
+            0.017158061,
+            0.010478165,
+            0.0011956673,
+            0.006445789,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(rmsprop_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_momentum_sgd() {
+        let optimizer = MomentumSGD {
+            config: MomentumSGDConfig {
+                lr: 0.01_f32,
+                wd: 0.01_f32,
+                momentum: 0.9_f32,
+                nesterov: false,
+            },
+        };
+
+        let embedding_entry = execute_test(Box::new(optimizer));
+
+        let momentum_sgd_result: Vec<f32> = vec![
+            0.703015,
+            -0.0007379606,
+            0.09652634,
+            0.39905518,
+            -0.0057342374,
+            0.6715717,
+            0.14966051,
+            0.6699928,
+            0.5201082,
+            -0.006841831,
+            0.75752103,
+            0.84786654,
+            1.1421616,
+            2.034635,
+            1.5036197,
+            1.8033915,
+            1.7489076,
+            1.3102788,
+            0.13763067,
+            1.4650419,
+            2.0723639,
+            1.4440142,
+            0.48850477,
+            1.0329647,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(momentum_sgd_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_nesterov_momentum_sgd() {
+        let optimizer = MomentumSGD {
+            config: MomentumSGDConfig {
+                lr: 0.01_f32,
+                wd: 0.01_f32,
+                momentum: 0.9_f32,
+                nesterov: true,
+            },
+        };
+
+        let embedding_entry = execute_test(Box::new(optimizer));
+
+        let nesterov_result: Vec<f32> = vec![
+            0.6927388,
+            -0.019047035,
+            0.08299805,
+            0.3828284,
+            -0.0214707,
+            0.6597816,
+            0.14842218,
+            0.65681195,
+            0.5014606,
+            -0.019833991,
+            0.7531255,
+            0.83857274,
+            1.1420215,
+            2.034508,
+            1.5034333,
+            1.803233,
+            1.7487451,
+            1.310174,
+            0.13761516,
+            1.4648447,
+            2.0721984,
+            1.4438465,
+            0.48846456,
+            1.0328344,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(nesterov_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    #[test]
+    fn test_lamb() {
+        let optimizer = LAMB {
+            config: LAMBConfig {
+                lr: 0.01_f32,
+                beta1: 0.9_f32,
+                beta2: 0.999_f32,
+                eps: 1e-6_f32,
+                weight_decay: 0.01_f32,
+            },
+            beta_powers: BetaPowers::with_index_prefixes(0.9, 0.999, 0, std::iter::empty()),
+        };
+
+        let embedding_entry = execute_test_with_beta_powers(Box::new(optimizer), 0.9, 0.999);
+
+        let lamb_result: Vec<f32> = vec![
+            0.7143816,
+            0.018310273,
+            0.117067225,
+            0.41968882,
+            0.013877576,
+            0.6803722,
+            0.13763918,
+            0.6914891,
+            0.54302216,
+            0.011710356,
+            0.75248474,
+            0.857519,
+            0.11225793,
+            0.20338804,
+            0.15003033,
+            0.17918463,
+            0.17483303,
+            0.12915604,
+            0.013351103,
+            0.14461753,
+            0.20574364,
+            0.14435533,
+            0.04677841,
+            0.10094902,
+            0.0006173667,
+            0.0019223015,
+            0.0011130385,
+            0.0014852291,
+            0.0012549075,
+            0.0007243107,
+            1.15105195e-5,
+            0.001169412,
+            0.0017272434,
+            0.0010620877,
+            0.00012045614,
+            0.0006518713,
+        ];
+
+        embedding_entry
+            .iter()
+            .zip(lamb_result.iter())
+            .for_each(|(x, y)| assert_eq!(x, y));
+    }
+
+    // keeps the signs of the batch as batch level state
+    struct SignRecorder;
+
+    impl Optimizable for SignRecorder {
+        fn update(&self, _: &mut [f32], _: &[f32], _: usize, _: &Option<Vec<f32>>) {}
+
+        fn get_batch_level_state(&self, signs: &[u64]) -> Option<Vec<f32>> {
+            Some(signs.iter().map(|sign| *sign as f32).collect())
+        }
+
+        fn get_emb_state(&self, state: &Option<Vec<f32>>, idx: usize) -> Option<Vec<f32>> {
+            state.as_ref().map(|state| state[idx..idx + 1].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_beta_powers_of_new_feature_group() {
+        let prefix_mask = 0xff00_0000_0000_0000;
+        let beta_powers =
+            BetaPowers::with_index_prefixes(0.5, 0.25, prefix_mask, std::iter::once(1 << 56));
+
+        beta_powers.get_batch_level_state(&[(1 << 56) | 1]);
+        // signs of a feature group appended to the embedding config after the optimizer is
+        // created start from the initial powers of betas
+        let state = beta_powers
+            .get_batch_level_state(&[(1 << 56) | 1, (2 << 56) | 1])
+            .unwrap();
+        assert_eq!(state, vec![0.125, 0.25, 0.015625, 0.0625]);
+    }
+
+    #[test]
+    fn test_feature_group_optimizers() {
+        let prefix_mask = !((1u64 << 56) - 1);
+        let group_prefix = 2u64 << 56;
+        let mut optimizers = FeatureGroupOptimizers::new(prefix_mask);
+        assert_eq!(optimizers.is_empty(), true);
+
+        optimizers.register_optimizable(Some(group_prefix), 0.0, Box::new(SignRecorder));
+        assert_eq!(optimizers.get(1).is_none(), true);
+        assert_eq!(optimizers.get_emb_states(&[1]), vec![None]);
+
+        optimizers.register(
+            None,
+            OptimizerConfig::SGD(NaiveSGDConfig { lr: 0.1, wd: 0.0 }),
+        );
+        assert_eq!(optimizers.get(1).unwrap().require_space(4), 0);
+
+        let signs = vec![1, group_prefix + 3, 2, group_prefix + 5];
+        let states = optimizers.get_emb_states(&signs);
+        assert_eq!(
+            states,
+            vec![
+                None,
+                Some(vec![(group_prefix + 3) as f32]),
+                None,
+                Some(vec![(group_prefix + 5) as f32]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_learning_rate_schedule() {
+        let step_decay = LearningRateSchedule::StepDecay(StepDecaySchedule {
+            step_size: 10,
+            gamma: 0.5,
+        });
+        assert_eq!(step_decay.lr(0.1, 9), 0.1);
+        assert_eq!(step_decay.lr(0.1, 25), 0.025);
+
+        let cosine = LearningRateSchedule::Cosine(CosineSchedule {
+            total_steps: 100,
+            min_lr: 0.01,
+        });
+        assert_eq!(cosine.lr(0.1, 0), 0.1);
+        assert!((cosine.lr(0.1, 50) - 0.055).abs() < 1e-6);
+        assert_eq!(cosine.lr(0.1, 200), 0.01);
+
+        let warmup = LearningRateSchedule::LinearWarmup(WarmupSchedule { warmup_steps: 4 });
+        assert_eq!(warmup.lr(0.1, 1), 0.025);
+        assert_eq!(warmup.lr(0.1, 8), 0.1);
+
+        let inverse_sqrt = LearningRateSchedule::InverseSqrt(WarmupSchedule { warmup_steps: 4 });
+        assert_eq!(inverse_sqrt.lr(0.1, 2), 0.05);
+        assert_eq!(inverse_sqrt.lr(0.1, 4), 0.1);
+        assert_eq!(inverse_sqrt.lr(0.1, 16), 0.05);
+    }
+
+    // keeps its learning rate as batch level state
+    struct LrRecorder {
+        lr: f32,
+    }
+
+    impl Optimizable for LrRecorder {
+        fn update(&self, _: &mut [f32], _: &[f32], _: usize, _: &Option<Vec<f32>>) {}
+
+        fn get_batch_level_state(&self, _signs: &[u64]) -> Option<Vec<f32>> {
+            Some(vec![self.lr])
+        }
+
+        fn update_lr(&mut self, lr: f32) {
+            self.lr = lr;
+        }
+    }
+
+    #[test]
+    fn test_feature_group_optimizers_learning_rate() {
+        let prefix_mask = !((1u64 << 56) - 1);
+        let group_prefix = 2u64 << 56;
+        let lr = |optimizers: &FeatureGroupOptimizers, sign: u64| {
+            optimizers
+                .get(sign)
+                .unwrap()
+                .get_batch_level_state(&[sign])
+                .unwrap()[0]
+        };
+
+        let mut optimizers = FeatureGroupOptimizers::new(prefix_mask);
+        optimizers.register_optimizable(None, 0.1, Box::new(LrRecorder { lr: 0.1 }));
+        optimizers.update_learning_rate(LearningRateUpdate::Schedule(
+            LearningRateSchedule::StepDecay(StepDecaySchedule {
+                step_size: 10,
+                gamma: 0.5,
+            }),
+        ));
+        optimizers.update_learning_rate(LearningRateUpdate::GlobalStep(10));
+        assert_eq!(lr(&optimizers, 1), 0.05);
+
+        // optimizers registered later join the schedule at the current step
+        optimizers.register_optimizable(Some(group_prefix), 0.2, Box::new(LrRecorder { lr: 0.2 }));
+        assert_eq!(lr(&optimizers, group_prefix), 0.1);
+
+        optimizers.update_learning_rate(LearningRateUpdate::GlobalStep(20));
+        assert_eq!(lr(&optimizers, 1), 0.025);
+        assert_eq!(lr(&optimizers, group_prefix), 0.05);
+
+        // a learning rate set by the trainer replaces the schedule
+        optimizers.update_learning_rate(LearningRateUpdate::LearningRate(0.3));
+        optimizers.update_learning_rate(LearningRateUpdate::GlobalStep(30));
+        assert_eq!(lr(&optimizers, 1), 0.3);
+        assert_eq!(lr(&optimizers, group_prefix), 0.3);
+    }
+
+    #[test]
+    fn test_advance_step() {
+        let mut optimizers = FeatureGroupOptimizers::new(0);
+        optimizers.register_optimizable(None, 0.1, Box::new(LrRecorder { lr: 0.1 }));
+        optimizers.update_learning_rate(LearningRateUpdate::Schedule(
+            LearningRateSchedule::LinearWarmup(WarmupSchedule { warmup_steps: 4 }),
+        ));
+        let lr = |optimizers: &FeatureGroupOptimizers| {
+            optimizers
+                .get(1)
+                .unwrap()
+                .get_batch_level_state(&[1])
+                .unwrap()[0]
+        };
+        assert_eq!(lr(&optimizers), 0.0);
+
+        // the warmup leaves step 0 without the trainer setting the global step
+        optimizers.advance_step();
+        assert_eq!(lr(&optimizers), 0.025);
+
+        // steps advanced on replaced optimizers are kept by their replacements
+        let replaced = optimizers.clone();
+        replaced.advance_step();
+        optimizers.advance_step();
+        assert_eq!(lr(&optimizers), 0.075);
+        assert_eq!(optimizers.get_state().global_step, 3);
+    }
+
+    fn adam_with_feature_groups(prefixes: &[u64]) -> Adam {
+        Adam {
+            config: AdamConfig {
+                lr: 0.01_f32,
+                beta1: 0.9_f32,
+                beta2: 0.999_f32,
+                eps: 1e-8_f32,
+                weight_decay: 0.0_f32,
+            },
+            beta_powers: BetaPowers::with_index_prefixes(
+                0.9,
+                0.999,
+                !((1u64 << 56) - 1),
+                prefixes.iter().copied(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_feature_group_optimizers_state() {
+        let prefix_mask = !((1u64 << 56) - 1);
+        let group_prefix = 2u64 << 56;
+        let prefixes = [0, group_prefix];
+
+        let mut optimizers = FeatureGroupOptimizers::new(prefix_mask);
+        optimizers.register_optimizable(None, 0.01, Box::new(adam_with_feature_groups(&prefixes)));
+        optimizers.update_learning_rate(LearningRateUpdate::Schedule(
+            LearningRateSchedule::LinearWarmup(WarmupSchedule { warmup_steps: 10 }),
+        ));
+        optimizers.update_learning_rate(LearningRateUpdate::GlobalStep(5));
+        optimizers.get_emb_states(&[1, group_prefix + 1]);
+        optimizers.get_emb_states(&[1]);
+        let state = optimizers.get_state();
+        assert_eq!(state.global_step, 5);
+        assert_eq!(state.groups.len(), 0);
+
+        // the state is restored into optimizers registered after loading it
+        let mut restored = FeatureGroupOptimizers::new(prefix_mask);
+        restored.load_state(state.clone());
+        restored.register_optimizable(None, 0.01, Box::new(adam_with_feature_groups(&prefixes)));
+        assert_eq!(restored.get_state(), state);
+
+        let signs = [1, group_prefix + 1];
+        assert_eq!(
+            restored.get_emb_states(&signs),
+            optimizers.get_emb_states(&signs)
+        );
+    }
 }
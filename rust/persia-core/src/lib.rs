@@ -22,6 +22,7 @@ use crate::forward::{forward_directly, PersiaTrainingBatch};
 use crate::optim::OptimizerBase;
 use crate::rpc::PersiaRpcClient;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -40,6 +41,7 @@ use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 use pyo3::wrap_pyfunction;
 
+use persia_common::optim::LearningRateUpdate;
 use persia_common::utils::start_deadlock_detection_thread;
 use persia_embedding_config::{PersiaGlobalConfigError, PersiaReplicaInfo};
 use persia_embedding_holder::emb_entry::HashMapEmbeddingEntry;
@@ -163,10 +165,29 @@ impl PersiaCommonContextImpl {
         Ok(instance)
     }
 
-    pub fn register_optimizer(&self, optimizer: &OptimizerBase) -> Result<(), PersiaError> {
+    pub fn register_optimizer(
+        &self,
+        optimizer: &OptimizerBase,
+        feature_groups: Vec<String>,
+    ) -> Result<(), PersiaError> {
         self.async_runtime.block_on(
             self.get_nats_publish_service()?
-                .register_optimizer(optimizer),
+                .register_optimizer(optimizer, feature_groups),
+        )
+    }
+
+    pub fn set_embedding_learning_rate(
+        &self,
+        update: LearningRateUpdate,
+    ) -> Result<(), PersiaError> {
+        self.async_runtime
+            .block_on(self.get_nats_publish_service()?.set_learning_rate(update))
+    }
+
+    pub fn reload_embedding_config(&self, content: String) -> Result<(), PersiaError> {
+        self.async_runtime.block_on(
+            self.get_nats_publish_service()?
+                .reload_embedding_config(content),
         )
     }
 
@@ -371,6 +392,11 @@ impl PersiaCommonContext {
         admit_probability: f32,
         enable_weight_bound: bool,
         weight_bound: f32,
+        admit_frequency: HashMap<String, u32>,
+        feature_group_hyperparameters: HashMap<
+            String,
+            (Option<(f32, f32)>, Option<f32>, Option<f32>),
+        >,
     ) -> PyResult<()> {
         self.inner
             .async_runtime
@@ -383,11 +409,36 @@ impl PersiaCommonContext {
                         admit_probability,
                         enable_weight_bound,
                         weight_bound,
+                        admit_frequency,
+                        feature_group_hyperparameters,
                     ),
             )
             .map_err(|e| e.into())
     }
 
+    /// Sets the learning rate of all embedding optimizers, replacing the learning rate schedule
+    /// if there is one.
+    pub fn set_embedding_learning_rate(&self, lr: f32) -> PyResult<()> {
+        self.inner
+            .set_embedding_learning_rate(LearningRateUpdate::LearningRate(lr))
+            .map_err(|e| e.into())
+    }
+
+    /// Moves the learning rate schedule of the embedding optimizers to `global_step`.
+    pub fn set_embedding_global_step(&self, global_step: u64) -> PyResult<()> {
+        self.inner
+            .set_embedding_learning_rate(LearningRateUpdate::GlobalStep(global_step))
+            .map_err(|e| e.into())
+    }
+
+    /// Reloads the embedding config of the embedding workers and embedding servers from the
+    /// given yaml content. Only new feature groups and slots can be added to the config.
+    pub fn reload_embedding_config(&self, content: String) -> PyResult<()> {
+        self.inner
+            .reload_embedding_config(content)
+            .map_err(|e| e.into())
+    }
+
     pub fn get_embedding_from_data(
         &self,
         batch: &mut PersiaBatch,
@@ -3,6 +3,7 @@ use crate::optim::OptimizerBase;
 use crate::utils::PersiaBatchDataSender;
 use crate::{PersiaCommonContextImpl, PersiaError};
 
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
@@ -11,10 +12,11 @@ use persia_libs::{async_lock::RwLock, backoff, once_cell::sync::OnceCell, tracin
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 
-use persia_common::IDTypeFeatureRemoteRef;
+use persia_common::{optim::LearningRateUpdate, IDTypeFeatureRemoteRef};
 use persia_embedding_config::PersiaReplicaInfo;
 use persia_embedding_config::{
-    BoundedUniformInitialization, InitializationMethod, PersiaEmbeddingModelHyperparameters,
+    BoundedUniformInitialization, FeatureGroupHyperparameters, InitializationMethod,
+    PersiaEmbeddingModelHyperparameters,
 };
 use persia_embedding_server::embedding_worker_service::EmbeddingWorkerNatsServicePublisher;
 use persia_nats_client::NatsError;
@@ -359,11 +361,43 @@ impl PersiaDataFlowComponent {
         admit_probability: f32,
         enable_weight_bound: bool,
         weight_bound: f32,
+        admit_frequency: HashMap<String, u32>,
+        // initialization bounds, admit probability and weight bound of feature groups
+        feature_group_hyperparameters: HashMap<
+            String,
+            (Option<(f32, f32)>, Option<f32>, Option<f32>),
+        >,
     ) -> Result<(), PersiaError> {
         assert!(
             (0. <= admit_probability) && (admit_probability <= 1.),
             "admit probability should be within 0 ~ 1"
         );
+        let feature_group_hyperparameters = feature_group_hyperparameters
+            .into_iter()
+            .map(
+                |(feature_group_name, (initialization, admit_probability, weight_bound))| {
+                    if let Some(p) = admit_probability {
+                        assert!(
+                            (0. ..=1.).contains(&p),
+                            "admit probability of feature group {} should be within 0 ~ 1",
+                            feature_group_name
+                        );
+                    }
+                    let hyperparameters = FeatureGroupHyperparameters {
+                        initialization_method: initialization.map(|(lower, upper)| {
+                            InitializationMethod::BoundedUniform(BoundedUniformInitialization {
+                                lower,
+                                upper,
+                            })
+                        }),
+                        admit_probability,
+                        weight_bound,
+                        gradient_clipping: None,
+                    };
+                    (feature_group_name, hyperparameters)
+                },
+            )
+            .collect();
         let config = PersiaEmbeddingModelHyperparameters {
             initialization_method: InitializationMethod::BoundedUniform(
                 BoundedUniformInitialization {
@@ -374,6 +408,8 @@ impl PersiaDataFlowComponent {
             admit_probability,
             weight_bound,
             enable_weight_bound,
+            admit_frequency,
+            feature_group_hyperparameters,
         };
 
         self.embedding_worker_publish_service
@@ -383,7 +419,11 @@ impl PersiaDataFlowComponent {
         Ok(())
     }
 
-    pub async fn register_optimizer(&self, opt: &OptimizerBase) -> Result<(), PersiaError> {
+    pub async fn register_optimizer(
+        &self,
+        opt: &OptimizerBase,
+        feature_groups: Vec<String>,
+    ) -> Result<(), PersiaError> {
         let optimizer = opt.get_inner();
         if optimizer.is_none() {
             return Err(PersiaError::NullOptimizerError);
@@ -391,12 +431,33 @@ impl PersiaDataFlowComponent {
         let optimizer = optimizer.unwrap();
 
         self.embedding_worker_publish_service
-            .publish_register_optimizer(&optimizer, None)
+            .publish_register_optimizer(&(feature_groups, optimizer), None)
+            .await??;
+
+        Ok(())
+    }
+
+    pub async fn set_learning_rate(&self, update: LearningRateUpdate) -> Result<(), PersiaError> {
+        self.embedding_worker_publish_service
+            .publish_set_learning_rate(&update, None)
             .await??;
 
         Ok(())
     }
 
+    pub async fn reload_embedding_config(&self, content: String) -> Result<(), PersiaError> {
+        // the embedding servers are reloaded through the first embedding worker, before any
+        // embedding worker can look up the signs of the new slots
+        for embedding_worker_idx in 0..self.num_embedding_workers {
+            let req = (content.clone(), embedding_worker_idx == 0);
+            self.embedding_worker_publish_service
+                .publish_reload_embedding_config(&req, Some(embedding_worker_idx))
+                .await??;
+        }
+
+        Ok(())
+    }
+
     pub async fn wait_servers_ready(&self) -> Result<String, PersiaError> {
         let addr = self
             .embedding_worker_publish_service
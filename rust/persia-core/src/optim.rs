@@ -3,7 +3,11 @@ use crate::PersiaCommonContextImpl;
 use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
 
-use persia_common::optim::{AdagradConfig, AdamConfig, NaiveSGDConfig, OptimizerConfig};
+use persia_common::optim::{
+    AdagradConfig, AdamConfig, AdamWConfig, CosineSchedule, FTRLConfig, LAMBConfig,
+    LearningRateSchedule, LearningRateUpdate, MomentumSGDConfig, NaiveSGDConfig, OptimizerConfig,
+    RMSPropConfig, StepDecaySchedule, WarmupSchedule,
+};
 
 #[pyclass]
 pub struct OptimizerBase {
@@ -48,20 +52,137 @@ impl OptimizerBase {
         self.inner = Some(OptimizerConfig::SGD(config));
     }
 
-    pub fn init_adam(&mut self, lr: f32, betas: (f32, f32), eps: f32) -> () {
+    pub fn init_adam(
+        &mut self,
+        lr: f32,
+        betas: (f32, f32),
+        eps: f32,
+        weight_decay: Option<f32>,
+    ) -> () {
         let config = AdamConfig {
             lr,
             beta1: betas.0,
             beta2: betas.1,
             eps,
+            weight_decay: weight_decay.unwrap_or(0.0),
         };
         self.inner = Some(OptimizerConfig::Adam(config));
     }
 
+    pub fn init_ftrl(
+        &mut self,
+        lr: f32,
+        beta: f32,
+        lambda1: f32,
+        lambda2: f32,
+        initialization: f32,
+    ) -> () {
+        let config = FTRLConfig {
+            lr,
+            beta,
+            lambda1,
+            lambda2,
+            initialization,
+        };
+        self.inner = Some(OptimizerConfig::FTRL(config));
+    }
+
+    pub fn init_adamw(&mut self, lr: f32, betas: (f32, f32), eps: f32, weight_decay: f32) -> () {
+        let config = AdamWConfig {
+            lr,
+            beta1: betas.0,
+            beta2: betas.1,
+            eps,
+            weight_decay,
+        };
+        self.inner = Some(OptimizerConfig::AdamW(config));
+    }
+
+    pub fn init_rmsprop(&mut self, lr: f32, alpha: f32, eps: f32, initialization: f32) -> () {
+        let config = RMSPropConfig {
+            lr,
+            alpha,
+            eps,
+            initialization,
+        };
+        self.inner = Some(OptimizerConfig::RMSProp(config));
+    }
+
+    pub fn init_momentum_sgd(&mut self, lr: f32, wd: f32, momentum: f32, nesterov: bool) -> () {
+        let config = MomentumSGDConfig {
+            lr,
+            wd,
+            momentum,
+            nesterov,
+        };
+        self.inner = Some(OptimizerConfig::MomentumSGD(config));
+    }
+
+    pub fn init_lamb(&mut self, lr: f32, betas: (f32, f32), eps: f32, weight_decay: f32) -> () {
+        let config = LAMBConfig {
+            lr,
+            beta1: betas.0,
+            beta2: betas.1,
+            eps,
+            weight_decay,
+        };
+        self.inner = Some(OptimizerConfig::LAMB(config));
+    }
+
+    /// Registers the optimizer for `feature_groups`, or as the optimizer of all feature groups
+    /// without their own one if no feature group is given.
+    pub fn apply(&self, feature_groups: Option<Vec<String>>) -> PyResult<()> {
+        let context = PersiaCommonContextImpl::get();
+        context
+            .register_optimizer(self, feature_groups.unwrap_or_default())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
+
+#[pyclass]
+pub struct LearningRateScheduleBase {
+    inner: Option<LearningRateSchedule>,
+}
+
+#[pymethods]
+impl LearningRateScheduleBase {
+    #[new]
+    pub fn new() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn init_step_decay(&mut self, step_size: u64, gamma: f32) -> () {
+        let schedule = StepDecaySchedule { step_size, gamma };
+        self.inner = Some(LearningRateSchedule::StepDecay(schedule));
+    }
+
+    pub fn init_cosine(&mut self, total_steps: u64, min_lr: f32) -> () {
+        let schedule = CosineSchedule {
+            total_steps,
+            min_lr,
+        };
+        self.inner = Some(LearningRateSchedule::Cosine(schedule));
+    }
+
+    pub fn init_linear_warmup(&mut self, warmup_steps: u64) -> () {
+        let schedule = WarmupSchedule { warmup_steps };
+        self.inner = Some(LearningRateSchedule::LinearWarmup(schedule));
+    }
+
+    pub fn init_inverse_sqrt(&mut self, warmup_steps: u64) -> () {
+        let schedule = WarmupSchedule { warmup_steps };
+        self.inner = Some(LearningRateSchedule::InverseSqrt(schedule));
+    }
+
+    /// Schedules the learning rates of the embedding optimizers from the global step.
     pub fn apply(&self) -> PyResult<()> {
+        let schedule = self
+            .inner
+            .clone()
+            .ok_or_else(|| PyRuntimeError::new_err("learning rate schedule not initialized"))?;
         let context = PersiaCommonContextImpl::get();
         context
-            .register_optimizer(self)
+            .set_embedding_learning_rate(LearningRateUpdate::Schedule(schedule))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 }
@@ -69,6 +190,7 @@ impl OptimizerBase {
 pub fn init_module(super_module: &PyModule, py: Python) -> PyResult<()> {
     let module = PyModule::new(py, "optim")?;
     module.add_class::<OptimizerBase>()?;
+    module.add_class::<LearningRateScheduleBase>()?;
     super_module.add_submodule(module)?;
     Ok(())
 }
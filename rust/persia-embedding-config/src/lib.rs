@@ -1,13 +1,19 @@
 #![allow(clippy::needless_return)]
 
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
 use persia_libs::{
     indexmap,
     once_cell::sync::OnceCell,
-    serde::{self, Deserialize, Serialize},
-    serde_yaml,
-    thiserror::Error,
+    parking_lot::RwLock,
+    serde::{self, de::DeserializeOwned, Deserialize, Deserializer, Serialize},
+    serde_ignored, serde_yaml,
+    thiserror::{self, Error},
     tracing,
 };
 
@@ -19,11 +25,159 @@ pub enum PersiaGlobalConfigError {
     NotReadyError,
     #[error("global config set error")]
     SetError,
-    #[error("failed to open/read config file error")]
+    #[error("failed to open/read config file error: {0}")]
     ConfigFileError(String),
+    #[error("failed to parse config file error: {0}")]
+    ConfigParseError(String),
+    #[error("invalid config: {}", format_config_problems(.0))]
+    InvalidConfigError(Vec<ConfigProblem>),
+}
+
+/// A problem found when validating a config, `path` is the yaml path of the invalid key.
+#[derive(Readable, Writable, Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    pub path: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+fn format_config_problems(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(|problem| problem.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn read_config_file(file_path: &Path) -> Result<String, PersiaGlobalConfigError> {
+    std::fs::read_to_string(file_path).map_err(|e| {
+        PersiaGlobalConfigError::ConfigFileError(format!("{}: {}", file_path.display(), e))
+    })
+}
+
+// deserializes a yaml config, the keys matching no config field are returned as problems
+fn parse_yaml_config<T: DeserializeOwned>(
+    content: &str,
+) -> Result<(T, Vec<ConfigProblem>), PersiaGlobalConfigError> {
+    deserialize_yaml_config(serde_yaml::Deserializer::from_str(content))
+}
+
+fn deserialize_yaml_config<'de, T: DeserializeOwned, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<(T, Vec<ConfigProblem>), PersiaGlobalConfigError> {
+    let mut problems = Vec::new();
+    let config = serde_ignored::deserialize(deserializer, |path| {
+        problems.push(ConfigProblem::new(path.to_string(), "unknown key"))
+    })
+    .map_err(|e| PersiaGlobalConfigError::ConfigParseError(e.to_string()))?;
+    Ok((config, problems))
 }
 
-#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone)]
+fn into_config_result<T>(
+    config: T,
+    problems: Vec<ConfigProblem>,
+) -> Result<T, PersiaGlobalConfigError> {
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        Err(PersiaGlobalConfigError::InvalidConfigError(problems))
+    }
+}
+
+const CONFIG_OVERRIDE_ENV_PREFIX: &str = "PERSIA__";
+const CONFIG_OVERRIDE_ENV_SEPARATOR: &str = "__";
+
+/// A value overriding the one read from a config file, `path` is the yaml path of the key.
+///
+/// Overrides are given as `PERSIA__EMBEDDING_WORKER_CONFIG__FORWARD_BUFFER_SIZE=1000` style
+/// environment variables, or as `embedding_worker_config.forward_buffer_size=1000` style
+/// command line flags. The value is parsed as yaml.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOverride {
+    pub path: Vec<String>,
+    pub value: String,
+}
+
+impl ConfigOverride {
+    /// Collects the overrides of the `PERSIA__` prefixed environment variables, sorted by key.
+    pub fn from_env_vars() -> Vec<Self> {
+        let mut overrides: Vec<Self> = std::env::vars()
+            .filter_map(|(key, value)| Self::from_env_var(key.as_str(), value.as_str()))
+            .collect();
+        overrides.sort_by(|a, b| a.path.cmp(&b.path));
+        overrides
+    }
+
+    pub fn from_env_var(key: &str, value: &str) -> Option<Self> {
+        let path = key.strip_prefix(CONFIG_OVERRIDE_ENV_PREFIX)?;
+        Some(Self {
+            path: path
+                .split(CONFIG_OVERRIDE_ENV_SEPARATOR)
+                .map(|key| key.to_lowercase())
+                .collect(),
+            value: value.to_string(),
+        })
+    }
+
+    fn apply(&self, config: &mut serde_yaml::Value) -> Result<(), ConfigProblem> {
+        let path = self.path.join(".");
+        if self.path.iter().any(|key| key.is_empty()) {
+            return Err(ConfigProblem::new(path, "empty key in override"));
+        }
+        let value = serde_yaml::from_str(self.value.as_str())
+            .unwrap_or_else(|_| serde_yaml::Value::String(self.value.clone()));
+
+        let mut node = config;
+        for key in self.path.iter() {
+            if node.is_null() {
+                *node = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+            }
+            node = match node {
+                serde_yaml::Value::Mapping(mapping) => mapping
+                    .entry(serde_yaml::Value::String(key.clone()))
+                    .or_insert(serde_yaml::Value::Null),
+                _ => {
+                    return Err(ConfigProblem::new(
+                        path,
+                        format!("cannot override a key of a non mapping value at {}", key),
+                    ))
+                }
+            };
+        }
+        *node = value;
+        Ok(())
+    }
+}
+
+impl FromStr for ConfigOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((path, value)) => Ok(Self {
+                path: path.split('.').map(|key| key.to_string()).collect(),
+                value: value.to_string(),
+            }),
+            None => Err(format!("expected KEY=VALUE, got {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub struct BoundedUniformInitialization {
     pub lower: f32,
@@ -36,7 +190,7 @@ impl BoundedUniformInitialization {
     }
 }
 
-#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub struct BoundedGammaInitialization {
     pub shape: f32,
@@ -49,7 +203,7 @@ impl BoundedGammaInitialization {
     }
 }
 
-#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub struct BoundedPoissonInitialization {
     pub lambda: f32,
@@ -61,7 +215,7 @@ impl BoundedPoissonInitialization {
     }
 }
 
-#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub struct BoundedNormalInitialization {
     pub mean: f32,
@@ -77,14 +231,36 @@ impl BoundedNormalInitialization {
     }
 }
 
-#[derive(Serialize, Deserialize, Readable, Writable, Debug, Clone)]
+// normal distribution with the samples out of two standard deviations from the mean redrawn
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone, PartialEq)]
+#[serde(crate = "self::serde")]
+pub struct TruncatedNormalInitialization {
+    pub mean: f32,
+    pub standard_deviation: f32,
+}
+
+impl TruncatedNormalInitialization {
+    pub fn new(mean: f32, standard_deviation: f32) -> Self {
+        TruncatedNormalInitialization {
+            mean,
+            standard_deviation,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub enum InitializationMethod {
+    // uniform in [-1 / sqrt(dim), 1 / sqrt(dim)]
     InverseEmbeddingSizeSqrt,
     BoundedUniform(BoundedUniformInitialization),
     BoundedGamma(BoundedGammaInitialization),
     BoundedPoisson(BoundedPoissonInitialization),
     BoundedNormal(BoundedNormalInitialization),
+    TruncatedNormal(TruncatedNormalInitialization),
+    // Xavier/Glorot uniform in [-sqrt(6 / (fan_in + fan_out)), sqrt(6 / (fan_in + fan_out))],
+    // both fans are taken as dim since the vocabulary size of a hashed feature is unknown
+    XavierUniform,
 }
 
 impl Default for InitializationMethod {
@@ -96,12 +272,75 @@ impl Default for InitializationMethod {
     }
 }
 
+/// Clipping applied to the gradient of each embedding before the optimizer update.
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "self::serde")]
+pub enum GradientClipping {
+    // rescales the gradient so that its L2 norm is at most the given value
+    Norm(f32),
+    // clamps each gradient value into [-value, value]
+    Value(f32),
+}
+
+impl GradientClipping {
+    pub fn clip(&self, gradient: &mut [f32]) {
+        match *self {
+            Self::Norm(max_norm) => {
+                let norm = gradient.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm > max_norm {
+                    let scale = max_norm / norm;
+                    gradient.iter_mut().for_each(|x| *x *= scale);
+                }
+            }
+            Self::Value(max_value) => gradient
+                .iter_mut()
+                .for_each(|x| *x = x.max(-max_value).min(max_value)),
+        }
+    }
+}
+
+/// Hyperparameters of a feature group overriding the ones of
+/// `PersiaEmbeddingModelHyperparameters`, unset fields fall back to them.
+#[derive(Serialize, Deserialize, Readable, Writable, Debug, Default, Clone, PartialEq)]
+#[serde(crate = "self::serde")]
+pub struct FeatureGroupHyperparameters {
+    pub initialization_method: Option<InitializationMethod>,
+    pub admit_probability: Option<f32>,
+    // enables the weight bound for the feature group even if it is disabled globally
+    pub weight_bound: Option<f32>,
+    pub gradient_clipping: Option<GradientClipping>,
+}
+
+impl FeatureGroupHyperparameters {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Takes the fields unset in `self` from `fallback`.
+    pub fn or(self, fallback: &Self) -> Self {
+        Self {
+            initialization_method: self
+                .initialization_method
+                .or_else(|| fallback.initialization_method.clone()),
+            admit_probability: self.admit_probability.or(fallback.admit_probability),
+            weight_bound: self.weight_bound.or(fallback.weight_bound),
+            gradient_clipping: self.gradient_clipping.or(fallback.gradient_clipping),
+        }
+    }
+}
+
 #[derive(Readable, Writable, Debug, Clone)]
 pub struct PersiaEmbeddingModelHyperparameters {
     pub initialization_method: InitializationMethod,
     pub admit_probability: f32,
     pub weight_bound: f32,
     pub enable_weight_bound: bool,
+    // number of sightings a new sign needs before its embedding is created, keyed by feature
+    // group name. Signs of groups not listed here are admitted at first sight.
+    pub admit_frequency: HashMap<String, u32>,
+    // overrides of the hyperparameters above keyed by feature group name, taking precedence
+    // over the overrides in the slot configs
+    pub feature_group_hyperparameters: HashMap<String, FeatureGroupHyperparameters>,
 }
 
 static PERSIA_EMBEDDING_SEVER_CONFIG: OnceCell<Arc<EmbeddingParameterServerConfig>> =
@@ -115,7 +354,8 @@ static PERSIA_REPLICA_INFO: OnceCell<Arc<PersiaReplicaInfo>> = OnceCell::new();
 
 static PERSIA_INSTANCE_INFO: OnceCell<Arc<InstanceInfo>> = OnceCell::new();
 
-static PERSIA_EMBEDDING_CONFIG: OnceCell<Arc<EmbeddingConfig>> = OnceCell::new();
+// reloadable to append new slots and feature groups, see `EmbeddingConfig::reload`
+static PERSIA_EMBEDDING_CONFIG: OnceCell<RwLock<Arc<EmbeddingConfig>>> = OnceCell::new();
 
 /// get local ip address by specific socket name or
 /// request the 8.8.8.8:80 to retrieve the ip address from response.
@@ -314,10 +554,34 @@ fn get_default_feature_groups() -> indexmap::IndexMap<String, Vec<String>> {
     indexmap::IndexMap::new()
 }
 
+fn get_default_feature_group_capacity() -> HashMap<String, usize> {
+    HashMap::new()
+}
+
 fn get_default_checkpointing_config() -> CheckpointingConfig {
     CheckpointingConfig::default()
 }
 
+fn get_default_eviction_policy() -> EvictionPolicy {
+    EvictionPolicy::default()
+}
+
+fn get_default_storage_backend() -> EmbeddingStorageBackend {
+    EmbeddingStorageBackend::default()
+}
+
+fn get_default_storage_dtype() -> StorageDtype {
+    StorageDtype::default()
+}
+
+fn get_default_gradient_reduction() -> GradientReduction {
+    GradientReduction::default()
+}
+
+fn get_none<T>() -> Option<T> {
+    None
+}
+
 #[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
 #[serde(crate = "self::serde")]
 pub struct PersiaMetricsConfig {
@@ -414,6 +678,73 @@ impl EmbeddingWorkerConfig {
     }
 }
 
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "self::serde")]
+pub enum EvictionPolicy {
+    // evict the least recently accessed entry
+    LRU,
+    // evict the least frequently accessed entry, ties broken by recency
+    LFU,
+    // admit entries through a small LRU window, then keep them only if they are accessed
+    // more frequently than the victim of the main region
+    WindowTinyLFU,
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        Self::LRU
+    }
+}
+
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
+#[serde(crate = "self::serde")]
+pub struct TieredDiskConfig {
+    // directory of the disk logs, one log file per internal shard
+    pub path: String,
+}
+
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
+#[serde(crate = "self::serde")]
+pub enum EmbeddingStorageBackend {
+    // entries evicted from memory are dropped
+    Memory,
+    // entries evicted from memory are spilled to local disk, and memory misses fall back to
+    // disk before creating new entries
+    TieredDisk(TieredDiskConfig),
+}
+
+impl Default for EmbeddingStorageBackend {
+    fn default() -> Self {
+        Self::Memory
+    }
+}
+
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "self::serde")]
+pub enum GradientReduction {
+    Sum,
+    Mean,
+}
+
+impl Default for GradientReduction {
+    fn default() -> Self {
+        Self::Mean
+    }
+}
+
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
+#[serde(crate = "self::serde")]
+pub struct GradientAccumulationConfig {
+    // the buffered gradients are applied every flush_interval_ms, the ones of a sign are
+    // applied earlier once max_accumulated_updates of its updates are buffered
+    #[serde(default = "get_ten")]
+    pub flush_interval_ms: usize,
+    #[serde(default = "get_ten")]
+    pub max_accumulated_updates: usize,
+    #[serde(default = "get_default_gradient_reduction")]
+    pub reduction: GradientReduction,
+}
+
 #[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
 #[serde(crate = "self::serde")]
 pub struct EmbeddingParameterServerConfig {
@@ -422,6 +753,21 @@ pub struct EmbeddingParameterServerConfig {
     pub capacity: usize,
     #[serde(default = "get_hundred")]
     pub num_hashmap_internal_shards: usize,
+    #[serde(default = "get_default_eviction_policy")]
+    pub eviction_policy: EvictionPolicy,
+    // bound each internal shard by memory_budget_bytes / num_hashmap_internal_shards bytes
+    // of entries instead of by capacity, 0 disables the memory budget
+    #[serde(default = "get_zero")]
+    pub memory_budget_bytes: u64,
+    // entries not accessed within ttl_sec seconds are removed, 0 disables expiry
+    #[serde(default = "get_zero")]
+    pub ttl_sec: u64,
+    // entries not accessed within ttl_steps gradient updates received by the embedding server
+    // are removed, 0 disables expiry, exclusive with ttl_sec
+    #[serde(default = "get_zero")]
+    pub ttl_steps: u64,
+    #[serde(default = "get_default_storage_backend")]
+    pub storage_backend: EmbeddingStorageBackend,
     // incremental dump config
     #[serde(default = "get_false")]
     pub enable_incremental_update: bool,
@@ -431,6 +777,10 @@ pub struct EmbeddingParameterServerConfig {
     pub incremental_dir: String,
     #[serde(default = "get_thousand")]
     pub incremental_channel_capacity: usize,
+    // buffers the gradients of each sign on the embedding server and applies them in one
+    // optimizer step, None applies every gradient update at once
+    #[serde(default = "get_none")]
+    pub gradient_accumulation: Option<GradientAccumulationConfig>,
 }
 
 impl Default for EmbeddingParameterServerConfig {
@@ -438,10 +788,16 @@ impl Default for EmbeddingParameterServerConfig {
         Self {
             capacity: 1_000_000_000,
             num_hashmap_internal_shards: 1000,
+            eviction_policy: EvictionPolicy::default(),
+            memory_budget_bytes: 0,
+            ttl_sec: 0,
+            ttl_steps: 0,
+            storage_backend: EmbeddingStorageBackend::default(),
             enable_incremental_update: false,
             incremental_buffer_size: 1_000_000,
             incremental_dir: get_default_incremental_dir(),
             incremental_channel_capacity: 1000,
+            gradient_accumulation: None,
         }
     }
 }
@@ -468,22 +824,108 @@ pub struct PersiaGlobalConfig {
 }
 
 impl PersiaGlobalConfig {
+    /// Reads a global config file, unknown keys and invalid values are reported all at once.
+    pub fn load(file_path: &Path) -> Result<Self, PersiaGlobalConfigError> {
+        Self::from_yaml_str(read_config_file(file_path)?.as_str())
+    }
+
+    pub fn from_yaml_str(content: &str) -> Result<Self, PersiaGlobalConfigError> {
+        Self::from_yaml_str_with_overrides(content, &[])
+    }
+
+    /// Reads a global config file layered with the overrides of the `PERSIA__` prefixed
+    /// environment variables, and then with the given command line overrides.
+    pub fn load_with_overrides(
+        file_path: &Path,
+        cli_overrides: &[ConfigOverride],
+    ) -> Result<Self, PersiaGlobalConfigError> {
+        let mut overrides = ConfigOverride::from_env_vars();
+        overrides.extend_from_slice(cli_overrides);
+        Self::from_yaml_str_with_overrides(read_config_file(file_path)?.as_str(), &overrides)
+    }
+
+    /// Parses a global config with the given overrides applied in order, so a later override
+    /// of a key takes precedence.
+    pub fn from_yaml_str_with_overrides(
+        content: &str,
+        overrides: &[ConfigOverride],
+    ) -> Result<Self, PersiaGlobalConfigError> {
+        // the config is only parsed through a yaml value when overridden, which loses the line
+        // numbers of the parse errors
+        let (config, mut problems): (Self, _) = if overrides.is_empty() {
+            parse_yaml_config(content)?
+        } else {
+            let mut value: serde_yaml::Value = serde_yaml::from_str(content)
+                .map_err(|e| PersiaGlobalConfigError::ConfigParseError(e.to_string()))?;
+            let override_problems: Vec<ConfigProblem> = overrides
+                .iter()
+                .filter_map(|config_override| config_override.apply(&mut value).err())
+                .collect();
+            let (config, mut problems) = deserialize_yaml_config(value)?;
+            problems.extend(override_problems);
+            (config, problems)
+        };
+        problems.extend(config.validate());
+        into_config_result(config, problems)
+    }
+
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        let server_config = &self.embedding_parameter_server_config;
+        if server_config.capacity == 0 {
+            problems.push(ConfigProblem::new(
+                "embedding_parameter_server_config.capacity",
+                "must be > 0",
+            ));
+        }
+        if server_config.num_hashmap_internal_shards == 0 {
+            problems.push(ConfigProblem::new(
+                "embedding_parameter_server_config.num_hashmap_internal_shards",
+                "must be > 0",
+            ));
+        }
+        if server_config.ttl_sec > 0 && server_config.ttl_steps > 0 {
+            problems.push(ConfigProblem::new(
+                "embedding_parameter_server_config.ttl_steps",
+                "can not be set together with ttl_sec",
+            ));
+        }
+        if let Some(gradient_accumulation) = server_config.gradient_accumulation.as_ref() {
+            if gradient_accumulation.flush_interval_ms == 0 {
+                problems.push(ConfigProblem::new(
+                    "embedding_parameter_server_config.gradient_accumulation.flush_interval_ms",
+                    "must be > 0",
+                ));
+            }
+            if gradient_accumulation.max_accumulated_updates == 0 {
+                problems.push(ConfigProblem::new(
+                    "embedding_parameter_server_config.gradient_accumulation.max_accumulated_updates",
+                    "must be > 0",
+                ));
+            }
+        }
+        if self.embedding_worker_config.forward_buffer_size == 0 {
+            problems.push(ConfigProblem::new(
+                "embedding_worker_config.forward_buffer_size",
+                "must be > 0",
+            ));
+        }
+        if self.common_config.checkpointing_config.num_workers == 0 {
+            problems.push(ConfigProblem::new(
+                "common_config.checkpointing_config.num_workers",
+                "must be > 0",
+            ));
+        }
+        problems
+    }
+
     pub fn set_configures(
-        file_path: &PathBuf,
+        self,
         port: u16,
         replica_index: usize,
         replica_size: usize,
     ) -> Result<(), PersiaGlobalConfigError> {
-        if !file_path.is_file() {
-            tracing::error!("global config yaml file NOT found");
-            std::thread::sleep(std::time::Duration::from_secs(120));
-            panic!("global config yaml file NOT found")
-        }
-
-        let global_config: PersiaGlobalConfig = serde_yaml::from_reader(
-            std::fs::File::open(file_path).expect("cannot read config file"),
-        )
-        .expect("cannot parse config file");
+        let global_config = self;
 
         tracing::info!(
             "setting embedding_parameter_server_config {:?}",
@@ -525,14 +967,29 @@ impl PersiaGlobalConfig {
     }
 }
 
-#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "self::serde")]
+pub enum StorageDtype {
+    F32,
+    F16,
+    // row-wise 8-bit quantization, each embedding keeps its own scale and bias in f32
+    Int8,
+}
+
+impl Default for StorageDtype {
+    fn default() -> Self {
+        Self::F32
+    }
+}
+
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub struct HashStackConfig {
     pub hash_stack_rounds: usize,
     pub embedding_size: usize,
 }
 
-#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone)]
+#[derive(Deserialize, Serialize, Readable, Writable, Debug, Clone, PartialEq)]
 #[serde(crate = "self::serde")]
 pub struct SlotConfig {
     pub dim: usize,
@@ -547,6 +1004,31 @@ pub struct SlotConfig {
     // index_prefix: different prefix add to index of different features, to prevent bucket conflict for each feature embedding.
     #[serde(default = "get_zero")]
     pub index_prefix: u64,
+    // dtype of the embedding weights on embedding server, the optimizer states are always f32.
+    // Slots of the same feature group must share the same storage dtype.
+    #[serde(default = "get_default_storage_dtype")]
+    pub storage_dtype: StorageDtype,
+    // overrides of the hyperparameters of PersiaEmbeddingModelHyperparameters.
+    // Slots of the same feature group must share the same overrides.
+    #[serde(default = "get_none")]
+    pub initialization_method: Option<InitializationMethod>,
+    #[serde(default = "get_none")]
+    pub admit_probability: Option<f32>,
+    #[serde(default = "get_none")]
+    pub weight_bound: Option<f32>,
+    #[serde(default = "get_none")]
+    pub gradient_clipping: Option<GradientClipping>,
+}
+
+impl SlotConfig {
+    pub fn hyperparameters(&self) -> FeatureGroupHyperparameters {
+        FeatureGroupHyperparameters {
+            initialization_method: self.initialization_method.clone(),
+            admit_probability: self.admit_probability,
+            weight_bound: self.weight_bound,
+            gradient_clipping: self.gradient_clipping,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Readable, Writable, Clone)]
@@ -557,26 +1039,153 @@ pub struct EmbeddingConfig {
     pub slots_config: indexmap::IndexMap<String, SlotConfig>,
     #[serde(default = "get_default_feature_groups")]
     pub feature_groups: indexmap::IndexMap<String, Vec<String>>,
+    // max number of embedding entries of a feature group on each embedding server. A group
+    // with capacity is evicted on its own, apart from the capacity shared by the other groups.
+    #[serde(default = "get_default_feature_group_capacity")]
+    pub feature_group_capacity: HashMap<String, usize>,
 }
 
 impl EmbeddingConfig {
-    pub fn set(file_path: &PathBuf) -> Result<(), PersiaGlobalConfigError> {
-        if !file_path.is_file() {
-            tracing::error!("embedding config yaml file NOT found");
-            std::thread::sleep(std::time::Duration::from_secs(120));
-            panic!("embedding config yaml file NOT found")
+    /// Reads an embedding config file and assigns the index prefixes of its slots, unknown keys
+    /// and invalid values are reported all at once.
+    pub fn load(file_path: &Path) -> Result<Self, PersiaGlobalConfigError> {
+        Self::from_yaml_str(read_config_file(file_path)?.as_str())
+    }
+
+    pub fn from_yaml_str(content: &str) -> Result<Self, PersiaGlobalConfigError> {
+        let (config, mut problems): (Self, _) = parse_yaml_config(content)?;
+        problems.extend(config.validate());
+        into_config_result(config, problems).and_then(parse_embedding_config)
+    }
+
+    /// Validates an embedding config as read from yaml, before the index prefixes are assigned
+    /// by `parse_embedding_config`.
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+
+        let mut slot_name_to_feature_group: HashMap<&str, &str> = HashMap::new();
+        for (feature_group_name, slot_names) in self.feature_groups.iter() {
+            for slot_name in slot_names {
+                if !self.slots_config.contains_key(slot_name) {
+                    problems.push(ConfigProblem::new(
+                        format!("feature_groups.{}", feature_group_name),
+                        format!("slot {} not found in slots_config", slot_name),
+                    ));
+                }
+                if let Some(other) =
+                    slot_name_to_feature_group.insert(slot_name, feature_group_name)
+                {
+                    problems.push(ConfigProblem::new(
+                        format!("feature_groups.{}", feature_group_name),
+                        format!("slot {} is already in feature group {}", slot_name, other),
+                    ));
+                }
+            }
+
+            let slot_configs: Vec<&SlotConfig> = slot_names
+                .iter()
+                .filter_map(|slot_name| self.slots_config.get(slot_name))
+                .collect();
+            if let Some(first) = slot_configs.first() {
+                if !slot_configs
+                    .iter()
+                    .all(|x| x.storage_dtype == first.storage_dtype)
+                {
+                    problems.push(ConfigProblem::new(
+                        format!("feature_groups.{}", feature_group_name),
+                        "slots of the feature group must share the same storage_dtype",
+                    ));
+                }
+                if !slot_configs
+                    .iter()
+                    .all(|x| x.hyperparameters() == first.hyperparameters())
+                {
+                    problems.push(ConfigProblem::new(
+                        format!("feature_groups.{}", feature_group_name),
+                        "slots of the feature group must share the same initialization_method, admit_probability, weight_bound and gradient_clipping",
+                    ));
+                }
+            }
         }
 
-        let embedding_config: EmbeddingConfig = serde_yaml::from_reader(
-            std::fs::File::open(file_path).expect("cannot read config file"),
-        )
-        .expect("cannot parse config file");
+        // slots not in any feature group make up a feature group of their own
+        let mut feature_group_names: HashSet<&str> =
+            self.feature_groups.keys().map(|x| x.as_str()).collect();
+        for (slot_name, slot_config) in self.slots_config.iter() {
+            if !slot_name_to_feature_group.contains_key(slot_name.as_str())
+                && !feature_group_names.insert(slot_name)
+            {
+                problems.push(ConfigProblem::new(
+                    format!("slots_config.{}", slot_name),
+                    "a slot not in any feature group can not share the name of a feature group",
+                ));
+            }
+            if slot_config.dim == 0 {
+                problems.push(ConfigProblem::new(
+                    format!("slots_config.{}.dim", slot_name),
+                    "must be > 0",
+                ));
+            }
+            if slot_config.index_prefix != 0 {
+                problems.push(ConfigProblem::new(
+                    format!("slots_config.{}.index_prefix", slot_name),
+                    "please do not set index_prefix manually",
+                ));
+            }
+            if slot_config.hash_stack_config.hash_stack_rounds > 0
+                && slot_config.hash_stack_config.embedding_size == 0
+            {
+                problems.push(ConfigProblem::new(
+                    format!(
+                        "slots_config.{}.hash_stack_config.embedding_size",
+                        slot_name
+                    ),
+                    "must be > 0 when hash_stack_rounds > 0",
+                ));
+            }
+        }
 
-        let embedding_config = parse_embedding_config(embedding_config);
+        if self.feature_index_prefix_bit == 0 || self.feature_index_prefix_bit >= u64::BITS as usize
+        {
+            problems.push(ConfigProblem::new(
+                "feature_index_prefix_bit",
+                format!("must be within 1 ~ {}", u64::BITS - 1),
+            ));
+        } else if feature_group_names.len() >= 1 << self.feature_index_prefix_bit {
+            problems.push(ConfigProblem::new(
+                "feature_index_prefix_bit",
+                format!(
+                    "index_prefix overflow, {} feature groups do not fit in {} bits, please try a bigger feature_index_prefix_bit",
+                    feature_group_names.len(),
+                    self.feature_index_prefix_bit
+                ),
+            ));
+        }
+
+        for (feature_group_name, capacity) in self.feature_group_capacity.iter() {
+            if !feature_group_names.contains(feature_group_name.as_str()) {
+                problems.push(ConfigProblem::new(
+                    format!("feature_group_capacity.{}", feature_group_name),
+                    "feature group not found",
+                ));
+            }
+            if *capacity == 0 {
+                problems.push(ConfigProblem::new(
+                    format!("feature_group_capacity.{}", feature_group_name),
+                    "must be > 0",
+                ));
+            }
+        }
+
+        problems
+    }
+
+    pub fn set(file_path: &PathBuf) -> Result<(), PersiaGlobalConfigError> {
+        let embedding_config = Self::load(file_path)?;
 
         tracing::info!("setting embedding_config {:?}", embedding_config,);
         PERSIA_EMBEDDING_CONFIG
-            .set(Arc::new(embedding_config))
+            .set(RwLock::new(Arc::new(embedding_config)))
             .map_err(|_| PersiaGlobalConfigError::SetError)?;
 
         Ok(())
@@ -585,66 +1194,473 @@ impl EmbeddingConfig {
     pub fn get() -> Result<Arc<Self>, PersiaGlobalConfigError> {
         let singleton = PERSIA_EMBEDDING_CONFIG.get();
         match singleton {
-            Some(s) => Ok(s.clone()),
+            Some(s) => Ok(s.read().clone()),
             None => Err(PersiaGlobalConfigError::NotReadyError),
         }
     }
 
+    /// Appends the new slots and feature groups of a yaml embedding config to the embedding
+    /// config set before, see `append`. Returns the reloaded embedding config.
+    pub fn reload(content: &str) -> Result<Arc<Self>, PersiaGlobalConfigError> {
+        let (config, problems): (Self, _) = parse_yaml_config(content)?;
+        let config = into_config_result(config, problems)?;
+        let singleton = PERSIA_EMBEDDING_CONFIG
+            .get()
+            .ok_or(PersiaGlobalConfigError::NotReadyError)?;
+
+        let mut current = singleton.write();
+        let reloaded = Arc::new(current.append(config)?);
+        tracing::info!("reloading embedding_config {:?}", reloaded);
+        *current = reloaded.clone();
+        Ok(reloaded)
+    }
+
+    /// Appends the slots and feature groups new to this embedding config from `config`, an
+    /// embedding config as read from yaml. The existing slots and feature groups must be kept
+    /// unchanged in `config`, and must get the same index prefixes from `config` as a restart
+    /// would give them, so that embeddings dumped after the reload load the same after restart.
+    /// New feature groups have to be declared after the implicit single slot feature groups for
+    /// that.
+    pub fn append(&self, config: EmbeddingConfig) -> Result<Self, PersiaGlobalConfigError> {
+        let config = parse_embedding_config(config)?;
+        let mut problems = Vec::new();
+
+        if config.feature_index_prefix_bit != self.feature_index_prefix_bit {
+            problems.push(ConfigProblem::new(
+                "feature_index_prefix_bit",
+                "can not be changed without restart",
+            ));
+        }
+        if config.feature_group_capacity != self.feature_group_capacity {
+            problems.push(ConfigProblem::new(
+                "feature_group_capacity",
+                "can not be changed without restart",
+            ));
+        }
+        for (feature_group_name, slot_names) in self.feature_groups.iter() {
+            if config.feature_groups.get(feature_group_name) != Some(slot_names) {
+                problems.push(ConfigProblem::new(
+                    format!("feature_groups.{}", feature_group_name),
+                    "existing feature group can not be changed or removed without restart",
+                ));
+            } else if config.get_feature_group_index_prefix(feature_group_name)
+                != self.get_feature_group_index_prefix(feature_group_name)
+            {
+                problems.push(ConfigProblem::new(
+                    format!("feature_groups.{}", feature_group_name),
+                    "index prefix of existing feature group can not be changed without restart, \
+                     declare the new feature groups after the existing ones",
+                ));
+            }
+        }
+        for (slot_name, slot_config) in self.slots_config.iter() {
+            let unchanged = config.slots_config.get(slot_name).is_some_and(|x| {
+                SlotConfig {
+                    index_prefix: slot_config.index_prefix,
+                    ..x.clone()
+                } == *slot_config
+            });
+            if !unchanged {
+                problems.push(ConfigProblem::new(
+                    format!("slots_config.{}", slot_name),
+                    "existing slot can not be changed or removed without restart",
+                ));
+            }
+        }
+
+        // the existing part of `config` is the same as this config, index prefixes included
+        into_config_result(config, problems)
+    }
+
     pub fn get_slot_by_feature_name(&self, feature_name: &str) -> &SlotConfig {
         self.slots_config
             .get(feature_name)
             .expect(format!("slot: {} not found", feature_name).as_str())
     }
-}
 
-pub fn parse_embedding_config(config: EmbeddingConfig) -> EmbeddingConfig {
-    let mut config = config;
-    let slots_config = &mut config.slots_config;
-    let feature_groups = &mut config.feature_groups;
-    let mut slot_name_to_feature_group = HashMap::new();
+    /// The index prefix shared by the slots of a feature group, see `parse_embedding_config`.
+    pub fn get_feature_group_index_prefix(&self, feature_group_name: &str) -> Option<u64> {
+        self.feature_groups
+            .get_index_of(feature_group_name)
+            .map(|feature_group_index| {
+                (feature_group_index as u64 + 1)
+                    << (u64::BITS - self.feature_index_prefix_bit as u32)
+            })
+    }
 
-    feature_groups
-        .iter()
-        .for_each(|(feature_group_name, slot_names)| {
-            slot_names.iter().for_each(|slot_name| {
-                slot_name_to_feature_group.insert(slot_name.clone(), feature_group_name.clone());
+    /// Capacity of the feature groups configured in `feature_group_capacity`, keyed by the
+    /// index prefix of the group.
+    pub fn get_feature_group_capacity_by_index_prefix(&self) -> HashMap<u64, usize> {
+        self.feature_group_capacity
+            .iter()
+            .filter_map(|(feature_group_name, capacity)| {
+                self.get_feature_group_index_prefix(feature_group_name)
+                    .map(|index_prefix| (index_prefix, *capacity))
             })
-        });
+            .collect()
+    }
+
+    // the feature group a sign belongs to, see `get_feature_group_index_prefix`
+    fn get_feature_group_by_sign(&self, sign: u64) -> Option<(&String, &Vec<String>)> {
+        let feature_group_index =
+            (sign >> (u64::BITS - self.feature_index_prefix_bit as u32)) as usize;
+        feature_group_index
+            .checked_sub(1)
+            .and_then(|idx| self.feature_groups.get_index(idx))
+    }
+
+    // a slot of the feature group a sign belongs to, the slots of a feature group share the
+    // configs checked in `parse_embedding_config`
+    fn get_slot_by_sign(&self, sign: u64) -> Option<&SlotConfig> {
+        self.get_feature_group_by_sign(sign)
+            .and_then(|(_, slot_names)| slot_names.first())
+            .and_then(|slot_name| self.slots_config.get(slot_name))
+    }
+
+    /// Name of the feature group a sign belongs to.
+    pub fn get_feature_group_name_by_sign(&self, sign: u64) -> Option<&str> {
+        self.get_feature_group_by_sign(sign)
+            .map(|(feature_group_name, _)| feature_group_name.as_str())
+    }
+
+    /// Storage dtype of the feature group a sign belongs to.
+    pub fn get_storage_dtype_by_sign(&self, sign: u64) -> StorageDtype {
+        self.get_slot_by_sign(sign)
+            .map_or(StorageDtype::default(), |slot| slot.storage_dtype)
+    }
+
+    /// Hyperparameter overrides in the slot configs, keyed by feature group index prefix.
+    /// Feature groups without any override are left out.
+    pub fn get_slot_hyperparameters_by_index_prefix(
+        &self,
+    ) -> HashMap<u64, FeatureGroupHyperparameters> {
+        self.feature_groups
+            .iter()
+            .filter_map(|(feature_group_name, slot_names)| {
+                let hyperparameters = slot_names
+                    .first()
+                    .and_then(|slot_name| self.slots_config.get(slot_name))?
+                    .hyperparameters();
+                if hyperparameters.is_empty() {
+                    return None;
+                }
+                self.get_feature_group_index_prefix(feature_group_name)
+                    .map(|index_prefix| (index_prefix, hyperparameters))
+            })
+            .collect()
+    }
+
+    /// Mask selecting the feature group index prefix bits of a sign.
+    pub fn feature_index_prefix_mask(&self) -> u64 {
+        !((1u64 << (u64::BITS - self.feature_index_prefix_bit as u32)) - 1)
+    }
+}
+
+/// Validates an embedding config, then puts the slots not in any feature group into a feature
+/// group of their own and assigns the index prefixes of the feature groups to their slots.
+pub fn parse_embedding_config(
+    config: EmbeddingConfig,
+) -> Result<EmbeddingConfig, PersiaGlobalConfigError> {
+    let problems = config.validate();
+    let mut config = into_config_result(config, problems)?;
+    let slots_config = &config.slots_config;
+    let feature_groups = &mut config.feature_groups;
+    let grouped_slot_names: HashSet<String> = feature_groups.values().flatten().cloned().collect();
 
     slots_config.iter().for_each(|(slot_name, _)| {
-        if !slot_name_to_feature_group.contains_key(slot_name) {
-            let res = feature_groups.insert(slot_name.clone(), vec![slot_name.clone()]);
-            if res.is_some() {
-                panic!("a slot name can not same with feature group name");
-            }
-            slot_name_to_feature_group.insert(slot_name.clone(), slot_name.clone());
+        if !grouped_slot_names.contains(slot_name) {
+            feature_groups.insert(slot_name.clone(), vec![slot_name.clone()]);
         }
     });
 
-    assert_ne!(
-        config.feature_index_prefix_bit, 0,
-        "feature_index_prefix_bit must > 0"
-    );
+    assign_index_prefixes(&mut config);
+    Ok(config)
+}
+
+// assigns the index prefix of its feature group to each slot, all slots must be in a feature group
+fn assign_index_prefixes(config: &mut EmbeddingConfig) {
     let feature_prefix_bias = u64::BITS - config.feature_index_prefix_bit as u32;
-    slots_config
-        .iter_mut()
-        .for_each(|(slot_name, slot_config)| {
+    for (feature_group_index, (_, slot_names)) in config.feature_groups.iter().enumerate() {
+        for slot_name in slot_names {
+            if let Some(slot_config) = config.slots_config.get_mut(slot_name) {
+                slot_config.index_prefix = (feature_group_index as u64 + 1) << feature_prefix_bias;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod config_validation_tests {
+    use super::*;
+
+    fn problem_paths(result: Result<impl std::fmt::Debug, PersiaGlobalConfigError>) -> Vec<String> {
+        match result {
+            Err(PersiaGlobalConfigError::InvalidConfigError(problems)) => {
+                problems.into_iter().map(|x| x.path).collect()
+            }
+            other => panic!("expect invalid config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_embedding_config() {
+        let config = EmbeddingConfig::from_yaml_str(
+            r#"
+feature_index_prefix_bit: 8
+slots_config:
+  age:
+    dim: 8
+  gender:
+    dim: 8
+  country:
+    dim: 16
+feature_groups:
+  user: [age, gender]
+"#,
+        )
+        .unwrap();
+
+        let user_prefix = config.get_feature_group_index_prefix("user").unwrap();
+        assert_eq!(user_prefix, 1 << 56);
+        assert_eq!(
+            config.get_slot_by_feature_name("age").index_prefix,
+            user_prefix
+        );
+        assert_eq!(
+            config.get_slot_by_feature_name("gender").index_prefix,
+            user_prefix
+        );
+        assert_eq!(
+            config.get_slot_by_feature_name("country").index_prefix,
+            2 << 56
+        );
+    }
+
+    #[test]
+    fn test_embedding_config_problems() {
+        let result = EmbeddingConfig::from_yaml_str(
+            r#"
+feature_index_prefix_bit: 8
+slots_config:
+  age:
+    dim: 0
+    index_prefix: 3
+  gender:
+    dim: 8
+    embeding_summation: false
+  user:
+    dim: 8
+feature_groups:
+  user: [age, gender, income]
+  other: [gender]
+feature_group_capacity:
+  item: 100
+"#,
+        );
+
+        assert_eq!(
+            problem_paths(result),
+            vec![
+                "slots_config.gender.embeding_summation",
+                "feature_groups.user",
+                "feature_groups.other",
+                "slots_config.age.dim",
+                "slots_config.age.index_prefix",
+                "slots_config.user",
+                "feature_group_capacity.item",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_index_prefix_overflow() {
+        let result = EmbeddingConfig::from_yaml_str(
+            r#"
+feature_index_prefix_bit: 1
+slots_config:
+  age:
+    dim: 8
+  gender:
+    dim: 8
+"#,
+        );
+
+        assert_eq!(problem_paths(result), vec!["feature_index_prefix_bit"]);
+    }
+
+    #[test]
+    fn test_append_embedding_config() {
+        let config = EmbeddingConfig::from_yaml_str(
+            r#"
+slots_config:
+  age:
+    dim: 8
+  gender:
+    dim: 8
+feature_groups:
+  user: [age]
+"#,
+        )
+        .unwrap();
+        let raw_config =
+            |content: &str| -> EmbeddingConfig { serde_yaml::from_str(content).unwrap() };
+
+        let content = r#"
+slots_config:
+  age:
+    dim: 8
+  gender:
+    dim: 8
+  item:
+    dim: 16
+  shop:
+    dim: 16
+feature_groups:
+  user: [age]
+  gender: [gender]
+  goods: [item, shop]
+"#;
+        let appended = config.append(raw_config(content)).unwrap();
+
+        // the reloaded config assigns the same index prefixes as a restart
+        let restarted = EmbeddingConfig::from_yaml_str(content).unwrap();
+        assert_eq!(
+            appended.feature_groups.keys().collect::<Vec<_>>(),
+            vec!["user", "gender", "goods"]
+        );
+        for slot_name in ["age", "gender"] {
+            assert_eq!(
+                appended.get_slot_by_feature_name(slot_name),
+                config.get_slot_by_feature_name(slot_name)
+            );
+        }
+        for slot_name in ["age", "gender", "item", "shop"] {
             assert_eq!(
-                slot_config.index_prefix, 0,
-                "please do not set index_prefix manually"
+                appended.get_slot_by_feature_name(slot_name),
+                restarted.get_slot_by_feature_name(slot_name)
             );
-            let feature_group_name = slot_name_to_feature_group
-                .get(slot_name)
-                .expect("feature group not found");
-            let feature_group_index = feature_groups
-                .get_index_of(feature_group_name)
-                .expect("feature group not found");
-            slot_config.index_prefix = num_traits::CheckedShl::checked_shl(
-                &(feature_group_index as u64 + 1),
-                feature_prefix_bias,
+        }
+        assert_eq!(
+            appended.get_slot_by_feature_name("item").index_prefix,
+            3 << 56
+        );
+
+        // the new feature group would go before the implicit feature group of gender on restart
+        let result = config.append(raw_config(
+            r#"
+slots_config:
+  age:
+    dim: 8
+  gender:
+    dim: 8
+  item:
+    dim: 16
+feature_groups:
+  user: [age]
+  goods: [item]
+"#,
+        ));
+        assert_eq!(problem_paths(result), vec!["feature_groups.gender"]);
+
+        let result = config.append(raw_config(
+            r#"
+slots_config:
+  age:
+    dim: 16
+  gender:
+    dim: 8
+feature_groups:
+  user: [age, gender]
+"#,
+        ));
+        assert_eq!(
+            problem_paths(result),
+            vec![
+                "feature_groups.user",
+                "feature_groups.gender",
+                "slots_config.age"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_global_config_problems() {
+        let result = PersiaGlobalConfig::from_yaml_str(
+            r#"
+common_config:
+  metrics_config:
+    push_interval_sec: 10
+embedding_parameter_server_config:
+  capacity: 0
+  ttl_sec: 3600
+  ttl_steps: 1000
+"#,
+        );
+
+        assert_eq!(
+            problem_paths(result),
+            vec![
+                "common_config.metrics_config.push_interval_sec",
+                "embedding_parameter_server_config.capacity",
+                "embedding_parameter_server_config.ttl_steps",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_global_config_overrides() {
+        let content = r#"
+embedding_parameter_server_config:
+  capacity: 1000
+  num_hashmap_internal_shards: 4
+"#;
+        let overrides = vec![
+            ConfigOverride::from_env_var(
+                "PERSIA__EMBEDDING_PARAMETER_SERVER_CONFIG__CAPACITY",
+                "2000",
+            )
+            .unwrap(),
+            ConfigOverride::from_env_var(
+                "PERSIA__EMBEDDING_WORKER_CONFIG__FORWARD_BUFFER_SIZE",
+                "500",
             )
-            .expect("slot index_prefix overflow, please try a bigger feature_index_prefix_bit");
-        });
+            .unwrap(),
+            "embedding_parameter_server_config.capacity=3000"
+                .parse()
+                .unwrap(),
+        ];
+        assert_eq!(
+            ConfigOverride::from_env_var("PERSIA_GLOBAL_CONFIG", ""),
+            None
+        );
 
-    config
+        let config = PersiaGlobalConfig::from_yaml_str_with_overrides(content, &overrides).unwrap();
+        assert_eq!(config.embedding_parameter_server_config.capacity, 3000);
+        assert_eq!(
+            config
+                .embedding_parameter_server_config
+                .num_hashmap_internal_shards,
+            4
+        );
+        assert_eq!(config.embedding_worker_config.forward_buffer_size, 500);
+
+        let overrides: Vec<ConfigOverride> = vec![
+            "embedding_parameter_server_config.capacity.max=1"
+                .parse()
+                .unwrap(),
+            "embedding_worker_config.forward_buffer_sizes=1"
+                .parse()
+                .unwrap(),
+        ];
+        assert_eq!(
+            problem_paths(PersiaGlobalConfig::from_yaml_str_with_overrides(
+                content, &overrides
+            )),
+            vec![
+                "embedding_worker_config.forward_buffer_sizes",
+                "embedding_parameter_server_config.capacity.max",
+            ]
+        );
+        assert!("embedding_worker_config".parse::<ConfigOverride>().is_err());
+    }
 }
@@ -663,9 +663,9 @@ impl<T> ArrayLinkedList<T> {
 
         self.count -= 1;
         if self.free_index > 0 {
-            self.elements[self.free_index as usize - 1].prev_index = index;
+            self.elements[self.free_index as usize - 1].prev_index = index + 1;
         }
-        self.free_index = index;
+        self.free_index = index + 1;
         Some(data.unwrap_or_else(|| unsafe { hint::unreachable_unchecked() }))
     }
 
@@ -701,9 +701,9 @@ impl<T> ArrayLinkedList<T> {
 
         self.count -= 1;
         if self.free_index > 0 {
-            self.elements[self.free_index as usize - 1].prev_index = index;
+            self.elements[self.free_index as usize - 1].prev_index = index + 1;
         }
-        self.free_index = index;
+        self.free_index = index + 1;
         Some(data.unwrap_or_else(|| unsafe { hint::unreachable_unchecked() }))
     }
 
@@ -0,0 +1,185 @@
+use std::borrow::Cow;
+
+use crate::emb_entry::HashMapEmbeddingEntry;
+use crate::eviction_map::EvictionMap;
+
+/// Eviction bookkeeping of an entry, carried over when resharding moves the entry to the
+/// backend of another internal shard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EvictionMeta {
+    pub last_access: u64,
+    // access count under LFU, region of the entry under window TinyLFU, unused under LRU
+    pub tag: u32,
+    // estimated access frequency of the sign under window TinyLFU
+    pub frequency: u8,
+}
+
+/// Storage of the embedding entries of one internal shard of `PersiaEmbeddingHolder`.
+///
+/// A backend is always accessed under the lock of its shard, so implementations need no
+/// synchronization of their own.
+pub trait EmbeddingShardBackend: Send + Sync {
+    /// Looks up an entry without updating its recency.
+    fn get(&self, sign: &u64) -> Option<Cow<'_, HashMapEmbeddingEntry>>;
+
+    fn get_mut(&mut self, sign: &u64) -> Option<&mut HashMapEmbeddingEntry>;
+
+    /// Looks up an entry and marks it as accessed for eviction.
+    fn get_refresh(&mut self, sign: &u64) -> Option<&HashMapEmbeddingEntry>;
+
+    /// Inserts `entry` under `sign`, returns the replaced entry and the entries dropped from
+    /// the backend to make room for it.
+    fn insert(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+    ) -> (Option<HashMapEmbeddingEntry>, Vec<HashMapEmbeddingEntry>);
+
+    fn remove(&mut self, sign: &u64) -> Option<HashMapEmbeddingEntry>;
+
+    /// Iterates over all entries of the backend in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, HashMapEmbeddingEntry>> + '_>;
+
+    /// Iterates over the signs of all entries of the backend in no particular order, without
+    /// reading the entries.
+    fn signs(&self) -> Box<dyn Iterator<Item = u64> + '_>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self);
+
+    /// Removes all entries, the first to be evicted first, along with their eviction
+    /// bookkeeping. Backends keeping none return `None` for it.
+    fn drain_with_meta(&mut self) -> Vec<(HashMapEmbeddingEntry, Option<EvictionMeta>)> {
+        let entries = self
+            .iter()
+            .map(|entry| (entry.into_owned(), None))
+            .collect();
+        self.clear();
+        entries
+    }
+
+    /// Inserts an entry drained from another backend by `drain_with_meta`, keeping its eviction
+    /// bookkeeping. Returns the entries dropped from the backend to make room for it.
+    fn insert_with_meta(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+        _meta: Option<EvictionMeta>,
+    ) -> Vec<HashMapEmbeddingEntry> {
+        self.insert(sign, entry).1
+    }
+
+    /// Records a sighting of a sign not admitted yet, returns how many times it has been seen
+    /// recently. Backends without an admission filter admit every sign at first sight.
+    fn record_sighting(&mut self, _sign: &u64) -> u8 {
+        u8::MAX
+    }
+
+    /// Removes the entries not accessed within `ttl` on the expiry clock of the backend,
+    /// checking at most `max_scanned` entries in memory. Returns the number of them.
+    fn evict_expired(&mut self, _ttl: u64, _max_scanned: usize) -> usize {
+        0
+    }
+
+    /// Compacts at most `max_records` records of the disk tier, returns whether the
+    /// compaction is still in progress. Backends without a disk tier have nothing to compact.
+    fn compact_disk(&mut self, _max_records: usize) -> bool {
+        false
+    }
+
+    fn capacity(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Bytes of memory held by the entries of the backend.
+    fn memory_bytes(&self) -> usize {
+        0
+    }
+
+    /// Bytes of the disk tier files of the backend.
+    fn disk_bytes(&self) -> u64 {
+        0
+    }
+}
+
+impl EmbeddingShardBackend for EvictionMap<u64, HashMapEmbeddingEntry> {
+    fn get(&self, sign: &u64) -> Option<Cow<'_, HashMapEmbeddingEntry>> {
+        EvictionMap::get(self, sign).map(Cow::Borrowed)
+    }
+
+    fn get_mut(&mut self, sign: &u64) -> Option<&mut HashMapEmbeddingEntry> {
+        EvictionMap::get_mut(self, sign)
+    }
+
+    fn get_refresh(&mut self, sign: &u64) -> Option<&HashMapEmbeddingEntry> {
+        EvictionMap::get_refresh(self, sign)
+    }
+
+    fn insert(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+    ) -> (Option<HashMapEmbeddingEntry>, Vec<HashMapEmbeddingEntry>) {
+        EvictionMap::insert(self, sign, entry)
+    }
+
+    fn remove(&mut self, sign: &u64) -> Option<HashMapEmbeddingEntry> {
+        EvictionMap::remove(self, sign)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, HashMapEmbeddingEntry>> + '_> {
+        Box::new(self.linkedlist.iter().map(Cow::Borrowed))
+    }
+
+    fn signs(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.hashmap.keys().copied())
+    }
+
+    fn len(&self) -> usize {
+        EvictionMap::len(self)
+    }
+
+    fn clear(&mut self) {
+        EvictionMap::clear(self)
+    }
+
+    fn drain_with_meta(&mut self) -> Vec<(HashMapEmbeddingEntry, Option<EvictionMeta>)> {
+        EvictionMap::drain_with_meta(self)
+            .into_iter()
+            .map(|(entry, meta)| (entry, Some(meta)))
+            .collect()
+    }
+
+    fn insert_with_meta(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+        meta: Option<EvictionMeta>,
+    ) -> Vec<HashMapEmbeddingEntry> {
+        match meta {
+            Some(meta) => EvictionMap::insert_with_meta(self, sign, entry, meta),
+            None => EvictionMap::insert(self, sign, entry).1,
+        }
+    }
+
+    fn record_sighting(&mut self, sign: &u64) -> u8 {
+        EvictionMap::record_sighting(self, sign)
+    }
+
+    fn evict_expired(&mut self, ttl: u64, max_scanned: usize) -> usize {
+        EvictionMap::evict_expired(self, ttl, max_scanned).len()
+    }
+
+    fn capacity(&self) -> usize {
+        EvictionMap::capacity(self)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        EvictionMap::memory_bytes(self)
+    }
+}
@@ -0,0 +1,303 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::emb_entry::HashMapEmbeddingEntry;
+use crate::shard_table::{ShardTable, WalkPin};
+use crate::sharded::get_index;
+
+/// The internal shards of `PersiaEmbeddingHolder` pinned against resharding, so that the shard
+/// indices stay valid and every entry is walked exactly once. Resharding waits for all pins to
+/// be dropped.
+#[derive(Clone)]
+pub struct PinnedShards {
+    _pin: Arc<WalkPin>,
+    table: Arc<ShardTable>,
+}
+
+impl PinnedShards {
+    pub(crate) fn new(pin: WalkPin, table: Arc<ShardTable>) -> Self {
+        Self {
+            _pin: Arc::new(pin),
+            table,
+        }
+    }
+
+    pub fn num_internal_shards(&self) -> usize {
+        self.table.len()
+    }
+
+    // signs of all entries of an internal shard in ascending order
+    fn shard_signs(&self, internal_shard_idx: usize) -> Vec<u64> {
+        let mut signs: Vec<u64> = self
+            .table
+            .shard_by_index(internal_shard_idx)
+            .read()
+            .signs()
+            .collect();
+        signs.sort_unstable();
+        signs
+    }
+
+    // owned copies of all entries of an internal shard, taken under one read lock
+    fn snapshot_shard(&self, internal_shard_idx: usize) -> Vec<HashMapEmbeddingEntry> {
+        let shard = self.table.shard_by_index(internal_shard_idx).read();
+        let mut entries = Vec::with_capacity(shard.len());
+        entries.extend(shard.iter().map(Cow::into_owned));
+        entries
+    }
+
+    // appends owned copies of the entries of `signs` in an internal shard to `entries`
+    fn copy_entries(
+        &self,
+        internal_shard_idx: usize,
+        signs: &[u64],
+        entries: &mut Vec<HashMapEmbeddingEntry>,
+    ) {
+        let shard = self.table.shard_by_index(internal_shard_idx).read();
+        entries.extend(
+            signs
+                .iter()
+                .filter_map(|sign| shard.get(sign).map(Cow::into_owned)),
+        );
+    }
+
+    /// Walks all entries in batches of at most `batch_size` entries, see `EmbeddingCursor`.
+    pub fn cursor(&self, batch_size: usize) -> EmbeddingCursor {
+        EmbeddingCursor::new(
+            self.clone(),
+            0..self.num_internal_shards(),
+            batch_size,
+            false,
+        )
+    }
+
+    /// Walks the entries of an internal shard in batches of at most `batch_size` entries, see
+    /// `EmbeddingCursor`.
+    pub fn shard_cursor(&self, internal_shard_idx: usize, batch_size: usize) -> EmbeddingCursor {
+        EmbeddingCursor::new(
+            self.clone(),
+            internal_shard_idx..internal_shard_idx + 1,
+            batch_size,
+            false,
+        )
+    }
+
+    /// Walks a snapshot of the entries of an internal shard in batches of at most `batch_size`
+    /// entries. The snapshot is copied under a single read lock of the shard, so it is a
+    /// consistent view of the shard as needed for checkpoints, but writers of the shard are
+    /// blocked while it is copied.
+    pub fn shard_snapshot_cursor(
+        &self,
+        internal_shard_idx: usize,
+        batch_size: usize,
+    ) -> EmbeddingCursor {
+        EmbeddingCursor::new(
+            self.clone(),
+            internal_shard_idx..internal_shard_idx + 1,
+            batch_size,
+            true,
+        )
+    }
+
+    /// Owned copies of the entries of `signs`, missing signs are skipped. The signs are grouped
+    /// by internal shard, so that each shard is read under one lock and viewed consistently.
+    pub fn get_entries(&self, signs: &[u64]) -> Vec<HashMapEmbeddingEntry> {
+        let num_internal_shards = self.num_internal_shards();
+        let mut signs_by_shard = vec![Vec::new(); num_internal_shards];
+        signs
+            .iter()
+            .for_each(|sign| signs_by_shard[get_index(sign, num_internal_shards)].push(*sign));
+
+        let mut entries = Vec::with_capacity(signs.len());
+        signs_by_shard
+            .iter()
+            .enumerate()
+            .filter(|(_, shard_signs)| !shard_signs.is_empty())
+            .for_each(|(idx, shard_signs)| self.copy_entries(idx, shard_signs, &mut entries));
+        entries
+    }
+}
+
+/// A batch of owned entries, all of them from the same internal shard.
+pub struct EmbeddingBatch {
+    pub internal_shard_idx: usize,
+    pub entries: Vec<HashMapEmbeddingEntry>,
+}
+
+/// Walks the entries of a range of internal shards in batches. Batches never span two shards.
+///
+/// By default the signs of a shard are listed when the cursor reaches it, and each batch
+/// copies the entries of the next range of them in ascending order under a read lock of its
+/// own, so writers are only blocked for the time of copying one batch. Such a walk is not a
+/// consistent view of the shard: entries updated between two batches are copied at different
+/// times, entries inserted after the signs are listed are not walked and removed ones are
+/// skipped. A snapshot cursor copies the whole shard under one read lock instead, see
+/// `PinnedShards::shard_snapshot_cursor`.
+pub struct EmbeddingCursor {
+    shards: PinnedShards,
+    shard_indices: Range<usize>,
+    batch_size: usize,
+    snapshot: bool,
+    current_shard_idx: usize,
+    // signs of the current shard not copied yet
+    signs: std::vec::IntoIter<u64>,
+    // entries of the snapshot of the current shard not returned yet
+    copied: std::vec::IntoIter<HashMapEmbeddingEntry>,
+}
+
+impl EmbeddingCursor {
+    fn new(
+        shards: PinnedShards,
+        shard_indices: Range<usize>,
+        batch_size: usize,
+        snapshot: bool,
+    ) -> Self {
+        assert!(batch_size > 0, "batch size of embedding cursor must > 0");
+        Self {
+            shards,
+            current_shard_idx: shard_indices.start,
+            shard_indices,
+            batch_size,
+            snapshot,
+            signs: Vec::new().into_iter(),
+            copied: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl Iterator for EmbeddingCursor {
+    type Item = EmbeddingBatch;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut entries = Vec::with_capacity(self.batch_size);
+        // a batch whose entries have all been removed since listing the signs is skipped
+        while entries.is_empty() {
+            if self.copied.len() > 0 {
+                entries.extend(self.copied.by_ref().take(self.batch_size));
+            } else if self.signs.len() > 0 {
+                let signs: Vec<_> = self.signs.by_ref().take(self.batch_size).collect();
+                self.shards
+                    .copy_entries(self.current_shard_idx, &signs, &mut entries);
+            } else {
+                self.current_shard_idx = self.shard_indices.next()?;
+                match self.snapshot {
+                    true => {
+                        self.copied = self
+                            .shards
+                            .snapshot_shard(self.current_shard_idx)
+                            .into_iter()
+                    }
+                    false => {
+                        self.signs = self.shards.shard_signs(self.current_shard_idx).into_iter()
+                    }
+                }
+            }
+        }
+        Some(EmbeddingBatch {
+            internal_shard_idx: self.current_shard_idx,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod cursor_tests {
+    use super::*;
+    use crate::backend::EmbeddingShardBackend;
+    use crate::eviction_map::EvictionMap;
+    use crate::shard_table::ShardRouter;
+    use persia_embedding_config::InitializationMethod;
+
+    #[test]
+    fn test_cursor() {
+        let backends = (0..3)
+            .map(|_| {
+                let backend: Box<dyn EmbeddingShardBackend> =
+                    Box::new(EvictionMap::with_capacity(100));
+                backend
+            })
+            .collect();
+        let router = Arc::new(ShardRouter::new(ShardTable::new(0, backends)));
+        let initialization = InitializationMethod::default();
+        // the shard of a sign varies between runs, shard 1 needs at least 6 entries below
+        let mut num_signs = 0;
+        let mut num_shard_signs = 0;
+        while num_signs < 20 || num_shard_signs < 6 {
+            let sign = num_signs;
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, sign, sign);
+            router.write(&sign).insert(sign, entry);
+            num_signs += 1;
+            if get_index(&sign, 3) == 1 {
+                num_shard_signs += 1;
+            }
+        }
+        let (pin, table) = router.pin();
+        let shards = PinnedShards::new(pin, table.clone());
+
+        let batches: Vec<_> = shards.cursor(4).collect();
+        let mut signs: Vec<u64> = batches
+            .iter()
+            .flat_map(|batch| batch.entries.iter().map(|entry| entry.sign()))
+            .collect();
+        signs.sort_unstable();
+        assert_eq!(signs, (0..num_signs).collect::<Vec<u64>>());
+        assert_eq!(batches.iter().all(|batch| batch.entries.len() <= 4), true);
+        assert_eq!(
+            batches
+                .iter()
+                .all(|batch| batch.entries.iter().all(|entry| {
+                    let shard = table.shard(&entry.sign());
+                    Arc::ptr_eq(shard, table.shard_by_index(batch.internal_shard_idx))
+                })),
+            true
+        );
+
+        let num_entries: usize = shards
+            .shard_cursor(1, 4)
+            .map(|batch| batch.entries.len())
+            .sum();
+        assert_eq!(num_entries, table.shard_by_index(1).read().len());
+
+        let entries = shards.get_entries(&[3, 5, 100]);
+        assert_eq!(entries.len(), 2);
+
+        // the shard is not locked between batches, removed entries are skipped
+        let shard = table.shard_by_index(1).clone();
+        let mut signs: Vec<u64> = shard.read().signs().collect();
+        signs.sort_unstable();
+        let mut cursor = shards.shard_cursor(1, 2);
+        let first: Vec<u64> = cursor
+            .next()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| entry.sign())
+            .collect();
+        assert_eq!(first, signs[..2].to_vec());
+        shard.write().remove(&signs[2]);
+        shard.write().remove(&signs[3]);
+        let rest: Vec<u64> = cursor
+            .flat_map(|batch| batch.entries.into_iter().map(|entry| entry.sign()))
+            .collect();
+        assert_eq!(rest, signs[4..].to_vec());
+
+        // a snapshot is copied at once, entries removed during the walk are still returned
+        let mut signs: Vec<u64> = shard.read().signs().collect();
+        signs.sort_unstable();
+        let mut cursor = shards.shard_snapshot_cursor(1, 1);
+        let mut walked: Vec<u64> = cursor
+            .next()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| entry.sign())
+            .collect();
+        signs.iter().for_each(|sign| {
+            shard.write().remove(sign);
+        });
+        walked.extend(cursor.flat_map(|batch| batch.entries.into_iter().map(|entry| entry.sign())));
+        walked.sort_unstable();
+        assert_eq!(walked, signs);
+    }
+}
@@ -0,0 +1,359 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use persia_embedding_config::StorageDtype;
+use persia_libs::hashbrown::HashMap;
+use persia_speedy::{Readable, Writable};
+
+use crate::emb_entry::HashMapEmbeddingEntry;
+
+// the log is only compacted after it grows beyond this size
+const MIN_COMPACTION_BYTES: u64 = 64 << 20;
+// each record starts with the sign and the length of the serialized entry
+const RECORD_HEADER_BYTES: u64 = 12;
+
+#[derive(Clone, Copy, Debug)]
+struct DiskRecord {
+    // generation of the file the record is in
+    generation: u32,
+    offset: u64,
+    // bytes of the record including its header
+    len: u32,
+    // time on the expiry clock of the memory tier when the entry was written to disk
+    spilled_at: u64,
+    // entries are written in f32 and converted back to their storage dtype when read
+    storage_dtype: StorageDtype,
+}
+
+fn to_io_error<E: std::fmt::Debug>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+fn generation_path(path: &Path, generation: u32) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", generation));
+    PathBuf::from(path)
+}
+
+fn create_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+// a file being compacted, its live records are moved to the file of the current generation
+struct RetiringFile {
+    path: PathBuf,
+    file: File,
+    generation: u32,
+    file_bytes: u64,
+    // offset of the next record to move
+    cursor: u64,
+}
+
+impl Drop for RetiringFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// An append only key value log of embedding entries on local disk.
+///
+/// Only the index is kept in memory. Overwritten and removed records stay in the file as
+/// garbage until the log is compacted. Compaction starts a file of a new generation for
+/// writes and moves the live records of the old file into it a few at a time by
+/// `compact_step`, so that no call holds the log for a rewrite of the whole file. The log is
+/// a cache of the memory tier, so it is truncated on open and removed on drop instead of
+/// being recovered after restart.
+pub struct DiskLog {
+    path: PathBuf,
+    file: File,
+    generation: u32,
+    retiring: Option<RetiringFile>,
+    index: HashMap<u64, DiskRecord>,
+    file_bytes: u64,
+    live_bytes: u64,
+}
+
+impl DiskLog {
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = create_file(&generation_path(&path, 0))?;
+        Ok(Self {
+            path,
+            file,
+            generation: 0,
+            retiring: None,
+            index: HashMap::new(),
+            file_bytes: 0,
+            live_bytes: 0,
+        })
+    }
+
+    pub fn put(&mut self, entry: &HashMapEmbeddingEntry, spilled_at: u64) -> io::Result<()> {
+        let payload = entry.write_to_vec().map_err(to_io_error)?;
+        let mut buffer = Vec::with_capacity(RECORD_HEADER_BYTES as usize + payload.len());
+        buffer.extend_from_slice(&entry.sign().to_le_bytes());
+        buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(payload.as_slice());
+        self.file.write_all_at(buffer.as_slice(), self.file_bytes)?;
+        let record = DiskRecord {
+            generation: self.generation,
+            offset: self.file_bytes,
+            len: buffer.len() as u32,
+            spilled_at,
+            storage_dtype: entry.storage_dtype(),
+        };
+        self.file_bytes += buffer.len() as u64;
+        self.live_bytes += buffer.len() as u64;
+        if let Some(old) = self.index.insert(entry.sign(), record) {
+            self.live_bytes -= old.len as u64;
+        }
+        Ok(())
+    }
+
+    fn read(&self, record: &DiskRecord) -> io::Result<HashMapEmbeddingEntry> {
+        let file = match &self.retiring {
+            Some(retiring) if retiring.generation == record.generation => &retiring.file,
+            _ => &self.file,
+        };
+        let mut buffer = vec![0u8; record.len as usize - RECORD_HEADER_BYTES as usize];
+        file.read_exact_at(buffer.as_mut_slice(), record.offset + RECORD_HEADER_BYTES)?;
+        let mut entry =
+            HashMapEmbeddingEntry::read_from_buffer(buffer.as_slice()).map_err(to_io_error)?;
+        entry.convert_storage_dtype(record.storage_dtype);
+        Ok(entry)
+    }
+
+    pub fn get(&self, sign: &u64) -> io::Result<Option<HashMapEmbeddingEntry>> {
+        match self.index.get(sign) {
+            Some(record) => self.read(record).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the entry from the log and returns it.
+    pub fn take(&mut self, sign: &u64) -> io::Result<Option<HashMapEmbeddingEntry>> {
+        let entry = self.get(sign)?;
+        self.remove(sign);
+        Ok(entry)
+    }
+
+    pub fn remove(&mut self, sign: &u64) -> bool {
+        match self.index.remove(sign) {
+            Some(record) => {
+                self.live_bytes -= record.len as u64;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the entries written to disk before `deadline`, returns the number of them.
+    pub fn evict_spilled_before(&mut self, deadline: u64) -> usize {
+        let num_entries = self.index.len();
+        let mut freed_bytes = 0;
+        self.index.retain(|_, record| {
+            let expired = record.spilled_at < deadline;
+            if expired {
+                freed_bytes += record.len as u64;
+            }
+            !expired
+        });
+        self.live_bytes -= freed_bytes;
+        num_entries - self.index.len()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = io::Result<HashMapEmbeddingEntry>> + '_ {
+        self.index.values().map(move |record| self.read(record))
+    }
+
+    pub fn signs(&self) -> impl Iterator<Item = u64> + '_ {
+        self.index.keys().copied()
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.index.clear();
+        self.retiring = None;
+        self.file.set_len(0)?;
+        self.file_bytes = 0;
+        self.live_bytes = 0;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Bytes of the files of the log, including the file being compacted.
+    pub fn file_bytes(&self) -> u64 {
+        self.file_bytes + self.retiring.as_ref().map_or(0, |x| x.file_bytes)
+    }
+
+    /// Whether most of the log is garbage and no compaction is in progress.
+    pub fn needs_compaction(&self) -> bool {
+        self.retiring.is_none()
+            && self.file_bytes >= MIN_COMPACTION_BYTES
+            && self.file_bytes > self.live_bytes * 2
+    }
+
+    // moves writes to a file of the next generation and retires the current one
+    fn start_compaction(&mut self) -> io::Result<()> {
+        let generation = self.generation.wrapping_add(1);
+        let path = generation_path(&self.path, generation);
+        let file = create_file(&path)?;
+        self.retiring = Some(RetiringFile {
+            path: generation_path(&self.path, self.generation),
+            file: std::mem::replace(&mut self.file, file),
+            generation: self.generation,
+            file_bytes: self.file_bytes,
+            cursor: 0,
+        });
+        self.generation = generation;
+        self.file_bytes = 0;
+        Ok(())
+    }
+
+    /// Moves at most `max_records` records of the file being compacted, starting a compaction
+    /// if the log needs one. Returns whether the compaction is still in progress.
+    ///
+    /// A record is pointed to its new offset only after it has been written there, so the
+    /// index stays valid if an I/O error interrupts the compaction, which resumes from the
+    /// same record on the next call.
+    pub fn compact_step(&mut self, max_records: usize) -> io::Result<bool> {
+        if self.needs_compaction() {
+            self.start_compaction()?;
+        }
+        let retiring = match self.retiring.as_mut() {
+            Some(retiring) => retiring,
+            None => return Ok(false),
+        };
+
+        let mut buffer = Vec::new();
+        let mut num_records = 0;
+        while num_records < max_records && retiring.cursor < retiring.file_bytes {
+            let mut header = [0u8; RECORD_HEADER_BYTES as usize];
+            retiring.file.read_exact_at(&mut header, retiring.cursor)?;
+            let mut sign = [0u8; 8];
+            sign.copy_from_slice(&header[..8]);
+            let mut payload_len = [0u8; 4];
+            payload_len.copy_from_slice(&header[8..]);
+            let len = RECORD_HEADER_BYTES + u32::from_le_bytes(payload_len) as u64;
+
+            if let Some(record) = self.index.get_mut(&u64::from_le_bytes(sign)) {
+                if record.generation == retiring.generation && record.offset == retiring.cursor {
+                    buffer.resize(len as usize, 0);
+                    retiring
+                        .file
+                        .read_exact_at(buffer.as_mut_slice(), retiring.cursor)?;
+                    self.file.write_all_at(buffer.as_slice(), self.file_bytes)?;
+                    record.generation = self.generation;
+                    record.offset = self.file_bytes;
+                    self.file_bytes += len;
+                }
+            }
+            retiring.cursor += len;
+            num_records += 1;
+        }
+
+        if retiring.cursor < retiring.file_bytes {
+            return Ok(true);
+        }
+        self.retiring = None;
+        Ok(false)
+    }
+
+    /// Compacts the whole log at once, dropping all of its garbage.
+    pub fn compact(&mut self) -> io::Result<()> {
+        if self.retiring.is_none() {
+            self.start_compaction()?;
+        }
+        while self.compact_step(usize::MAX)? {}
+        Ok(())
+    }
+}
+
+impl Drop for DiskLog {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(generation_path(&self.path, self.generation));
+    }
+}
+
+#[cfg(test)]
+mod disk_log_tests {
+    use super::*;
+    use persia_embedding_config::InitializationMethod;
+
+    #[test]
+    fn test_put_and_take() {
+        let path = std::env::temp_dir().join(format!("disk_log_test_{}.log", std::process::id()));
+        let mut log = DiskLog::open(path).unwrap();
+        let initialization = InitializationMethod::default();
+
+        for i in 0..10 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            log.put(&entry, i).unwrap();
+        }
+        let overwritten = HashMapEmbeddingEntry::new(&initialization, 8, 16, 100, 3);
+        log.put(&overwritten, 10).unwrap();
+
+        assert_eq!(log.len(), 10);
+        assert_eq!(log.get(&3).unwrap().unwrap().emb(), overwritten.emb());
+        assert_eq!(log.take(&4).unwrap().is_some(), true);
+        assert_eq!(log.get(&4).unwrap().is_none(), true);
+        assert_eq!(log.len(), 9);
+
+        assert_eq!(log.evict_spilled_before(2), 2);
+        assert_eq!(log.len(), 7);
+
+        let file_bytes = log.file_bytes();
+        log.compact().unwrap();
+        assert_eq!(log.file_bytes() < file_bytes, true);
+        assert_eq!(log.entries().count(), 7);
+        assert_eq!(log.get(&3).unwrap().unwrap().emb(), overwritten.emb());
+        assert_eq!(log.get(&9).unwrap().unwrap().sign(), 9);
+    }
+
+    #[test]
+    fn test_compact_step() {
+        let path =
+            std::env::temp_dir().join(format!("disk_log_step_test_{}.log", std::process::id()));
+        let mut log = DiskLog::open(path).unwrap();
+        let initialization = InitializationMethod::default();
+
+        for i in 0..10 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            log.put(&entry, i).unwrap();
+        }
+        log.remove(&0);
+        log.start_compaction().unwrap();
+
+        // entries written and removed during the compaction are kept apart from the moved ones
+        let overwritten = HashMapEmbeddingEntry::new(&initialization, 8, 16, 100, 5);
+        assert_eq!(log.compact_step(3).unwrap(), true);
+        log.put(&overwritten, 10).unwrap();
+        log.remove(&1);
+        assert_eq!(log.get(&2).unwrap().unwrap().sign(), 2);
+        assert_eq!(log.get(&8).unwrap().unwrap().sign(), 8);
+        while log.compact_step(3).unwrap() {}
+
+        assert_eq!(log.len(), 8);
+        assert_eq!(log.entries().count(), 8);
+        assert_eq!(log.get(&1).unwrap().is_none(), true);
+        assert_eq!(log.get(&5).unwrap().unwrap().emb(), overwritten.emb());
+        for i in 2..10 {
+            assert_eq!(log.get(&i).unwrap().unwrap().sign(), i);
+        }
+    }
+}
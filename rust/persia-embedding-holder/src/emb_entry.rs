@@ -1,29 +1,206 @@
+use std::borrow::Cow;
+
 use persia_libs::{
+    half::f16,
     ndarray::Array1,
-    ndarray_rand::rand_distr::{Gamma, Normal, Poisson, Uniform},
+    ndarray_rand::rand_distr::{Distribution, Gamma, Normal, Poisson, Uniform},
     ndarray_rand::RandomExt,
-    rand::prelude::SmallRng,
-    rand::SeedableRng,
+    rand::{self, prelude::SmallRng, Rng, SeedableRng},
     serde::{self, Deserialize, Serialize},
 };
 
-use persia_embedding_config::InitializationMethod;
-use persia_speedy::{Readable, Writable};
+use persia_embedding_config::{InitializationMethod, StorageDtype};
+use persia_speedy::{Context, Readable, Writable};
 
 use crate::eviction_map::EvictionMapValue;
+use crate::slab::Slab;
+
+// number of f32 words taken by an embedding of `dim` stored in `storage_dtype`
+fn emb_storage_len(storage_dtype: StorageDtype, dim: usize) -> usize {
+    match storage_dtype {
+        StorageDtype::F32 => dim,
+        // two f16 per word
+        StorageDtype::F16 => dim.div_ceil(2),
+        // scale and bias, then four u8 per word
+        StorageDtype::Int8 => 2 + dim.div_ceil(4),
+    }
+}
+
+// how values are rounded to the ones representable in a quantized storage dtype
+#[derive(Clone, Copy, PartialEq)]
+enum Rounding {
+    Nearest,
+    // rounds to one of the two neighbouring values with the probability of being closer to
+    // it, so that updates smaller than the spacing of the values are applied in expectation
+    // instead of being rounded away
+    Stochastic,
+}
+
+fn round_to_int<R: Rng>(x: f32, rounding: Rounding, rng: &mut R) -> f32 {
+    match rounding {
+        Rounding::Nearest => x.round(),
+        Rounding::Stochastic => (x + rng.gen::<f32>()).floor(),
+    }
+}
 
-#[derive(Serialize, Deserialize, Readable, Writable, Clone, Debug)]
+fn round_to_f16<R: Rng>(x: f32, rounding: Rounding, rng: &mut R) -> f16 {
+    let nearest = f16::from_f32(x);
+    let y = nearest.to_f32();
+    if rounding == Rounding::Nearest || y == x || !y.is_finite() {
+        return nearest;
+    }
+    // the neighbour of `nearest` on the other side of `x`, f16 is in sign magnitude so the
+    // bits step away from zero when `nearest` is smaller than `x` in magnitude
+    let other = match y.abs() < x.abs() {
+        true => f16::from_bits(nearest.to_bits() + 1),
+        false => f16::from_bits(nearest.to_bits() - 1),
+    };
+    let z = other.to_f32();
+    if z.is_finite() && rng.gen::<f32>() < (x - y) / (z - y) {
+        other
+    } else {
+        nearest
+    }
+}
+
+// packed values are kept as raw bits in the f32 words of the storage
+fn store_emb(storage_dtype: StorageDtype, dst: &mut [f32], emb: &[f32], rounding: Rounding) {
+    let mut rng = rand::thread_rng();
+    match storage_dtype {
+        StorageDtype::F32 => dst.copy_from_slice(emb),
+        StorageDtype::F16 => {
+            dst.iter_mut().zip(emb.chunks(2)).for_each(|(word, pair)| {
+                let bits = pair.iter().enumerate().fold(0u32, |bits, (j, x)| {
+                    let half = round_to_f16(*x, rounding, &mut rng);
+                    bits | (half.to_bits() as u32) << (16 * j)
+                });
+                *word = f32::from_bits(bits);
+            });
+        }
+        StorageDtype::Int8 => {
+            let (min, max) = emb.iter().fold((f32::MAX, f32::MIN), |(min, max), x| {
+                (min.min(*x), max.max(*x))
+            });
+            let scale = if max > min { (max - min) / 255.0 } else { 0.0 };
+            let bias = if emb.is_empty() { 0.0 } else { min };
+            dst[0] = scale;
+            dst[1] = bias;
+            dst[2..]
+                .iter_mut()
+                .zip(emb.chunks(4))
+                .for_each(|(word, quad)| {
+                    let bits = quad.iter().enumerate().fold(0u32, |bits, (j, x)| {
+                        let q = if scale > 0.0 {
+                            round_to_int((x - bias) / scale, rounding, &mut rng).clamp(0.0, 255.0)
+                                as u32
+                        } else {
+                            0
+                        };
+                        bits | q << (8 * j)
+                    });
+                    *word = f32::from_bits(bits);
+                });
+        }
+    }
+}
+
+fn load_emb(storage_dtype: StorageDtype, src: &[f32], dst: &mut [f32]) {
+    match storage_dtype {
+        StorageDtype::F32 => dst.copy_from_slice(src),
+        StorageDtype::F16 => {
+            dst.iter_mut().enumerate().for_each(|(i, x)| {
+                let bits = (src[i / 2].to_bits() >> (16 * (i % 2))) as u16;
+                *x = f16::from_bits(bits).to_f32();
+            });
+        }
+        StorageDtype::Int8 => {
+            let (scale, bias) = (src[0], src[1]);
+            dst.iter_mut().enumerate().for_each(|(i, x)| {
+                let q = (src[2 + i / 4].to_bits() >> (8 * (i % 4))) & 0xff;
+                *x = q as f32 * scale + bias;
+            });
+        }
+    }
+}
+
+/// An embedding entry, serialized with the embedding in f32 whatever its storage dtype. The
+/// format is the one of entries from before quantized storage, so older checkpoints and
+/// incremental updates stay readable. Deserialized entries are in `StorageDtype::F32` and
+/// are converted to the storage dtype of their slot when loaded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(crate = "self::serde")]
+#[serde(into = "SerializedEmbeddingEntry", from = "SerializedEmbeddingEntry")]
 pub struct HashMapEmbeddingEntry {
-    inner: Vec<f32>, // TODO option1: consider using smallvec and slab allocator, and reference that smallvec with &[f32] here to avoid const generics
-    // TODO option2: consider wrap BufferPool (see crates.io) or modify sharded slab to allocate &[f32] here
-    // TODO option3: consider using a object pool of &[f32] with predefined length and all these &[f32] comes from a large continuous Vec. When the object pool is exhausted, create a new large continuous Vec and split it to &[f32]s and add them to the object pool
-    // TODO option4: allocate slices and put them in the slice_arena (see crates.io), then put the slice in the arena into a reusable object pool for consumption
-    // TODO option5: allocate slices in bumpalo_herd allocator with alloc_slice_fill_default, and unsafely converts it to Vec, then put the Vec in a reusable object pool for consumption. In this case we can actually put the whole entry in the pool
+    // embedding in `storage_dtype` followed by the optimizer space in f32, carved from the
+    // slab pool
+    inner: Slab,
+    embedding_dim: usize,
+    sign: u64,
+    storage_dtype: StorageDtype,
+}
+
+// the serialized layout of `HashMapEmbeddingEntry`
+#[derive(Serialize, Deserialize, Readable, Writable)]
+#[serde(crate = "self::serde")]
+struct SerializedEmbeddingEntry {
+    inner: Slab,
     embedding_dim: usize,
     sign: u64,
 }
 
+impl From<HashMapEmbeddingEntry> for SerializedEmbeddingEntry {
+    fn from(mut entry: HashMapEmbeddingEntry) -> Self {
+        entry.convert_storage_dtype(StorageDtype::F32);
+        Self {
+            inner: entry.inner,
+            embedding_dim: entry.embedding_dim,
+            sign: entry.sign,
+        }
+    }
+}
+
+impl From<SerializedEmbeddingEntry> for HashMapEmbeddingEntry {
+    fn from(entry: SerializedEmbeddingEntry) -> Self {
+        Self {
+            inner: entry.inner,
+            embedding_dim: entry.embedding_dim,
+            sign: entry.sign,
+            storage_dtype: StorageDtype::F32,
+        }
+    }
+}
+
+impl<'a, C: Context> Readable<'a, C> for HashMapEmbeddingEntry {
+    #[inline]
+    fn read_from<R: persia_speedy::Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
+        SerializedEmbeddingEntry::read_from(reader).map(Self::from)
+    }
+
+    #[inline]
+    fn minimum_bytes_needed() -> usize {
+        <SerializedEmbeddingEntry as Readable<'a, C>>::minimum_bytes_needed()
+    }
+}
+
+impl<C: Context> Writable<C> for HashMapEmbeddingEntry {
+    // same as writing a `SerializedEmbeddingEntry`, without copying entries stored in f32
+    fn write_to<W: ?Sized + persia_speedy::Writer<C>>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), C::Error> {
+        match self.storage_dtype {
+            StorageDtype::F32 => self.inner.write_to(writer)?,
+            _ => {
+                let mut emb_entry = self.emb().into_owned();
+                emb_entry.extend_from_slice(self.opt());
+                emb_entry.write_to(writer)?
+            }
+        }
+        self.embedding_dim.write_to(writer)?;
+        self.sign.write_to(writer)
+    }
+}
+
 impl HashMapEmbeddingEntry {
     pub fn new(
         initialization_method: &InitializationMethod,
@@ -31,6 +208,24 @@ impl HashMapEmbeddingEntry {
         require_space: usize,
         seed: u64,
         sign: u64,
+    ) -> Self {
+        Self::new_with_storage_dtype(
+            initialization_method,
+            dim,
+            require_space,
+            seed,
+            sign,
+            StorageDtype::F32,
+        )
+    }
+
+    pub fn new_with_storage_dtype(
+        initialization_method: &InitializationMethod,
+        dim: usize,
+        require_space: usize,
+        seed: u64,
+        sign: u64,
+        storage_dtype: StorageDtype,
     ) -> Self {
         let emb = {
             let mut rng = SmallRng::seed_from_u64(seed);
@@ -49,54 +244,79 @@ impl HashMapEmbeddingEntry {
                     Normal::new(x.mean, x.standard_deviation).unwrap(),
                     &mut rng,
                 ),
-                _ => panic!(
-                    "unsupported initialization method for hashmap impl: {:?}",
-                    initialization_method
-                ),
+                InitializationMethod::InverseEmbeddingSizeSqrt => {
+                    let bound = 1.0 / (dim as f32).sqrt();
+                    Array1::random_using((dim,), Uniform::new(-bound, bound), &mut rng)
+                }
+                InitializationMethod::TruncatedNormal(x) => {
+                    let normal = Normal::new(x.mean, x.standard_deviation).unwrap();
+                    let bound = 2.0 * x.standard_deviation;
+                    Array1::from_shape_fn(dim, |_| loop {
+                        let v = normal.sample(&mut rng);
+                        if (v - x.mean).abs() <= bound {
+                            break v;
+                        }
+                    })
+                }
+                InitializationMethod::XavierUniform => {
+                    let bound = (6.0 / (2 * dim) as f32).sqrt();
+                    Array1::random_using((dim,), Uniform::new(-bound, bound), &mut rng)
+                }
             }
         };
 
-        let mut inner = emb.into_raw_vec();
-        if require_space > 0 {
-            inner.resize(inner.len() + require_space, 0.0_f32);
-        }
+        let storage_len = emb_storage_len(storage_dtype, dim);
+        let mut inner = Slab::zeroed(storage_len + require_space);
+        store_emb(
+            storage_dtype,
+            &mut inner[..storage_len],
+            emb.as_slice().unwrap(),
+            Rounding::Nearest,
+        );
         Self {
             inner,
             embedding_dim: dim,
             sign,
+            storage_dtype,
         }
     }
 
     pub fn new_empty(dim: usize, require_space: usize, sign: u64) -> Self {
         Self {
-            inner: vec![0f32; dim + require_space],
+            inner: Slab::zeroed(dim + require_space),
             embedding_dim: dim,
             sign,
+            storage_dtype: StorageDtype::F32,
         }
     }
 
     pub fn from_emb(emb: Vec<f32>, sign: u64) -> Self {
         let embedding_dim = emb.len();
         Self {
-            inner: emb,
+            inner: Slab::from_slice(&emb),
             embedding_dim,
             sign,
+            storage_dtype: StorageDtype::F32,
         }
     }
 
     pub fn from_emb_and_opt(emb: Vec<f32>, opt: &[f32], sign: u64) -> Self {
         let embedding_dim = emb.len();
-        let mut inner = emb;
-        inner.extend_from_slice(opt);
+        let mut inner = Slab::zeroed(embedding_dim + opt.len());
+        inner[..embedding_dim].copy_from_slice(&emb);
+        inner[embedding_dim..].copy_from_slice(opt);
         Self {
             inner,
             embedding_dim,
             sign,
+            storage_dtype: StorageDtype::F32,
         }
     }
 
     pub fn copy_from_other(&mut self, other: &Self) -> bool {
-        if self.embedding_dim() != other.embedding_dim() {
+        if self.embedding_dim() != other.embedding_dim()
+            || self.storage_dtype() != other.storage_dtype()
+        {
             return false;
         }
         for (dst, src) in self.inner.iter_mut().zip(other.inner.iter()) {
@@ -105,12 +325,69 @@ impl HashMapEmbeddingEntry {
         return true;
     }
 
+    /// Converts the embedding to `storage_dtype`, the optimizer space is kept as is.
+    pub fn convert_storage_dtype(&mut self, storage_dtype: StorageDtype) {
+        if self.storage_dtype == storage_dtype {
+            return;
+        }
+        let emb = self.emb().into_owned();
+        let storage_len = emb_storage_len(storage_dtype, self.embedding_dim);
+        let mut inner = Slab::zeroed(storage_len + self.opt().len());
+        store_emb(
+            storage_dtype,
+            &mut inner[..storage_len],
+            &emb,
+            Rounding::Nearest,
+        );
+        inner[storage_len..].copy_from_slice(self.opt());
+        self.inner = inner;
+        self.storage_dtype = storage_dtype;
+    }
+
+    /// Runs `f` on the entry laid out as [embedding, optimizer space] in f32. Quantized
+    /// embeddings are dequantized before and quantized again after `f` with stochastic
+    /// rounding, so that updates too small for the storage dtype still move the embedding
+    /// in expectation.
+    pub fn update_emb_and_opt<F: FnOnce(&mut [f32])>(&mut self, f: F) {
+        if self.storage_dtype == StorageDtype::F32 {
+            return f(&mut self.inner);
+        }
+        let dim = self.embedding_dim;
+        let storage_len = self.emb_storage_len();
+        let mut emb_entry = vec![0f32; dim + self.inner.len() - storage_len];
+        load_emb(
+            self.storage_dtype,
+            &self.inner[..storage_len],
+            &mut emb_entry[..dim],
+        );
+        emb_entry[dim..].copy_from_slice(&self.inner[storage_len..]);
+
+        f(emb_entry.as_mut_slice());
+
+        store_emb(
+            self.storage_dtype,
+            &mut self.inner[..storage_len],
+            &emb_entry[..dim],
+            Rounding::Stochastic,
+        );
+        self.inner[storage_len..].copy_from_slice(&emb_entry[dim..]);
+    }
+
+    /// Resizes the optimizer space to `require_space` zeroed floats, the embedding is kept.
+    pub fn reset_opt(&mut self, require_space: usize) {
+        let storage_len = self.emb_storage_len();
+        let mut inner = Slab::zeroed(storage_len + require_space);
+        inner[..storage_len].copy_from_slice(&self.inner[..storage_len]);
+        self.inner = inner;
+    }
+
+    // raw storage of the entry, the embedding is only f32 under StorageDtype::F32
     pub fn as_mut_emb_entry_slice(&mut self) -> &mut [f32] {
-        self.inner.as_mut_slice()
+        &mut self.inner
     }
 
     pub fn as_emb_entry_slice(&self) -> &[f32] {
-        self.inner.as_slice()
+        &self.inner
     }
 
     pub fn inner_size(&self) -> usize {
@@ -125,11 +402,30 @@ impl HashMapEmbeddingEntry {
         self.embedding_dim
     }
 
-    pub fn emb(&self) -> &[f32] {
-        &self.inner[..self.embedding_dim()]
+    pub fn storage_dtype(&self) -> StorageDtype {
+        self.storage_dtype
     }
 
+    fn emb_storage_len(&self) -> usize {
+        emb_storage_len(self.storage_dtype, self.embedding_dim)
+    }
+
+    /// The embedding in f32, borrowed from the storage unless it is quantized.
+    pub fn emb(&self) -> Cow<'_, [f32]> {
+        let storage = &self.inner[..self.emb_storage_len()];
+        match self.storage_dtype {
+            StorageDtype::F32 => Cow::Borrowed(storage),
+            _ => {
+                let mut emb = vec![0f32; self.embedding_dim];
+                load_emb(self.storage_dtype, storage, &mut emb);
+                Cow::Owned(emb)
+            }
+        }
+    }
+
+    // only available under StorageDtype::F32, use update_emb_and_opt otherwise
     pub fn emb_mut(&mut self) -> &mut [f32] {
+        debug_assert_eq!(self.storage_dtype, StorageDtype::F32);
         let dim = self.embedding_dim();
         &mut self.inner[..dim]
     }
@@ -139,15 +435,17 @@ impl HashMapEmbeddingEntry {
     }
 
     pub fn opt(&self) -> &[f32] {
-        &self.inner[self.embedding_dim()..]
+        &self.inner[self.emb_storage_len()..]
     }
 
     pub fn opt_mut(&mut self) -> &mut [f32] {
-        let dim = self.embedding_dim();
-        &mut self.inner[dim..]
+        let storage_len = self.emb_storage_len();
+        &mut self.inner[storage_len..]
     }
 
+    // only available under StorageDtype::F32, use update_emb_and_opt otherwise
     pub fn emb_and_opt_mut(&mut self) -> (&mut [f32], &mut [f32]) {
+        debug_assert_eq!(self.storage_dtype, StorageDtype::F32);
         let dim = self.embedding_dim();
         self.inner.split_at_mut(dim)
     }
@@ -161,4 +459,138 @@ impl EvictionMapValue<u64> for HashMapEmbeddingEntry {
     fn hashmap_key(&self) -> u64 {
         self.sign
     }
+
+    fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.inner.len() * std::mem::size_of::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod emb_entry_tests {
+    use super::*;
+    use persia_embedding_config::TruncatedNormalInitialization;
+
+    #[test]
+    fn test_initialization() {
+        let dim = 16;
+        let cases = [
+            (InitializationMethod::InverseEmbeddingSizeSqrt, 0.25),
+            (
+                InitializationMethod::TruncatedNormal(TruncatedNormalInitialization::new(
+                    0.0, 0.01,
+                )),
+                0.02,
+            ),
+            (InitializationMethod::XavierUniform, (6.0f32 / 32.0).sqrt()),
+        ];
+        for (initialization, bound) in cases.iter() {
+            for seed in 0..100 {
+                let entry = HashMapEmbeddingEntry::new(initialization, dim, 0, seed, seed);
+                assert_eq!(entry.dim(), dim);
+                assert_eq!(entry.emb().iter().all(|x| x.abs() <= *bound), true);
+            }
+        }
+    }
+
+    #[test]
+    fn test_storage_dtype() {
+        let emb: Vec<f32> = (0..9).map(|x| x as f32 * 0.25 - 1.0).collect();
+        let opt = vec![0.5f32; 18];
+
+        for (storage_dtype, storage_len, tolerance) in
+            [(StorageDtype::F16, 5, 1e-3), (StorageDtype::Int8, 5, 1e-2)]
+        {
+            let mut entry = HashMapEmbeddingEntry::from_emb_and_opt(emb.clone(), &opt, 1);
+            entry.convert_storage_dtype(storage_dtype);
+
+            assert_eq!(entry.inner_size(), storage_len + opt.len());
+            assert_eq!(entry.opt(), opt.as_slice());
+            entry
+                .emb()
+                .iter()
+                .zip(emb.iter())
+                .for_each(|(x, y)| assert_eq!((x - y).abs() < tolerance, true));
+
+            entry.update_emb_and_opt(|emb_entry| {
+                assert_eq!(emb_entry.len(), emb.len() + opt.len());
+                emb_entry.iter_mut().for_each(|x| *x += 1.0);
+            });
+            assert_eq!(entry.opt().iter().all(|x| *x == 1.5), true);
+            entry
+                .emb()
+                .iter()
+                .zip(emb.iter())
+                .for_each(|(x, y)| assert_eq!((x - y - 1.0).abs() < tolerance, true));
+        }
+    }
+
+    #[test]
+    fn test_small_updates() {
+        let emb: Vec<f32> = (0..8).map(|x| x as f32 * 2.0 / 7.0 - 1.0).collect();
+        let (num_updates, lr) = (2000, 1e-4f32);
+
+        // the update of each step is far below the spacing of the quantized values, about 1e-3
+        // around 1.0 for f16 and 2 / 255 for int8 here
+        for storage_dtype in [StorageDtype::F16, StorageDtype::Int8] {
+            let mut entry = HashMapEmbeddingEntry::from_emb(emb.clone(), 1);
+            entry.convert_storage_dtype(storage_dtype);
+            let start = entry.emb().into_owned();
+            for _ in 0..num_updates {
+                entry.update_emb_and_opt(|emb_entry| {
+                    // the min and max of the embedding are kept for the scale of int8
+                    emb_entry[1..7].iter_mut().for_each(|x| *x += lr);
+                });
+            }
+            let moved = entry.emb()[1..7]
+                .iter()
+                .zip(start[1..7].iter())
+                .map(|(x, y)| x - y)
+                .sum::<f32>()
+                / 6.0;
+            assert_eq!((moved - num_updates as f32 * lr).abs() < 0.1, true);
+        }
+    }
+
+    #[test]
+    fn test_serialization() {
+        let emb: Vec<f32> = (0..9).map(|x| x as f32 * 0.25 - 1.0).collect();
+        let opt = vec![0.5f32; 18];
+
+        // entries written before quantized storage are read as f32
+        let old = SerializedEmbeddingEntry {
+            inner: Slab::from_slice(&[emb.as_slice(), opt.as_slice()].concat()),
+            embedding_dim: emb.len(),
+            sign: 7,
+        };
+        let entry = HashMapEmbeddingEntry::read_from_buffer(&old.write_to_vec().unwrap()).unwrap();
+        assert_eq!(entry.storage_dtype(), StorageDtype::F32);
+        assert_eq!(entry.sign(), 7);
+        assert_eq!(entry.emb().as_ref(), emb.as_slice());
+        assert_eq!(entry.opt(), opt.as_slice());
+
+        for storage_dtype in [StorageDtype::F16, StorageDtype::Int8] {
+            let mut entry = HashMapEmbeddingEntry::from_emb_and_opt(emb.clone(), &opt, 7);
+            entry.convert_storage_dtype(storage_dtype);
+            let buffer = entry.write_to_vec().unwrap();
+            assert_eq!(buffer.len(), old.write_to_vec().unwrap().len());
+            let decoded = HashMapEmbeddingEntry::read_from_buffer(&buffer).unwrap();
+            assert_eq!(decoded.storage_dtype(), StorageDtype::F32);
+            assert_eq!(decoded.emb(), entry.emb());
+            assert_eq!(decoded.opt(), opt.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_reset_opt() {
+        let emb: Vec<f32> = (0..8).map(|x| x as f32).collect();
+        let mut entry = HashMapEmbeddingEntry::from_emb_and_opt(emb.clone(), &[0.5f32; 8], 1);
+
+        entry.reset_opt(16);
+        assert_eq!(entry.emb().as_ref(), emb.as_slice());
+        assert_eq!(entry.opt(), vec![0f32; 16].as_slice());
+
+        entry.reset_opt(0);
+        assert_eq!(entry.emb().as_ref(), emb.as_slice());
+        assert_eq!(entry.inner_size(), emb.len());
+    }
 }
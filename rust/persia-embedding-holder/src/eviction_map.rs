@@ -1,11 +1,121 @@
 use persia_libs::hashbrown::HashMap;
-use std::convert::TryFrom;
 use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use persia_embedding_config::EvictionPolicy;
 
 use crate::array_linked_list::ArrayLinkedList;
+use crate::backend::EvictionMeta;
+use crate::frequency_sketch::FrequencySketch;
+
+// size of an entry assumed to size the admission sketch of a map bounded by memory, about a
+// 16 dim f32 embedding with its optimizer state and bookkeeping
+const ESTIMATED_ENTRY_BYTES: usize = 256;
+
+// region tags of the window TinyLFU policy
+const WINDOW_REGION: u32 = 0;
+const MAIN_REGION: u32 = 1;
 
 pub trait EvictionMapValue<K> {
     fn hashmap_key(&self) -> K;
+
+    // bytes held by the value, including its heap allocations
+    fn size_in_bytes(&self) -> usize;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EvictionMapSlot {
+    // index of the value in the linkedlist
+    pub index: u32,
+    // access count under LFU, region of the entry under window TinyLFU, unused under LRU
+    pub tag: u32,
+    // time of the last insert or refresh of the entry on the expiry clock of the map
+    pub last_access: u64,
+}
+
+// Hands the LFU run tail over to the previous entry if the entry at `slot` is the tail of its
+// run, returns the index of the previous entry.
+fn detach_from_run<K, V>(
+    run_tails: &mut HashMap<u32, u32>,
+    hashmap: &HashMap<K, EvictionMapSlot>,
+    linkedlist: &ArrayLinkedList<V>,
+    slot: &EvictionMapSlot,
+) -> Option<u32>
+where
+    K: Hash + Eq + Clone,
+    V: EvictionMapValue<K>,
+{
+    let prev_idx = linkedlist.indices_before(slot.index).next_back();
+    if run_tails.get(&slot.tag) == Some(&slot.index) {
+        let prev_in_run = prev_idx.filter(|prev| {
+            let prev_key = linkedlist[*prev as usize].as_ref().unwrap().hashmap_key();
+            hashmap.get(&prev_key).unwrap().tag == slot.tag
+        });
+        match prev_in_run {
+            Some(prev) => run_tails.insert(slot.tag, prev),
+            None => run_tails.remove(&slot.tag),
+        };
+    }
+    prev_idx
+}
+
+pub(crate) fn current_unix_sec() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// The clock the last access of entries is recorded on, entries expire after a ttl measured
+/// on it.
+#[derive(Clone, Debug)]
+pub enum ExpiryClock {
+    // unix time in seconds
+    UnixSec,
+    // a step counter shared with the owner of the map, which advances it
+    Steps(Arc<AtomicU64>),
+}
+
+impl ExpiryClock {
+    pub fn now(&self) -> u64 {
+        match self {
+            ExpiryClock::UnixSec => current_unix_sec(),
+            ExpiryClock::Steps(steps) => steps.load(Ordering::Acquire),
+        }
+    }
+}
+
+enum PolicyState {
+    LRU,
+    // the linkedlist is sorted by (access count, recency), `run_tails` maps every
+    // access count to the index of the last entry having it
+    LFU {
+        run_tails: HashMap<u32, u32>,
+    },
+    // the linkedlist is laid out as [main LRU .. main MRU][window LRU .. window MRU]
+    WindowTinyLFU {
+        sketch: FrequencySketch,
+        window_head: Option<u32>,
+        window_len: usize,
+    },
+}
+
+impl PolicyState {
+    fn new(policy: EvictionPolicy, capacity: usize) -> Self {
+        match policy {
+            EvictionPolicy::LRU => PolicyState::LRU,
+            EvictionPolicy::LFU => PolicyState::LFU {
+                run_tails: HashMap::new(),
+            },
+            EvictionPolicy::WindowTinyLFU => PolicyState::WindowTinyLFU {
+                sketch: FrequencySketch::with_capacity(capacity),
+                window_head: None,
+                window_len: 0,
+            },
+        }
+    }
 }
 
 pub struct EvictionMap<K, V>
@@ -13,9 +123,19 @@ where
     K: Hash + Eq + Clone,
     V: EvictionMapValue<K>,
 {
-    pub hashmap: HashMap<K, u32>,
+    pub hashmap: HashMap<K, EvictionMapSlot>,
     pub linkedlist: ArrayLinkedList<V>,
     pub capacity: usize,
+    memory_budget_bytes: Option<usize>,
+    memory_bytes: usize,
+    policy: EvictionPolicy,
+    state: PolicyState,
+    // sightings of keys not admitted into the map yet
+    admission_sketch: FrequencySketch,
+    expiry_clock: ExpiryClock,
+    // index of the next entry to check by the expiry sweep under LFU and window TinyLFU,
+    // None starts over from the front
+    expiry_cursor: Option<u32>,
 }
 
 impl<K, V> EvictionMap<K, V>
@@ -24,90 +144,597 @@ where
     V: EvictionMapValue<K>,
 {
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_policy(capacity, EvictionPolicy::LRU)
+    }
+
+    pub fn with_capacity_and_policy(capacity: usize, policy: EvictionPolicy) -> Self {
         Self {
             hashmap: HashMap::with_capacity(capacity + 1),
             linkedlist: ArrayLinkedList::with_capacity(capacity as u32 + 1),
             capacity,
+            memory_budget_bytes: None,
+            memory_bytes: 0,
+            policy,
+            state: PolicyState::new(policy, capacity),
+            admission_sketch: FrequencySketch::with_capacity(capacity),
+            expiry_clock: ExpiryClock::UnixSec,
+            expiry_cursor: None,
+        }
+    }
+
+    /// Creates a map bounded by the bytes held by its entries instead of the number of entries.
+    pub fn with_memory_budget_and_policy(
+        memory_budget_bytes: usize,
+        policy: EvictionPolicy,
+    ) -> Self {
+        Self {
+            hashmap: HashMap::new(),
+            linkedlist: ArrayLinkedList::new(),
+            capacity: usize::MAX,
+            memory_budget_bytes: Some(memory_budget_bytes),
+            memory_bytes: 0,
+            policy,
+            state: PolicyState::new(policy, memory_budget_bytes / ESTIMATED_ENTRY_BYTES),
+            admission_sketch: FrequencySketch::with_capacity(
+                memory_budget_bytes / ESTIMATED_ENTRY_BYTES,
+            ),
+            expiry_clock: ExpiryClock::UnixSec,
+            expiry_cursor: None,
+        }
+    }
+
+    /// Bounds the map by `memory_budget_bytes` of entries as well as by its capacity.
+    pub fn with_memory_budget(mut self, memory_budget_bytes: usize) -> Self {
+        self.memory_budget_bytes = Some(memory_budget_bytes);
+        self
+    }
+
+    /// Records the last access of entries on `expiry_clock` instead of the unix time.
+    pub fn with_expiry_clock(mut self, expiry_clock: ExpiryClock) -> Self {
+        self.expiry_clock = expiry_clock;
+        self
+    }
+
+    pub fn expiry_clock(&self) -> &ExpiryClock {
+        &self.expiry_clock
+    }
+
+    // number of entries the map is expected to hold when full
+    fn expected_entries(&self) -> usize {
+        match self.memory_budget_bytes {
+            Some(memory_budget_bytes) => {
+                (memory_budget_bytes / ESTIMATED_ENTRY_BYTES).min(self.capacity)
+            }
+            None => self.capacity,
         }
     }
 
     pub fn get(&self, key: &K) -> Option<&V> {
         match self.hashmap.get(&key) {
-            Some(idx) => self.linkedlist[*idx as usize].as_ref(),
+            Some(slot) => self.linkedlist[slot.index as usize].as_ref(),
             None => None,
         }
     }
 
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
         match self.hashmap.get(&key) {
-            Some(idx) => self.linkedlist[*idx as usize].as_mut(),
+            Some(slot) => self.linkedlist[slot.index as usize].as_mut(),
             None => None,
         }
     }
 
     pub fn get_refresh(&mut self, key: &K) -> Option<&V> {
-        match self.hashmap.get(&key) {
-            Some(idx) => {
-                let idx = u32::try_from(*idx).expect("u32 array linked list overflow");
-                let v = self.linkedlist.remove(idx).unwrap();
-                let new_idx = self.linkedlist.push_back(v);
-                let idx_ref = self.hashmap.get_mut(key).unwrap();
-                *idx_ref = new_idx;
-                self.linkedlist[new_idx as usize].as_ref()
-            }
+        match self.refresh(key) {
+            Some(idx) => self.linkedlist[idx as usize].as_ref(),
             None => None,
         }
     }
 
     pub fn get_refresh_mut(&mut self, key: &K) -> Option<&mut V> {
-        match self.hashmap.get(&key) {
-            Some(idx) => {
-                let idx = u32::try_from(*idx).expect("u32 array linked list overflow");
-                let v = self.linkedlist.remove(idx).unwrap();
-                let new_idx = self.linkedlist.push_back(v);
-                let idx_ref = self.hashmap.get_mut(key).unwrap();
-                *idx_ref = new_idx;
-                self.linkedlist[new_idx as usize].as_mut()
-            }
+        match self.refresh(key) {
+            Some(idx) => self.linkedlist[idx as usize].as_mut(),
             None => None,
         }
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> (Option<V>, Option<V>) {
-        let old = match self.hashmap.get(&key) {
-            Some(idx) => self.linkedlist.remove(*idx),
-            None => None,
+    /// Inserts `value` under `key`, returns the replaced value and the entries evicted to make
+    /// room for it.
+    pub fn insert(&mut self, key: K, value: V) -> (Option<V>, Vec<V>) {
+        if let Some(slot) = self.hashmap.get(&key) {
+            self.memory_bytes += Self::entry_bytes(&value);
+            let old = self.linkedlist[slot.index as usize].replace(value);
+            if let Some(old_v) = &old {
+                self.memory_bytes -= Self::entry_bytes(old_v);
+            }
+            self.refresh(&key);
+            let evicted = self.evict_over_limit(Some(&key));
+            return (old, evicted);
+        }
+
+        let evicted = match &self.state {
+            PolicyState::LRU => self.insert_lru(key, value),
+            PolicyState::LFU { .. } => self.insert_lfu(key, value),
+            PolicyState::WindowTinyLFU { .. } => self.insert_window_tiny_lfu(key, value),
         };
 
-        let new_idx = self.linkedlist.push_back(value);
-        self.hashmap.insert(key, new_idx);
+        (None, evicted)
+    }
 
-        let evicted = if self.linkedlist.len() as usize > self.capacity {
-            let evicted = self.linkedlist.pop_front();
-            if let Some(evicted_v) = &evicted {
-                let evicted_k = evicted_v.hashmap_key();
-                self.hashmap.remove(&evicted_k);
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.hashmap.remove(key)?;
+        self.skip_expiry_cursor(slot.index);
+        match &mut self.state {
+            PolicyState::LRU => {}
+            PolicyState::LFU { run_tails } => {
+                detach_from_run(run_tails, &self.hashmap, &self.linkedlist, &slot);
+            }
+            PolicyState::WindowTinyLFU {
+                window_head,
+                window_len,
+                ..
+            } => {
+                if slot.tag == WINDOW_REGION {
+                    if *window_head == Some(slot.index) {
+                        *window_head = self.linkedlist.indices_after(slot.index).next();
+                    }
+                    *window_len -= 1;
+                }
+            }
+        }
+        let removed = self.linkedlist.remove(slot.index);
+        if let Some(removed_v) = &removed {
+            self.memory_bytes -= Self::entry_bytes(removed_v);
+        }
+        removed
+    }
+
+    /// Removes all entries, the first to be evicted first, along with their eviction bookkeeping.
+    pub fn drain_with_meta(&mut self) -> Vec<(V, EvictionMeta)> {
+        let mut entries = Vec::with_capacity(self.len());
+        while let Some(front_key) = self.front_key() {
+            let slot = *self.hashmap.get(&front_key).unwrap();
+            let frequency = match &self.state {
+                PolicyState::WindowTinyLFU { sketch, .. } => sketch.frequency(&front_key),
+                _ => 0,
+            };
+            let meta = EvictionMeta {
+                last_access: slot.last_access,
+                tag: slot.tag,
+                frequency,
+            };
+            entries.extend(self.remove(&front_key).map(|v| (v, meta)));
+        }
+        self.clear();
+        entries
+    }
+
+    /// Inserts an entry drained from another map by `drain_with_meta`. It keeps its last access
+    /// time, its access count under LFU, and its region and estimated frequency under window
+    /// TinyLFU. Returns the entries evicted to make room for it.
+    pub fn insert_with_meta(&mut self, key: K, value: V, meta: EvictionMeta) -> Vec<V> {
+        if self.hashmap.contains_key(&key) {
+            return self.insert(key, value).1;
+        }
+
+        let evicted = match &self.state {
+            PolicyState::LRU => self.insert_lru(key.clone(), value),
+            PolicyState::LFU { .. } => {
+                self.insert_lfu_with_count(key.clone(), value, meta.tag.max(1))
+            }
+            PolicyState::WindowTinyLFU { .. } => {
+                self.insert_window_tiny_lfu_with_meta(key.clone(), value, meta.tag, meta.frequency)
             }
-            evicted
-        } else {
-            None
         };
+        if let Some(slot) = self.hashmap.get_mut(&key) {
+            slot.last_access = meta.last_access;
+        }
+        evicted
+    }
+
+    /// Removes the entries which are not inserted or refreshed within the last `ttl` on the
+    /// expiry clock, returns the removed entries.
+    ///
+    /// At most `max_scanned` entries are checked per call. Under LRU the expired entries are all
+    /// at the front. Under the other policies the sweep walks the linkedlist from where the
+    /// previous call stopped, so the whole map is checked over successive calls.
+    pub fn evict_expired(&mut self, ttl: u64, max_scanned: usize) -> Vec<V> {
+        let deadline = self.expiry_clock.now().saturating_sub(ttl);
+        let is_expired = |slot: &EvictionMapSlot| slot.last_access < deadline;
+
+        match &self.state {
+            PolicyState::LRU => {
+                let mut expired = Vec::new();
+                while let Some(front_key) = self.front_key() {
+                    if expired.len() >= max_scanned
+                        || !is_expired(self.hashmap.get(&front_key).unwrap())
+                    {
+                        break;
+                    }
+                    expired.extend(self.remove(&front_key));
+                }
+                expired
+            }
+            _ => {
+                let start = match self.expiry_cursor.or_else(|| self.linkedlist.front_index()) {
+                    Some(start) => start,
+                    None => return Vec::new(),
+                };
+                let mut indices =
+                    std::iter::once(start).chain(self.linkedlist.indices_after(start));
+                let expired_keys: Vec<K> = indices
+                    .by_ref()
+                    .take(max_scanned)
+                    .map(|idx| {
+                        self.linkedlist[idx as usize]
+                            .as_ref()
+                            .unwrap()
+                            .hashmap_key()
+                    })
+                    .filter(|k| is_expired(self.hashmap.get(k).unwrap()))
+                    .collect();
+                self.expiry_cursor = indices.next();
+                drop(indices);
+                expired_keys.iter().filter_map(|k| self.remove(k)).collect()
+            }
+        }
+    }
+
+    // moves the expiry cursor off the entry at `idx`, which is about to leave its position
+    fn skip_expiry_cursor(&mut self, idx: u32) {
+        if self.expiry_cursor == Some(idx) {
+            self.expiry_cursor = self.linkedlist.indices_after(idx).next();
+        }
+    }
 
-        (old, evicted)
+    /// Records a sighting of `key` which is not admitted into the map yet, returns how many
+    /// times it has been seen recently.
+    pub fn record_sighting(&mut self, key: &K) -> u8 {
+        // only a map bounded by memory can outgrow its sketch
+        self.admission_sketch.ensure_capacity(self.len());
+        self.admission_sketch.increment(key)
     }
 
     pub fn clear(&mut self) {
         self.hashmap.clear();
         self.linkedlist.clear();
+        self.memory_bytes = 0;
+        self.state = PolicyState::new(self.policy, self.expected_entries());
+        self.admission_sketch.clear();
+        self.expiry_cursor = None;
     }
 
     pub fn capacity(&self) -> usize {
         self.capacity
     }
 
+    pub fn memory_budget_bytes(&self) -> Option<usize> {
+        self.memory_budget_bytes
+    }
+
+    /// Bytes held by the entries of the map, including the per entry bookkeeping of the map.
+    pub fn memory_bytes(&self) -> usize {
+        self.memory_bytes
+    }
+
     pub fn len(&self) -> usize {
         self.linkedlist.len() as usize
     }
+
+    pub fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    fn entry_bytes(value: &V) -> usize {
+        value.size_in_bytes() + std::mem::size_of::<(K, EvictionMapSlot)>()
+    }
+
+    fn exceeds_limit(&self, num_entries: usize, memory_bytes: usize) -> bool {
+        num_entries > self.capacity
+            || self
+                .memory_budget_bytes
+                .is_some_and(|budget| memory_bytes > budget)
+    }
+
+    fn front_key(&self) -> Option<K> {
+        self.linkedlist.front_index().map(|idx| {
+            self.linkedlist[idx as usize]
+                .as_ref()
+                .unwrap()
+                .hashmap_key()
+        })
+    }
+
+    // Evicts entries from the front of the linkedlist until the map fits in its limit again,
+    // `protected` is the key of a just inserted entry which is evicted only as the last one.
+    fn evict_over_limit(&mut self, protected: Option<&K>) -> Vec<V> {
+        let mut evicted = Vec::new();
+        while self.exceeds_limit(self.len(), self.memory_bytes) {
+            let victim_key = match self.front_key() {
+                Some(front_key) if Some(&front_key) == protected && self.len() > 1 => {
+                    let next_idx = self
+                        .linkedlist
+                        .indices_after(self.linkedlist.front_index().unwrap())
+                        .next()
+                        .unwrap();
+                    self.linkedlist[next_idx as usize]
+                        .as_ref()
+                        .unwrap()
+                        .hashmap_key()
+                }
+                Some(front_key) => front_key,
+                None => break,
+            };
+            evicted.extend(self.remove(&victim_key));
+        }
+        evicted
+    }
+
+    // Moves the entry of `key` to the position its policy assigns to a just accessed entry,
+    // returns the new index of the entry.
+    fn refresh(&mut self, key: &K) -> Option<u32> {
+        let slot = *self.hashmap.get(key)?;
+        let idx = slot.index;
+        let last_access = self.expiry_clock.now();
+        self.skip_expiry_cursor(idx);
+
+        let new_slot = match &mut self.state {
+            PolicyState::LRU => {
+                let v = self.linkedlist.remove(idx).unwrap();
+                EvictionMapSlot {
+                    index: self.linkedlist.push_back(v),
+                    tag: slot.tag,
+                    last_access,
+                }
+            }
+            PolicyState::LFU { run_tails } => {
+                let count = slot.tag;
+                let prev_idx = detach_from_run(run_tails, &self.hashmap, &self.linkedlist, &slot);
+
+                let v = self.linkedlist.remove(idx).unwrap();
+                let new_count = count.saturating_add(1);
+                let new_idx = match run_tails.get(&new_count).or_else(|| run_tails.get(&count)) {
+                    Some(tail) => self.linkedlist.insert_after(*tail, v).unwrap(),
+                    None => match prev_idx {
+                        Some(prev) => self.linkedlist.insert_after(prev, v).unwrap(),
+                        None => self.linkedlist.push_front(v),
+                    },
+                };
+                run_tails.insert(new_count, new_idx);
+                EvictionMapSlot {
+                    index: new_idx,
+                    tag: new_count,
+                    last_access,
+                }
+            }
+            PolicyState::WindowTinyLFU {
+                sketch,
+                window_head,
+                ..
+            } => {
+                sketch.increment(key);
+                if slot.tag == WINDOW_REGION {
+                    let next_idx = self.linkedlist.indices_after(idx).next();
+                    let v = self.linkedlist.remove(idx).unwrap();
+                    let new_idx = self.linkedlist.push_back(v);
+                    if *window_head == Some(idx) {
+                        *window_head = Some(next_idx.unwrap_or(new_idx));
+                    }
+                    EvictionMapSlot {
+                        index: new_idx,
+                        tag: WINDOW_REGION,
+                        last_access,
+                    }
+                } else {
+                    let v = self.linkedlist.remove(idx).unwrap();
+                    let new_idx = match window_head {
+                        Some(head) => self.linkedlist.insert_before(*head, v).unwrap(),
+                        None => self.linkedlist.push_back(v),
+                    };
+                    EvictionMapSlot {
+                        index: new_idx,
+                        tag: MAIN_REGION,
+                        last_access,
+                    }
+                }
+            }
+        };
+
+        *self.hashmap.get_mut(key).unwrap() = new_slot;
+        Some(new_slot.index)
+    }
+
+    fn insert_lru(&mut self, key: K, value: V) -> Vec<V> {
+        self.memory_bytes += Self::entry_bytes(&value);
+        let new_idx = self.linkedlist.push_back(value);
+        self.hashmap.insert(
+            key.clone(),
+            EvictionMapSlot {
+                index: new_idx,
+                tag: 0,
+                last_access: self.expiry_clock.now(),
+            },
+        );
+
+        self.evict_over_limit(Some(&key))
+    }
+
+    fn insert_lfu(&mut self, key: K, value: V) -> Vec<V> {
+        self.insert_lfu_with_count(key, value, 1)
+    }
+
+    fn insert_lfu_with_count(&mut self, key: K, value: V, count: u32) -> Vec<V> {
+        // evict before inserting, otherwise the new entry would be the one to go
+        let new_bytes = Self::entry_bytes(&value);
+        let mut evicted = Vec::new();
+        while self.exceeds_limit(self.len() + 1, self.memory_bytes + new_bytes) {
+            match self.front_key() {
+                Some(front_key) => evicted.extend(self.remove(&front_key)),
+                None => break,
+            }
+        }
+
+        let run_tails = match &mut self.state {
+            PolicyState::LFU { run_tails } => run_tails,
+            _ => unreachable!(),
+        };
+        // the entry goes to the end of the run of its count, or after the run of the closest
+        // lower count
+        let prev_tail = match run_tails.get(&count) {
+            Some(tail) => Some(*tail),
+            None => run_tails
+                .iter()
+                .filter(|(c, _)| **c < count)
+                .max_by_key(|(c, _)| **c)
+                .map(|(_, tail)| *tail),
+        };
+        let new_idx = match prev_tail {
+            Some(tail) => self.linkedlist.insert_after(tail, value).unwrap(),
+            None => self.linkedlist.push_front(value),
+        };
+        run_tails.insert(count, new_idx);
+        self.memory_bytes += new_bytes;
+        self.hashmap.insert(
+            key,
+            EvictionMapSlot {
+                index: new_idx,
+                tag: count,
+                last_access: self.expiry_clock.now(),
+            },
+        );
+
+        evicted
+    }
+
+    fn insert_window_tiny_lfu_with_meta(
+        &mut self,
+        key: K,
+        value: V,
+        region: u32,
+        frequency: u8,
+    ) -> Vec<V> {
+        self.memory_bytes += Self::entry_bytes(&value);
+        let num_entries = self.len() + 1;
+        let capacity = self.capacity;
+        let (sketch, window_head, window_len) = match &mut self.state {
+            PolicyState::WindowTinyLFU {
+                sketch,
+                window_head,
+                window_len,
+            } => (sketch, window_head, window_len),
+            _ => unreachable!(),
+        };
+
+        sketch.ensure_capacity(num_entries);
+        for _ in sketch.frequency(&key)..frequency {
+            sketch.increment(&key);
+        }
+        let new_idx = if region == MAIN_REGION {
+            match window_head {
+                Some(head) => self.linkedlist.insert_before(*head, value).unwrap(),
+                None => self.linkedlist.push_back(value),
+            }
+        } else {
+            let new_idx = self.linkedlist.push_back(value);
+            if window_head.is_none() {
+                *window_head = Some(new_idx);
+            }
+            *window_len += 1;
+            new_idx
+        };
+        self.hashmap.insert(
+            key.clone(),
+            EvictionMapSlot {
+                index: new_idx,
+                tag: region,
+                last_access: self.expiry_clock.now(),
+            },
+        );
+
+        // the window LRU entries overflowing the window move to the main region
+        while *window_len > std::cmp::max(num_entries.min(capacity) / 100, 1) {
+            let head = window_head.unwrap();
+            *window_head = self.linkedlist.indices_after(head).next();
+            *window_len -= 1;
+            let head_key = self.linkedlist[head as usize]
+                .as_ref()
+                .unwrap()
+                .hashmap_key();
+            self.hashmap.get_mut(&head_key).unwrap().tag = MAIN_REGION;
+        }
+
+        self.evict_over_limit(Some(&key))
+    }
+
+    fn insert_window_tiny_lfu(&mut self, key: K, value: V) -> Vec<V> {
+        self.memory_bytes += Self::entry_bytes(&value);
+        let num_entries = self.len() + 1;
+        let (sketch, window_head, window_len) = match &mut self.state {
+            PolicyState::WindowTinyLFU {
+                sketch,
+                window_head,
+                window_len,
+            } => (sketch, window_head, window_len),
+            _ => unreachable!(),
+        };
+
+        sketch.ensure_capacity(num_entries);
+        sketch.increment(&key);
+        let new_idx = self.linkedlist.push_back(value);
+        self.hashmap.insert(
+            key,
+            EvictionMapSlot {
+                index: new_idx,
+                tag: WINDOW_REGION,
+                last_access: self.expiry_clock.now(),
+            },
+        );
+        if window_head.is_none() {
+            *window_head = Some(new_idx);
+        }
+        *window_len += 1;
+
+        // the window holds 1% of the entries
+        if *window_len <= std::cmp::max(num_entries.min(self.capacity) / 100, 1) {
+            return self.evict_over_limit(None);
+        }
+
+        // the window LRU entry is moved to the main region as a candidate
+        let candidate_idx = window_head.unwrap();
+        *window_head = self.linkedlist.indices_after(candidate_idx).next();
+        *window_len -= 1;
+        let candidate_key = self.linkedlist[candidate_idx as usize]
+            .as_ref()
+            .unwrap()
+            .hashmap_key();
+        self.hashmap.get_mut(&candidate_key).unwrap().tag = MAIN_REGION;
+
+        // main region is full, keep whichever of candidate and victim is accessed more often
+        let mut evicted = Vec::new();
+        let mut candidate_key = Some(candidate_key);
+        while self.exceeds_limit(self.len(), self.memory_bytes) {
+            let victim_key = match self.front_key() {
+                Some(front_key) => front_key,
+                None => break,
+            };
+            let evicted_key = match candidate_key.take() {
+                Some(c) if c != victim_key => {
+                    let sketch = match &self.state {
+                        PolicyState::WindowTinyLFU { sketch, .. } => sketch,
+                        _ => unreachable!(),
+                    };
+                    if sketch.frequency(&c) > sketch.frequency(&victim_key) {
+                        candidate_key = Some(c);
+                        victim_key
+                    } else {
+                        c
+                    }
+                }
+                _ => victim_key,
+            };
+            evicted.extend(self.remove(&evicted_key));
+        }
+        evicted
+    }
 }
 
 #[cfg(test)]
@@ -146,4 +773,306 @@ mod eviction_map_tests {
         assert_eq!(map.get_refresh(&6).is_none(), true);
         assert_eq!(map.get_refresh(&5).is_some(), true);
     }
+
+    #[test]
+    fn test_evict_lfu() {
+        let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+            EvictionMap::with_capacity_and_policy(5, EvictionPolicy::LFU);
+
+        let initialization = InitializationMethod::default();
+
+        for i in 0..5 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            map.insert(i, entry);
+        }
+
+        // 0 and 1 are hot, 2 is warm, 3 and 4 are only seen once
+        for _ in 0..3 {
+            assert_eq!(map.get_refresh(&0).is_some(), true);
+            assert_eq!(map.get_refresh(&1).is_some(), true);
+        }
+        assert_eq!(map.get_refresh(&2).is_some(), true);
+
+        // a burst of long-tail ids only evicts other cold ids
+        for i in 5..10 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            map.insert(i, entry);
+        }
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&0).is_some(), true);
+        assert_eq!(map.get(&1).is_some(), true);
+        assert_eq!(map.get(&2).is_some(), true);
+        assert_eq!(map.get(&3).is_none(), true);
+        assert_eq!(map.get(&7).is_none(), true);
+        assert_eq!(map.get(&8).is_some(), true);
+        assert_eq!(map.get(&9).is_some(), true);
+
+        // the least frequently used entry goes first, ties are broken by recency
+        assert_eq!(map.get_refresh(&9).is_some(), true);
+        assert_eq!(map.get_refresh(&9).is_some(), true);
+        let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, 10, 10);
+        map.insert(10, entry);
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&8).is_none(), true);
+        assert_eq!(map.get(&2).is_some(), true);
+        assert_eq!(map.get(&9).is_some(), true);
+        assert_eq!(map.get(&10).is_some(), true);
+    }
+
+    #[test]
+    fn test_evict_window_tiny_lfu() {
+        let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+            EvictionMap::with_capacity_and_policy(5, EvictionPolicy::WindowTinyLFU);
+
+        let initialization = InitializationMethod::default();
+
+        for i in 0..5 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            map.insert(i, entry);
+        }
+        assert_eq!(map.len(), 5);
+
+        for _ in 0..3 {
+            for i in 0..4 {
+                assert_eq!(map.get_refresh(&i).is_some(), true);
+            }
+        }
+
+        // long-tail ids pass through the window but are not admitted into the main region
+        for i in 5..20 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            map.insert(i, entry);
+        }
+
+        assert_eq!(map.len(), 5);
+        for i in 0..4 {
+            assert_eq!(map.get(&i).is_some(), true);
+        }
+        assert_eq!(map.get(&19).is_some(), true);
+        assert_eq!(map.get(&18).is_none(), true);
+
+        // an id accessed often enough replaces the coldest entry of the main region
+        for _ in 0..10 {
+            assert_eq!(map.get_refresh(&19).is_some(), true);
+        }
+        let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, 20, 20);
+        map.insert(20, entry);
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&19).is_some(), true);
+        assert_eq!(map.get(&20).is_some(), true);
+        assert_eq!(
+            (0..4).filter(|i| map.get(i).is_some()).count(),
+            3,
+            "exactly one hot id should have been replaced"
+        );
+    }
+
+    #[test]
+    fn test_evict_expired() {
+        let initialization = InitializationMethod::default();
+
+        for policy in [
+            EvictionPolicy::LRU,
+            EvictionPolicy::LFU,
+            EvictionPolicy::WindowTinyLFU,
+        ] {
+            let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+                EvictionMap::with_capacity_and_policy(5, policy);
+
+            for i in 0..5 {
+                let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+                map.insert(i, entry);
+            }
+
+            // pretend 0 and 1 were last accessed a minute ago
+            for i in 0..2 {
+                map.hashmap.get_mut(&i).unwrap().last_access -= 60;
+            }
+
+            let expired = map.evict_expired(30, usize::MAX);
+            assert_eq!(expired.len(), 2);
+            assert_eq!(map.len(), 3);
+            assert_eq!(map.get(&0).is_none(), true);
+            assert_eq!(map.get(&1).is_none(), true);
+            assert_eq!(map.evict_expired(30, usize::MAX).len(), 0);
+
+            // the map keeps evicting by its policy after expiry
+            for i in 5..10 {
+                let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+                map.insert(i, entry);
+                assert_eq!(map.get_refresh(&i).is_some(), true);
+            }
+            assert_eq!(map.len(), 5);
+            assert_eq!(map.get(&9).is_some(), true);
+        }
+    }
+    #[test]
+    fn test_evict_expired_by_steps() {
+        let initialization = InitializationMethod::default();
+
+        for policy in [
+            EvictionPolicy::LRU,
+            EvictionPolicy::LFU,
+            EvictionPolicy::WindowTinyLFU,
+        ] {
+            let steps = Arc::new(AtomicU64::new(0));
+            let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+                EvictionMap::with_capacity_and_policy(100, policy)
+                    .with_expiry_clock(ExpiryClock::Steps(steps.clone()));
+
+            for i in 0..10 {
+                let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+                map.insert(i, entry);
+            }
+            steps.store(10, Ordering::Release);
+            for i in (0..10).step_by(2) {
+                assert_eq!(map.get_refresh(&i).is_some(), true);
+            }
+
+            // each sweep checks at most 3 entries, the sweeps together cover the whole map
+            let mut num_expired = 0;
+            for _ in 0..5 {
+                let expired = map.evict_expired(5, 3);
+                assert_eq!(expired.len() <= 3, true);
+                num_expired += expired.len();
+            }
+            assert_eq!(num_expired, 5);
+            assert_eq!(map.len(), 5);
+            assert_eq!((0..10).step_by(2).all(|i| map.get(&i).is_some()), true);
+        }
+    }
+
+    #[test]
+    fn test_evict_memory_budget() {
+        let initialization = InitializationMethod::default();
+        let small_entry_bytes = EvictionMap::<u64, HashMapEmbeddingEntry>::entry_bytes(
+            &HashMapEmbeddingEntry::new(&initialization, 8, 16, 0, 0),
+        );
+
+        for policy in [
+            EvictionPolicy::LRU,
+            EvictionPolicy::LFU,
+            EvictionPolicy::WindowTinyLFU,
+        ] {
+            let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+                EvictionMap::with_memory_budget_and_policy(5 * small_entry_bytes, policy);
+
+            for i in 0..10 {
+                let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+                map.insert(i, entry);
+            }
+
+            assert_eq!(map.len(), 5);
+            assert_eq!(map.memory_bytes(), 5 * small_entry_bytes);
+
+            // a larger entry takes the room of several small ones
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 56, 10, 10);
+            let large_entry_bytes = EvictionMap::<u64, HashMapEmbeddingEntry>::entry_bytes(&entry);
+            let (_, evicted) = map.insert(10, entry);
+
+            assert_eq!(evicted.len() > 1, true);
+            assert_eq!(map.memory_bytes() <= 5 * small_entry_bytes, true);
+            assert_eq!(
+                map.memory_bytes(),
+                (map.len() - 1) * small_entry_bytes + large_entry_bytes
+            );
+
+            map.remove(&10);
+            assert_eq!(map.memory_bytes(), map.len() * small_entry_bytes);
+
+            // a map bounded by both stops at whichever limit is reached first
+            let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+                EvictionMap::with_capacity_and_policy(5, policy)
+                    .with_memory_budget(3 * small_entry_bytes);
+            for i in 0..10 {
+                let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+                map.insert(i, entry);
+            }
+            assert_eq!(map.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_migrate_with_meta() {
+        let initialization = InitializationMethod::default();
+
+        for policy in [
+            EvictionPolicy::LRU,
+            EvictionPolicy::LFU,
+            EvictionPolicy::WindowTinyLFU,
+        ] {
+            let mut map: EvictionMap<u64, HashMapEmbeddingEntry> =
+                EvictionMap::with_capacity_and_policy(5, policy);
+            for i in 0..5 {
+                let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+                map.insert(i, entry);
+            }
+            // 0 is the only cold entry
+            for _ in 0..3 {
+                for i in 1..5 {
+                    assert_eq!(map.get_refresh(&i).is_some(), true);
+                }
+            }
+            map.hashmap.get_mut(&3).unwrap().last_access -= 60;
+            let slots: HashMap<u64, (u32, u64)> = map
+                .hashmap
+                .iter()
+                .map(|(k, slot)| (*k, (slot.tag, slot.last_access)))
+                .collect();
+
+            let drained = map.drain_with_meta();
+            assert_eq!(map.len(), 0);
+            assert_eq!(drained.len(), 5);
+
+            let mut migrated: EvictionMap<u64, HashMapEmbeddingEntry> =
+                EvictionMap::with_capacity_and_policy(5, policy);
+            for (entry, meta) in drained {
+                let sign = entry.sign();
+                assert_eq!(migrated.insert_with_meta(sign, entry, meta).len(), 0);
+            }
+            for (k, (tag, last_access)) in slots.iter() {
+                let slot = migrated.hashmap.get(k).unwrap();
+                assert_eq!(slot.last_access, *last_access);
+                if policy == EvictionPolicy::LFU {
+                    assert_eq!(slot.tag, *tag);
+                }
+            }
+
+            // the cold entry is still the one to go
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, 5, 5);
+            migrated.insert(5, entry);
+            assert_eq!(migrated.len(), 5);
+            assert_eq!(migrated.get(&0).is_none(), true, "{:?}", policy);
+            assert_eq!((1..5).all(|i| migrated.get(&i).is_some()), true);
+        }
+    }
+
+    #[test]
+    fn test_record_sighting() {
+        let mut map: EvictionMap<u64, HashMapEmbeddingEntry> = EvictionMap::with_capacity(5);
+
+        assert_eq!(map.record_sighting(&1), 1);
+        assert_eq!(map.record_sighting(&1), 2);
+        assert_eq!(map.record_sighting(&2), 1);
+        assert_eq!(map.record_sighting(&1), 3);
+
+        map.clear();
+        assert_eq!(map.record_sighting(&1), 1);
+
+        // the sketch is sized by the capacity, cold signs do not pile up on each other before
+        // the map fills up
+        let mut map: EvictionMap<u64, HashMapEmbeddingEntry> = EvictionMap::with_capacity(100000);
+        for sign in 0..10000 {
+            map.record_sighting(&sign);
+        }
+        assert_eq!(
+            (10000..10100)
+                .filter(|sign| map.record_sighting(sign) > 1)
+                .count(),
+            0
+        );
+    }
 }
@@ -0,0 +1,162 @@
+use std::hash::{Hash, Hasher};
+
+const SKETCH_DEPTH: usize = 4;
+
+/// A count-min sketch that estimates how many times a key has been seen recently.
+///
+/// Counters saturate at `u8::MAX` and are halved every `sample_size` increments, so the
+/// popularity of keys that are no longer accessed fades out over time.
+pub struct FrequencySketch {
+    table: Vec<u8>,
+    mask: usize,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl FrequencySketch {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let width = capacity.max(256).next_power_of_two();
+        Self {
+            table: vec![0; width * SKETCH_DEPTH],
+            mask: width - 1,
+            additions: 0,
+            sample_size: width * 10,
+        }
+    }
+
+    /// Grows the sketch to fit `capacity` keys, keeping the recorded frequencies.
+    ///
+    /// The column of a key in a row of the grown sketch agrees with its column before in the
+    /// low bits, so every counter of the grown sketch starts from the counter it is split from.
+    pub fn ensure_capacity(&mut self, capacity: usize) {
+        let width = self.mask + 1;
+        if capacity <= width {
+            return;
+        }
+        let mut grown = Self::with_capacity(capacity);
+        let grown_width = grown.mask + 1;
+        for row in 0..SKETCH_DEPTH {
+            for col in 0..grown_width {
+                grown.table[row * grown_width + col] = self.table[row * width + (col & self.mask)];
+            }
+        }
+        grown.additions = self.additions;
+        *self = grown;
+    }
+
+    #[inline]
+    fn indices<K: Hash>(&self, key: &K) -> [usize; SKETCH_DEPTH] {
+        let mut s = ahash::AHasher::default();
+        key.hash(&mut s);
+        let hash = s.finish();
+
+        let h1 = hash as u32 as usize;
+        let h2 = (hash >> 32) as usize | 1;
+        let width = self.mask + 1;
+
+        let mut indices = [0; SKETCH_DEPTH];
+        for (row, idx) in indices.iter_mut().enumerate() {
+            *idx = row * width + (h1.wrapping_add(row.wrapping_mul(h2)) & self.mask);
+        }
+        indices
+    }
+
+    pub fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.indices(key)
+            .iter()
+            .map(|idx| self.table[*idx])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Records one occurrence of `key` and returns its estimated frequency afterwards.
+    pub fn increment<K: Hash>(&mut self, key: &K) -> u8 {
+        let indices = self.indices(key);
+        let current = indices
+            .iter()
+            .map(|idx| self.table[*idx])
+            .min()
+            .unwrap_or(0);
+        if current == u8::MAX {
+            return current;
+        }
+
+        // conservative update: only the counters holding the minimum are raised
+        indices.iter().for_each(|idx| {
+            if self.table[*idx] == current {
+                self.table[*idx] += 1;
+            }
+        });
+
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            self.reset();
+        }
+
+        current + 1
+    }
+
+    fn reset(&mut self) {
+        self.table.iter_mut().for_each(|x| *x >>= 1);
+        self.additions /= 2;
+    }
+
+    pub fn clear(&mut self) {
+        self.table.iter_mut().for_each(|x| *x = 0);
+        self.additions = 0;
+    }
+}
+
+#[cfg(test)]
+mod frequency_sketch_tests {
+    use super::*;
+
+    #[test]
+    fn test_frequency() {
+        let mut sketch = FrequencySketch::with_capacity(64);
+
+        for _ in 0..5 {
+            sketch.increment(&1u64);
+        }
+        sketch.increment(&2u64);
+
+        assert_eq!(sketch.frequency(&1u64), 5);
+        assert_eq!(sketch.frequency(&2u64), 1);
+        assert_eq!(sketch.frequency(&3u64), 0);
+    }
+
+    #[test]
+    fn test_ensure_capacity() {
+        let mut sketch = FrequencySketch::with_capacity(16);
+
+        for i in 0..100u64 {
+            for _ in 0..(i % 5) {
+                sketch.increment(&i);
+            }
+        }
+        let frequencies: Vec<u8> = (0..100u64).map(|i| sketch.frequency(&i)).collect();
+        sketch.ensure_capacity(100000);
+
+        // grown counters never lose recorded counts
+        assert_eq!(
+            (0..100u64).all(|i| sketch.frequency(&i) >= (i % 5) as u8),
+            true
+        );
+        assert_eq!(
+            (0..100u64).all(|i| sketch.frequency(&i) <= frequencies[i as usize]),
+            true
+        );
+    }
+
+    #[test]
+    fn test_aging() {
+        let mut sketch = FrequencySketch::with_capacity(16);
+
+        for _ in 0..8 {
+            sketch.increment(&1u64);
+        }
+        sketch.reset();
+
+        assert_eq!(sketch.frequency(&1u64), 4);
+    }
+}
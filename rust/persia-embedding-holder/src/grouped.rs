@@ -0,0 +1,184 @@
+use std::borrow::Cow;
+
+use persia_libs::hashbrown::HashMap;
+
+use crate::backend::{EmbeddingShardBackend, EvictionMeta};
+use crate::emb_entry::HashMapEmbeddingEntry;
+
+/// A backend routing the feature groups with their own capacity quota to separate backends,
+/// so that a group exceeding its quota only evicts its own entries. Signs of the other feature
+/// groups share the `shared` backend.
+pub struct GroupedBackend {
+    // selects the feature group index prefix bits of a sign
+    prefix_mask: u64,
+    shared: Box<dyn EmbeddingShardBackend>,
+    groups: HashMap<u64, Box<dyn EmbeddingShardBackend>>,
+}
+
+impl GroupedBackend {
+    pub fn new(
+        prefix_mask: u64,
+        shared: Box<dyn EmbeddingShardBackend>,
+        groups: HashMap<u64, Box<dyn EmbeddingShardBackend>>,
+    ) -> Self {
+        Self {
+            prefix_mask,
+            shared,
+            groups,
+        }
+    }
+
+    fn backend(&self, sign: &u64) -> &dyn EmbeddingShardBackend {
+        match self.groups.get(&(sign & self.prefix_mask)) {
+            Some(group) => group.as_ref(),
+            None => self.shared.as_ref(),
+        }
+    }
+
+    fn backend_mut(&mut self, sign: &u64) -> &mut dyn EmbeddingShardBackend {
+        match self.groups.get_mut(&(sign & self.prefix_mask)) {
+            Some(group) => group.as_mut(),
+            None => self.shared.as_mut(),
+        }
+    }
+
+    fn backends(&self) -> impl Iterator<Item = &Box<dyn EmbeddingShardBackend>> {
+        std::iter::once(&self.shared).chain(self.groups.values())
+    }
+
+    fn backends_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn EmbeddingShardBackend>> {
+        std::iter::once(&mut self.shared).chain(self.groups.values_mut())
+    }
+}
+
+impl EmbeddingShardBackend for GroupedBackend {
+    fn get(&self, sign: &u64) -> Option<Cow<'_, HashMapEmbeddingEntry>> {
+        self.backend(sign).get(sign)
+    }
+
+    fn get_mut(&mut self, sign: &u64) -> Option<&mut HashMapEmbeddingEntry> {
+        self.backend_mut(sign).get_mut(sign)
+    }
+
+    fn get_refresh(&mut self, sign: &u64) -> Option<&HashMapEmbeddingEntry> {
+        self.backend_mut(sign).get_refresh(sign)
+    }
+
+    fn insert(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+    ) -> (Option<HashMapEmbeddingEntry>, Vec<HashMapEmbeddingEntry>) {
+        self.backend_mut(&sign).insert(sign, entry)
+    }
+
+    fn remove(&mut self, sign: &u64) -> Option<HashMapEmbeddingEntry> {
+        self.backend_mut(sign).remove(sign)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, HashMapEmbeddingEntry>> + '_> {
+        Box::new(self.backends().flat_map(|backend| backend.iter()))
+    }
+
+    fn signs(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.backends().flat_map(|backend| backend.signs()))
+    }
+
+    fn len(&self) -> usize {
+        self.backends().map(|backend| backend.len()).sum()
+    }
+
+    fn clear(&mut self) {
+        self.backends_mut().for_each(|backend| backend.clear());
+    }
+
+    fn drain_with_meta(&mut self) -> Vec<(HashMapEmbeddingEntry, Option<EvictionMeta>)> {
+        self.backends_mut()
+            .flat_map(|backend| backend.drain_with_meta())
+            .collect()
+    }
+
+    fn insert_with_meta(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+        meta: Option<EvictionMeta>,
+    ) -> Vec<HashMapEmbeddingEntry> {
+        self.backend_mut(&sign).insert_with_meta(sign, entry, meta)
+    }
+
+    fn record_sighting(&mut self, sign: &u64) -> u8 {
+        self.backend_mut(sign).record_sighting(sign)
+    }
+
+    fn evict_expired(&mut self, ttl: u64, max_scanned: usize) -> usize {
+        self.backends_mut()
+            .map(|backend| backend.evict_expired(ttl, max_scanned))
+            .sum()
+    }
+
+    fn compact_disk(&mut self, max_records: usize) -> bool {
+        // every backend makes progress even if an earlier one is still compacting
+        let mut in_progress = false;
+        for backend in self.backends_mut() {
+            in_progress |= backend.compact_disk(max_records);
+        }
+        in_progress
+    }
+
+    fn capacity(&self) -> usize {
+        self.backends()
+            .map(|backend| backend.capacity())
+            .fold(0, usize::saturating_add)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.backends().map(|backend| backend.memory_bytes()).sum()
+    }
+
+    fn disk_bytes(&self) -> u64 {
+        self.backends().map(|backend| backend.disk_bytes()).sum()
+    }
+}
+
+#[cfg(test)]
+mod grouped_tests {
+    use super::*;
+    use crate::eviction_map::EvictionMap;
+    use persia_embedding_config::InitializationMethod;
+
+    #[test]
+    fn test_group_quota() {
+        let prefix_mask = !((1u64 << 56) - 1);
+        let group_prefix = 2u64 << 56;
+        let mut groups: HashMap<u64, Box<dyn EmbeddingShardBackend>> = HashMap::new();
+        groups.insert(group_prefix, Box::new(EvictionMap::with_capacity(3)));
+        let mut backend =
+            GroupedBackend::new(prefix_mask, Box::new(EvictionMap::with_capacity(5)), groups);
+        let initialization = InitializationMethod::default();
+
+        let shared_signs: Vec<u64> = (0..5).map(|i| (1u64 << 56) + i).collect();
+        for sign in shared_signs.iter() {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, *sign, *sign);
+            backend.insert(*sign, entry);
+        }
+
+        let mut num_evicted = 0;
+        for i in 0..10 {
+            let sign = group_prefix + i;
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, sign, sign);
+            num_evicted += backend.insert(sign, entry).1.len();
+        }
+
+        assert_eq!(num_evicted, 7);
+        assert_eq!(backend.len(), 8);
+        assert_eq!(backend.capacity(), 8);
+        assert_eq!(shared_signs.iter().all(|s| backend.get(s).is_some()), true);
+        assert_eq!(backend.get(&group_prefix).is_none(), true);
+        assert_eq!(backend.get(&(group_prefix + 9)).is_some(), true);
+        assert_eq!(backend.iter().count(), 8);
+
+        backend.clear();
+        assert_eq!(backend.is_empty(), true);
+    }
+}
@@ -1,17 +1,111 @@
 pub mod array_linked_list;
+pub mod backend;
+pub mod cursor;
+pub mod disk_log;
 pub mod emb_entry;
 pub mod eviction_map;
+pub mod frequency_sketch;
+pub mod grouped;
+pub mod shard_table;
 pub mod sharded;
+pub mod slab;
+pub mod tiered_map;
 
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use persia_libs::{once_cell, parking_lot::RwLock, thiserror};
+use persia_libs::{hashbrown::HashMap, once_cell, thiserror, tracing};
 
+use backend::EmbeddingShardBackend;
+use cursor::{EmbeddingCursor, PinnedShards};
+use disk_log::DiskLog;
 use emb_entry::HashMapEmbeddingEntry;
-use eviction_map::EvictionMap;
-use persia_embedding_config::{EmbeddingParameterServerConfig, PersiaGlobalConfigError};
+use eviction_map::{EvictionMap, ExpiryClock};
+use grouped::GroupedBackend;
+use persia_embedding_config::{
+    EmbeddingConfig, EmbeddingParameterServerConfig, EmbeddingStorageBackend, EvictionPolicy,
+    PersiaGlobalConfigError,
+};
+use persia_metrics::{
+    Gauge, GaugeVec, IntCounter, PersiaMetricsManager, PersiaMetricsManagerError,
+};
 use persia_speedy::{Readable, Writable};
-use sharded::Sharded;
+use shard_table::{ShardReadGuard, ShardRouter, ShardTable, ShardWriteGuard};
+use slab::SlabPool;
+use tiered_map::TieredEvictionMap;
+
+// upper bound of the interval between two expiry sweeps
+const MAX_EXPIRY_SWEEP_INTERVAL_SEC: u64 = 60;
+// entries checked by the expiry sweep per acquisition of the shard lock
+const EXPIRY_SWEEP_BATCH_SIZE: usize = 4096;
+const MEMORY_REPORT_INTERVAL_SEC: u64 = 10;
+const DISK_COMPACTION_INTERVAL_SEC: u64 = 1;
+// records of the disk tier compacted per acquisition of the shard lock
+const DISK_COMPACTION_BATCH_SIZE: usize = 256;
+
+static METRICS_HOLDER: once_cell::sync::OnceCell<MetricsHolder> = once_cell::sync::OnceCell::new();
+
+struct MetricsHolder {
+    pub expired_entry_count: IntCounter,
+    pub memory_bytes: Gauge,
+    pub shard_memory_bytes: GaugeVec,
+    pub disk_bytes: Gauge,
+    pub slab_reserved_bytes: Gauge,
+    pub slab_free_bytes: Gauge,
+    pub disk_hit_count: IntCounter,
+    pub disk_miss_count: IntCounter,
+    pub spilled_entry_count: IntCounter,
+}
+
+impl MetricsHolder {
+    pub fn get() -> Result<&'static Self, PersiaMetricsManagerError> {
+        METRICS_HOLDER.get_or_try_init(|| {
+            let m = PersiaMetricsManager::get()?;
+            let holder = Self {
+                expired_entry_count: m.create_counter(
+                    "expired_entry_count",
+                    "num of embedding entries removed from embedding server by ttl expiry",
+                )?,
+                memory_bytes: m.create_gauge(
+                    "embedding_memory_bytes",
+                    "bytes held by the embedding entries of embedding server",
+                )?,
+                shard_memory_bytes: m.create_gauge_vec_with_label(
+                    "embedding_shard_memory_bytes",
+                    "bytes held by the embedding entries of each internal shard of embedding server",
+                    "shard",
+                )?,
+                disk_bytes: m.create_gauge(
+                    "embedding_disk_bytes",
+                    "bytes of the disk tier files of embedding server",
+                )?,
+                slab_reserved_bytes: m.create_gauge(
+                    "slab_reserved_bytes",
+                    "bytes reserved by the slab pool of embedding entries",
+                )?,
+                slab_free_bytes: m.create_gauge(
+                    "slab_free_bytes",
+                    "bytes of the slabs reserved but not used by any embedding entry",
+                )?,
+                disk_hit_count: m.create_counter(
+                    "disk_hit_count",
+                    "num of memory misses found on disk in embedding server",
+                )?,
+                disk_miss_count: m.create_counter(
+                    "disk_miss_count",
+                    "num of memory misses not found on disk either in embedding server",
+                )?,
+                spilled_entry_count: m.create_counter(
+                    "spilled_entry_count",
+                    "num of embedding entries spilled from memory to disk in embedding server",
+                )?,
+            };
+            Ok(holder)
+        })
+    }
+}
 
 #[derive(Clone, Readable, Writable, thiserror::Error, Debug)]
 pub enum PersiaEmbeddingHolderError {
@@ -19,83 +113,465 @@ pub enum PersiaEmbeddingHolderError {
     PersiaGlobalConfigError(#[from] PersiaGlobalConfigError),
     #[error("id not fonud")]
     IdNotFound,
+    #[error("disk tier error: {0}")]
+    DiskTierError(String),
+    #[error("num of internal shards must > 0")]
+    InvalidShardNum,
+    #[error("resharding in progress")]
+    ReshardingInProgress,
 }
 
 static PERSIA_EMBEDDING_HOLDER: once_cell::sync::OnceCell<PersiaEmbeddingHolder> =
     once_cell::sync::OnceCell::new();
 
+// spills the evicted entries of `memory` to a log under `disk_dir` if the disk tier is enabled
+fn new_shard_backend(
+    memory: EvictionMap<u64, HashMapEmbeddingEntry>,
+    disk_dir: &Option<PathBuf>,
+    log_name: String,
+) -> Result<Box<dyn EmbeddingShardBackend>, PersiaEmbeddingHolderError> {
+    match disk_dir {
+        Some(dir) => {
+            let disk = DiskLog::open(dir.join(log_name))
+                .map_err(|e| PersiaEmbeddingHolderError::DiskTierError(e.to_string()))?;
+            Ok(Box::new(TieredEvictionMap::new(memory, disk)))
+        }
+        None => Ok(Box::new(memory)),
+    }
+}
+
+// builds the backends of a shard table, the capacity of the server is split evenly over them
+// and the memory budget of a shard is split over its backends by their capacity
+#[derive(Clone)]
+struct ShardBackendBuilder {
+    capacity: usize,
+    memory_budget_bytes: usize,
+    eviction_policy: EvictionPolicy,
+    expiry_clock: ExpiryClock,
+    disk_dir: Option<PathBuf>,
+    prefix_mask: u64,
+    // capacity of the feature groups having a quota, keyed by feature group index prefix
+    feature_group_capacity: HashMap<u64, usize>,
+}
+
+impl ShardBackendBuilder {
+    fn build(
+        &self,
+        num_shards: usize,
+        shard_idx: usize,
+        generation: usize,
+    ) -> Result<Box<dyn EmbeddingShardBackend>, PersiaEmbeddingHolderError> {
+        let memory_budget_per_shard = self.memory_budget_bytes / num_shards;
+        let total_capacity = self
+            .feature_group_capacity
+            .values()
+            .fold(self.capacity, |acc, x| acc.saturating_add(*x));
+        // share of the memory budget of the shard for a backend of `capacity`
+        let memory_budget = |capacity: usize| {
+            (memory_budget_per_shard as u128 * capacity as u128 / total_capacity.max(1) as u128)
+                as usize
+        };
+
+        let memory = if memory_budget_per_shard > 0 {
+            EvictionMap::with_memory_budget_and_policy(
+                memory_budget(self.capacity),
+                self.eviction_policy,
+            )
+        } else {
+            EvictionMap::with_capacity_and_policy(self.capacity / num_shards, self.eviction_policy)
+        }
+        .with_expiry_clock(self.expiry_clock.clone());
+        let disk_dir = self.generation_disk_dir(generation);
+        let shared = new_shard_backend(memory, &disk_dir, format!("shard_{}.log", shard_idx))?;
+        if self.feature_group_capacity.is_empty() {
+            return Ok(shared);
+        }
+
+        let groups = self
+            .feature_group_capacity
+            .iter()
+            .map(|(index_prefix, capacity)| {
+                let mut memory = EvictionMap::with_capacity_and_policy(
+                    (capacity / num_shards).max(1),
+                    self.eviction_policy,
+                )
+                .with_expiry_clock(self.expiry_clock.clone());
+                if memory_budget_per_shard > 0 {
+                    memory = memory.with_memory_budget(memory_budget(*capacity));
+                }
+                let log_name = format!("shard_{}_group_{:x}.log", shard_idx, index_prefix);
+                Ok((
+                    *index_prefix,
+                    new_shard_backend(memory, &disk_dir, log_name)?,
+                ))
+            })
+            .collect::<Result<HashMap<_, _>, PersiaEmbeddingHolderError>>()?;
+        Ok(Box::new(GroupedBackend::new(
+            self.prefix_mask,
+            shared,
+            groups,
+        )))
+    }
+
+    // logs of different generations coexist during resharding
+    fn generation_disk_dir(&self, generation: usize) -> Option<PathBuf> {
+        self.disk_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("gen_{}", generation)))
+    }
+
+    // removes the logs of a shard table after its entries are migrated to the next one
+    fn remove_generation_disk_dir(&self, generation: usize) {
+        if let Some(dir) = self.generation_disk_dir(generation) {
+            if let Err(e) = std::fs::remove_dir_all(&dir) {
+                tracing::error!("failed to remove disk tier dir {:?}, {:?}", dir, e);
+            }
+        }
+    }
+
+    fn build_table(
+        &self,
+        num_shards: usize,
+        generation: usize,
+    ) -> Result<ShardTable, PersiaEmbeddingHolderError> {
+        let handles: Vec<std::thread::JoinHandle<_>> = (0..num_shards)
+            .map(|shard_idx| {
+                let builder = self.clone();
+                std::thread::spawn(move || builder.build(num_shards, shard_idx, generation))
+            })
+            .collect();
+
+        let backends = handles
+            .into_iter()
+            .map(|h| h.join().expect("failed to create map"))
+            .collect::<Result<Vec<_>, PersiaEmbeddingHolderError>>()?;
+        Ok(ShardTable::new(generation, backends))
+    }
+}
+
+/// The internal shard of a sign, locked by `read` or `write`.
+pub struct ShardRef<'a> {
+    router: &'a ShardRouter,
+    sign: u64,
+}
+
+impl<'a> ShardRef<'a> {
+    pub fn read(&self) -> ShardReadGuard {
+        self.router.read(&self.sign)
+    }
+
+    pub fn write(&self) -> ShardWriteGuard {
+        self.router.write(&self.sign)
+    }
+}
+
 #[derive(Clone)]
 pub struct PersiaEmbeddingHolder {
-    inner: Arc<Sharded<EvictionMap<u64, HashMapEmbeddingEntry>, u64>>,
+    router: Arc<ShardRouter>,
+    builder: Arc<ShardBackendBuilder>,
+    resharding: Arc<AtomicBool>,
+    // gradient updates received, the expiry clock of the entries when expiring by steps
+    steps: Arc<AtomicU64>,
 }
 
 impl PersiaEmbeddingHolder {
-    pub fn get() -> Result<PersiaEmbeddingHolder, PersiaEmbeddingHolderError> {
-        let singleton = PERSIA_EMBEDDING_HOLDER.get_or_try_init(|| {
-            let config = EmbeddingParameterServerConfig::get()?;
-
-            let bucket_size = config.num_hashmap_internal_shards;
-            let cpapacity_per_bucket = config.capacity / bucket_size;
+    /// Creates a holder by `config`. The feature groups with a capacity quota in
+    /// `embedding_config` are evicted apart from the other feature groups, all feature groups
+    /// share the capacity of `config` if no embedding config is given.
+    pub fn new(
+        config: &EmbeddingParameterServerConfig,
+        embedding_config: Option<&EmbeddingConfig>,
+    ) -> Result<PersiaEmbeddingHolder, PersiaEmbeddingHolderError> {
+        let steps = Arc::new(AtomicU64::new(0));
 
-            let handles: Vec<std::thread::JoinHandle<_>> = (0..bucket_size)
-                .map(|_| {
-                    std::thread::spawn(move || {
-                        EvictionMap::with_capacity(cpapacity_per_bucket as usize)
-                    })
+        let builder = ShardBackendBuilder {
+            capacity: config.capacity,
+            memory_budget_bytes: config.memory_budget_bytes as usize,
+            eviction_policy: config.eviction_policy,
+            expiry_clock: match config.ttl_steps > 0 {
+                true => ExpiryClock::Steps(steps.clone()),
+                false => ExpiryClock::UnixSec,
+            },
+            disk_dir: match &config.storage_backend {
+                EmbeddingStorageBackend::Memory => None,
+                EmbeddingStorageBackend::TieredDisk(disk_config) => {
+                    Some(PathBuf::from(&disk_config.path))
+                }
+            },
+            prefix_mask: embedding_config.map_or(0, |x| x.feature_index_prefix_mask()),
+            feature_group_capacity: embedding_config
+                .map(|x| {
+                    x.get_feature_group_capacity_by_index_prefix()
+                        .into_iter()
+                        .collect()
                 })
-                .collect();
+                .unwrap_or_default(),
+        };
+        let table = builder.build_table(config.num_hashmap_internal_shards, 0)?;
 
-            let maps: Vec<_> = handles
-                .into_iter()
-                .map(|h| RwLock::new(h.join().expect("failed to create map")))
-                .collect();
+        let holder = PersiaEmbeddingHolder {
+            router: Arc::new(ShardRouter::new(table)),
+            builder: Arc::new(builder),
+            resharding: Arc::new(AtomicBool::new(false)),
+            steps,
+        };
+        if config.ttl_sec > 0 {
+            let sweep_interval = config.ttl_sec.min(MAX_EXPIRY_SWEEP_INTERVAL_SEC);
+            holder.spawn_expiry_sweeper(config.ttl_sec, Duration::from_secs(sweep_interval));
+        } else if config.ttl_steps > 0 {
+            let sweep_interval = Duration::from_secs(MAX_EXPIRY_SWEEP_INTERVAL_SEC);
+            holder.spawn_expiry_sweeper(config.ttl_steps, sweep_interval);
+        }
+        holder.spawn_memory_reporter();
+        if holder.builder.disk_dir.is_some() {
+            holder.spawn_disk_compactor();
+        }
+        Ok(holder)
+    }
 
-            let sharded = Sharded {
-                inner: maps,
-                phantom: std::marker::PhantomData::default(),
-            };
-            Ok(PersiaEmbeddingHolder {
-                inner: Arc::new(sharded),
+    /// Creates the holder returned by `get`, see `new`. The holder created first is kept if
+    /// called more than once.
+    pub fn set(
+        config: &EmbeddingParameterServerConfig,
+        embedding_config: Option<&EmbeddingConfig>,
+    ) -> Result<PersiaEmbeddingHolder, PersiaEmbeddingHolderError> {
+        PERSIA_EMBEDDING_HOLDER
+            .get_or_try_init(|| Self::new(config, embedding_config))
+            .cloned()
+    }
+
+    /// The holder created by `set`, or a holder created by the global
+    /// `EmbeddingParameterServerConfig` without feature group quotas if `set` is not called.
+    pub fn get() -> Result<PersiaEmbeddingHolder, PersiaEmbeddingHolderError> {
+        PERSIA_EMBEDDING_HOLDER
+            .get_or_try_init(|| {
+                let config = EmbeddingParameterServerConfig::get()?;
+                Self::new(&config, None)
             })
-        });
-        match singleton {
-            Ok(s) => Ok(s.clone()),
-            Err(e) => Err(e),
-        }
+            .cloned()
     }
 
     pub fn num_total_signs(&self) -> usize {
-        self.inner
-            .inner
+        self.router
+            .all_shards()
             .iter()
             .map(|x| x.read().len())
             .sum::<usize>()
     }
 
     pub fn num_internal_shards(&self) -> usize {
-        self.inner.inner.len()
+        self.router.current().len()
     }
 
     pub fn capacity(&self) -> usize {
-        self.inner
-            .inner
+        self.router
+            .current()
+            .shards()
             .iter()
             .map(|x| x.read().capacity())
+            .fold(0, usize::saturating_add)
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.router
+            .all_shards()
+            .iter()
+            .map(|x| x.read().memory_bytes())
             .sum::<usize>()
     }
 
+    pub fn disk_bytes(&self) -> u64 {
+        self.router
+            .all_shards()
+            .iter()
+            .map(|x| x.read().disk_bytes())
+            .sum::<u64>()
+    }
+
     pub fn clear(&self) {
-        self.inner.inner.iter().for_each(|x| x.write().clear());
+        self.router
+            .all_shards()
+            .iter()
+            .for_each(|x| x.write().clear());
     }
 
-    pub fn shard(&self, key: &u64) -> &RwLock<EvictionMap<u64, HashMapEmbeddingEntry>> {
-        self.inner.shard(key)
+    pub fn shard(&self, key: &u64) -> ShardRef<'_> {
+        ShardRef {
+            router: &self.router,
+            sign: *key,
+        }
     }
 
-    pub fn get_shard_by_index(
+    /// Inserts an entry loaded from a checkpoint, an incremental update or a `set_embedding`
+    /// request. Serialized entries are in f32 whatever the storage dtype of their slot, so the
+    /// entry is converted to the storage dtype of its slot first.
+    pub fn insert_restored(
         &self,
-        index: usize,
-    ) -> &RwLock<EvictionMap<u64, HashMapEmbeddingEntry>> {
-        self.inner.get_shard_by_index(index)
+        mut entry: HashMapEmbeddingEntry,
+        embedding_config: &EmbeddingConfig,
+    ) {
+        let sign = entry.sign();
+        entry.convert_storage_dtype(embedding_config.get_storage_dtype_by_sign(sign));
+        self.shard(&sign).write().insert(sign, entry);
+    }
+
+    /// Pins the internal shards against resharding for walking them, waits for a running
+    /// resharding to finish.
+    pub fn pin_shards(&self) -> PinnedShards {
+        let (pin, table) = self.router.pin();
+        PinnedShards::new(pin, table)
+    }
+
+    /// Walks all entries of the holder in batches of at most `batch_size` entries.
+    pub fn cursor(&self, batch_size: usize) -> EmbeddingCursor {
+        self.pin_shards().cursor(batch_size)
+    }
+
+    /// Owned copies of the entries of `signs`, see `PinnedShards::get_entries`.
+    pub fn get_entries(&self, signs: &[u64]) -> Vec<HashMapEmbeddingEntry> {
+        self.pin_shards().get_entries(signs)
+    }
+
+    pub fn is_resharding(&self) -> bool {
+        self.resharding.load(Ordering::Acquire)
+    }
+
+    /// Changes the number of internal shards. The entries are migrated to the new shards by a
+    /// background thread, lookups and updates keep working during the migration. The migration
+    /// waits for the pinned shards to be dropped before it starts.
+    pub fn reshard(&self, num_internal_shards: usize) -> Result<(), PersiaEmbeddingHolderError> {
+        if num_internal_shards == 0 {
+            return Err(PersiaEmbeddingHolderError::InvalidShardNum);
+        }
+        if self
+            .resharding
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(PersiaEmbeddingHolderError::ReshardingInProgress);
+        }
+
+        let generation = self.router.current().generation() + 1;
+        let table = match self.builder.build_table(num_internal_shards, generation) {
+            Ok(table) => table,
+            Err(e) => {
+                self.resharding.store(false, Ordering::Release);
+                return Err(e);
+            }
+        };
+
+        let holder = self.clone();
+        std::thread::spawn(move || {
+            let start_time = std::time::Instant::now();
+            let old = holder.router.begin_migration(table);
+            tracing::info!(
+                "start to reshard embedding holder from {} to {} internal shards",
+                old.len(),
+                num_internal_shards
+            );
+            let num_migrated: usize = (0..old.len())
+                .map(|idx| holder.router.migrate_shard(&old, idx))
+                .sum();
+            holder.router.finish_migration();
+            let old_generation = old.generation();
+            drop(old);
+            holder.builder.remove_generation_disk_dir(old_generation);
+            holder.resharding.store(false, Ordering::Release);
+            tracing::info!(
+                "reshard embedding holder compelete, {} entries migrated in {:?}",
+                num_migrated,
+                start_time.elapsed()
+            );
+        });
+        Ok(())
+    }
+
+    /// Advances the step clock the entries expire on when `ttl_steps` is set, called once per
+    /// gradient update received.
+    pub fn advance_step(&self) {
+        self.steps.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Removes the entries not accessed within `ttl` on the expiry clock of the holder. Each
+    /// shard is checked in batches, releasing its lock between them so that lookups and
+    /// updates are not blocked for the whole sweep.
+    pub fn evict_expired(&self, ttl: u64) -> usize {
+        self.router
+            .all_shards()
+            .iter()
+            .map(|x| {
+                let num_batches = x.read().len() / EXPIRY_SWEEP_BATCH_SIZE + 1;
+                (0..num_batches)
+                    .map(|_| x.write().evict_expired(ttl, EXPIRY_SWEEP_BATCH_SIZE))
+                    .sum::<usize>()
+            })
+            .sum::<usize>()
+    }
+
+    fn spawn_expiry_sweeper(&self, ttl: u64, sweep_interval: Duration) {
+        let holder = self.clone();
+        std::thread::spawn(move || {
+            tracing::info!(
+                "background thread for expiring embedding entries start, ttl: {}",
+                ttl
+            );
+            loop {
+                std::thread::sleep(sweep_interval);
+                let num_expired = holder.evict_expired(ttl);
+                tracing::debug!("{} embedding entries expired", num_expired);
+                if let Ok(m) = MetricsHolder::get() {
+                    m.expired_entry_count.inc_by(num_expired as u64);
+                }
+            }
+        });
+    }
+
+    /// Compacts the disk tier of each shard in batches, releasing its lock between them so
+    /// that spilling entries to disk is not blocked by rewriting the log.
+    pub fn compact_disk(&self) {
+        self.router.all_shards().iter().for_each(|x| {
+            while x.write().compact_disk(DISK_COMPACTION_BATCH_SIZE) {}
+        });
+    }
+
+    fn spawn_disk_compactor(&self) {
+        let holder = self.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(DISK_COMPACTION_INTERVAL_SEC));
+            holder.compact_disk();
+        });
+    }
+
+    // reports the memory of the internal shards of the current shard table by shard index, the
+    // shards of a table being migrated from are only counted in the total
+    fn spawn_memory_reporter(&self) {
+        let holder = self.clone();
+        std::thread::spawn(move || {
+            let mut num_reported_shards = 0;
+            loop {
+                std::thread::sleep(Duration::from_secs(MEMORY_REPORT_INTERVAL_SEC));
+                if let Ok(m) = MetricsHolder::get() {
+                    let table = holder.router.current();
+                    table.shards().iter().enumerate().for_each(|(idx, shard)| {
+                        m.shard_memory_bytes
+                            .with_label_values(&[idx.to_string().as_str()])
+                            .set(shard.read().memory_bytes() as f64);
+                    });
+                    // shards gone after resharding to fewer internal shards
+                    (table.len()..num_reported_shards).for_each(|idx| {
+                        let _ = m
+                            .shard_memory_bytes
+                            .remove_label_values(&[idx.to_string().as_str()]);
+                    });
+                    num_reported_shards = table.len();
+
+                    m.memory_bytes.set(holder.memory_bytes() as f64);
+                    m.disk_bytes.set(holder.disk_bytes() as f64);
+                    let slab_pool = SlabPool::get();
+                    m.slab_reserved_bytes.set(slab_pool.reserved_bytes() as f64);
+                    m.slab_free_bytes.set(slab_pool.free_bytes() as f64);
+                }
+            }
+        });
     }
 }
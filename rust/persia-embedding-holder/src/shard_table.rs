@@ -0,0 +1,299 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use persia_libs::parking_lot::{
+    lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard},
+    Condvar, Mutex, RawRwLock, RwLock,
+};
+
+use crate::backend::EmbeddingShardBackend;
+use crate::sharded::get_index;
+
+pub type ShardReadGuard = ArcRwLockReadGuard<RawRwLock, ShardCell>;
+pub type ShardWriteGuard = ArcRwLockWriteGuard<RawRwLock, ShardCell>;
+
+/// An internal shard of `PersiaEmbeddingHolder`, dereferences to its backend.
+pub struct ShardCell {
+    backend: Box<dyn EmbeddingShardBackend>,
+    // generation of the shard table the shard belongs to
+    generation: usize,
+    // set once the entries of the shard have been migrated to the next shard table
+    retired: bool,
+}
+
+impl Deref for ShardCell {
+    type Target = dyn EmbeddingShardBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend.as_ref()
+    }
+}
+
+impl DerefMut for ShardCell {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.backend.as_mut()
+    }
+}
+
+pub(crate) struct ShardTable {
+    generation: usize,
+    shards: Vec<Arc<RwLock<ShardCell>>>,
+}
+
+impl ShardTable {
+    pub(crate) fn new(generation: usize, backends: Vec<Box<dyn EmbeddingShardBackend>>) -> Self {
+        let shards = backends
+            .into_iter()
+            .map(|backend| {
+                Arc::new(RwLock::new(ShardCell {
+                    backend,
+                    generation,
+                    retired: false,
+                }))
+            })
+            .collect();
+        Self { generation, shards }
+    }
+
+    pub(crate) fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub(crate) fn shard(&self, sign: &u64) -> &Arc<RwLock<ShardCell>> {
+        &self.shards[get_index(sign, self.shards.len())]
+    }
+
+    pub(crate) fn shard_by_index(&self, index: usize) -> &Arc<RwLock<ShardCell>> {
+        &self.shards[index]
+    }
+
+    pub(crate) fn shards(&self) -> &[Arc<RwLock<ShardCell>>] {
+        self.shards.as_slice()
+    }
+}
+
+struct ShardTables {
+    current: Arc<ShardTable>,
+    // the previous table while its entries are migrated to `current`
+    migrating_from: Option<Arc<ShardTable>>,
+}
+
+#[derive(Default)]
+struct WalkState {
+    num_walkers: usize,
+    migrating: bool,
+}
+
+/// Keeps a migration from starting while the shard table is walked, see `ShardRouter::pin`.
+pub(crate) struct WalkPin {
+    router: Arc<ShardRouter>,
+}
+
+impl Drop for WalkPin {
+    fn drop(&mut self) {
+        let mut state = self.router.walk_state.lock();
+        state.num_walkers -= 1;
+        self.router.walk_cond.notify_all();
+    }
+}
+
+/// Routes signs to the internal shards across resharding.
+///
+/// A resharding creates a new shard table and migrates the shards of the previous table one by
+/// one. A shard of the previous table is locked for writing during its migration and retired
+/// afterwards, a sign is looked up in the previous table until its shard there is retired. So
+/// every sign lives in exactly one shard at any time and lookups and updates stay correct
+/// during the migration.
+pub(crate) struct ShardRouter {
+    tables: RwLock<ShardTables>,
+    walk_state: Mutex<WalkState>,
+    walk_cond: Condvar,
+}
+
+impl ShardRouter {
+    pub(crate) fn new(table: ShardTable) -> Self {
+        Self {
+            tables: RwLock::new(ShardTables {
+                current: Arc::new(table),
+                migrating_from: None,
+            }),
+            walk_state: Mutex::new(WalkState::default()),
+            walk_cond: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Arc<ShardTable> {
+        self.tables.read().current.clone()
+    }
+
+    // the shard holding `sign`, unless it is in a table of `retired_generation`
+    fn locate(&self, sign: &u64, retired_generation: Option<usize>) -> Arc<RwLock<ShardCell>> {
+        let tables = self.tables.read();
+        match &tables.migrating_from {
+            Some(old) if Some(old.generation) != retired_generation => old.shard(sign).clone(),
+            _ => tables.current.shard(sign).clone(),
+        }
+    }
+
+    pub(crate) fn read(&self, sign: &u64) -> ShardReadGuard {
+        let mut retired_generation = None;
+        loop {
+            let shard = self.locate(sign, retired_generation).read_arc();
+            if !shard.retired {
+                return shard;
+            }
+            retired_generation = Some(shard.generation);
+        }
+    }
+
+    pub(crate) fn write(&self, sign: &u64) -> ShardWriteGuard {
+        let mut retired_generation = None;
+        loop {
+            let shard = self.locate(sign, retired_generation).write_arc();
+            if !shard.retired {
+                return shard;
+            }
+            retired_generation = Some(shard.generation);
+        }
+    }
+
+    /// The shards of all tables, including the ones being migrated.
+    pub(crate) fn all_shards(&self) -> Vec<Arc<RwLock<ShardCell>>> {
+        let tables = self.tables.read();
+        tables
+            .migrating_from
+            .iter()
+            .chain(std::iter::once(&tables.current))
+            .flat_map(|table| table.shards().iter().cloned())
+            .collect()
+    }
+
+    /// Pins the current shard table, migrations wait for all pins to be dropped and pinning
+    /// waits for a running migration to finish.
+    pub(crate) fn pin(self: &Arc<Self>) -> (WalkPin, Arc<ShardTable>) {
+        let mut state = self.walk_state.lock();
+        while state.migrating {
+            self.walk_cond.wait(&mut state);
+        }
+        state.num_walkers += 1;
+        let pin = WalkPin {
+            router: self.clone(),
+        };
+        (pin, self.current())
+    }
+
+    /// Makes `table` the current table once no walker pins the current one, the previous table
+    /// stays in use until `finish_migration`.
+    pub(crate) fn begin_migration(&self, table: ShardTable) -> Arc<ShardTable> {
+        let mut state = self.walk_state.lock();
+        assert!(!state.migrating, "shard table is being migrated already");
+        state.migrating = true;
+        while state.num_walkers > 0 {
+            self.walk_cond.wait(&mut state);
+        }
+        drop(state);
+
+        let mut tables = self.tables.write();
+        let old = std::mem::replace(&mut tables.current, Arc::new(table));
+        tables.migrating_from = Some(old.clone());
+        old
+    }
+
+    /// Moves the entries of a shard of the previous table to the current table, returns the
+    /// number of entries moved. The entries keep their eviction bookkeeping, see
+    /// `EmbeddingShardBackend::drain_with_meta`.
+    pub(crate) fn migrate_shard(&self, old: &ShardTable, index: usize) -> usize {
+        let current = self.current();
+        let mut shard = old.shard_by_index(index).write();
+        if shard.retired {
+            return 0;
+        }
+        let entries = shard.drain_with_meta();
+        let num_entries = entries.len();
+        entries.into_iter().for_each(|(entry, meta)| {
+            let sign = entry.sign();
+            let _ = current
+                .shard(&sign)
+                .write()
+                .insert_with_meta(sign, entry, meta);
+        });
+        shard.retired = true;
+        num_entries
+    }
+
+    pub(crate) fn finish_migration(&self) {
+        self.tables.write().migrating_from = None;
+        let mut state = self.walk_state.lock();
+        state.migrating = false;
+        self.walk_cond.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod shard_table_tests {
+    use super::*;
+    use crate::emb_entry::HashMapEmbeddingEntry;
+    use crate::eviction_map::EvictionMap;
+    use persia_embedding_config::InitializationMethod;
+
+    fn new_table(generation: usize, num_shards: usize) -> ShardTable {
+        let backends = (0..num_shards)
+            .map(|_| {
+                let backend: Box<dyn EmbeddingShardBackend> =
+                    Box::new(EvictionMap::with_capacity(1000));
+                backend
+            })
+            .collect();
+        ShardTable::new(generation, backends)
+    }
+
+    #[test]
+    fn test_migration() {
+        let router = Arc::new(ShardRouter::new(new_table(0, 2)));
+        let initialization = InitializationMethod::default();
+        for sign in 0..100 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, sign, sign);
+            router.write(&sign).insert(sign, entry);
+        }
+
+        let old = router.begin_migration(new_table(1, 5));
+        assert_eq!(router.all_shards().len(), 7);
+        assert_eq!(router.migrate_shard(&old, 0) > 0, true);
+
+        // signs are found whether their shard is migrated or not
+        assert_eq!(
+            (0..100).all(|sign| router.read(&sign).get(&sign).is_some()),
+            true
+        );
+        for sign in 100..200 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, sign, sign);
+            router.write(&sign).insert(sign, entry);
+        }
+        assert_eq!(router.write(&7).remove(&7).is_some(), true);
+
+        router.migrate_shard(&old, 1);
+        router.finish_migration();
+        drop(old);
+
+        let current = router.current();
+        assert_eq!(current.generation(), 1);
+        assert_eq!(current.len(), 5);
+        let num_signs: usize = router.all_shards().iter().map(|s| s.read().len()).sum();
+        assert_eq!(num_signs, 199);
+        assert_eq!(
+            (0..200).all(|sign| {
+                let found = router.read(&sign).get(&sign).is_some();
+                found == (sign != 7)
+            }),
+            true
+        );
+
+        let (pin, table) = router.pin();
+        assert_eq!(table.generation(), 1);
+        drop(pin);
+    }
+}
@@ -0,0 +1,364 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use persia_libs::{
+    hashbrown::HashMap,
+    once_cell,
+    parking_lot::Mutex,
+    serde::{Deserialize, Deserializer, Serialize, Serializer},
+};
+use persia_speedy::{Context, Readable, Writable};
+
+// bytes of the contiguous chunk every size class carves its slabs from
+const CHUNK_BYTES: usize = 4 << 20;
+// number of independent parts of the pool, each thread allocates from one of them
+const NUM_STRIPES: usize = 64;
+
+static SLAB_POOL: once_cell::sync::OnceCell<SlabPool> = once_cell::sync::OnceCell::new();
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static STRIPE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// the stripe the current thread allocates from, assigned round robin on first use
+fn current_stripe() -> usize {
+    STRIPE.with(|stripe| match stripe.get() {
+        Some(idx) => idx,
+        None => {
+            let idx = NEXT_STRIPE.fetch_add(1, Ordering::Relaxed) % NUM_STRIPES;
+            stripe.set(Some(idx));
+            idx
+        }
+    })
+}
+
+struct Chunk {
+    slabs: Box<[f32]>,
+    // slabs from `num_used` on have never been handed out
+    num_used: usize,
+    // recycled slabs, by their index in the chunk
+    free: Vec<u32>,
+    num_live: usize,
+}
+
+struct SizeClass {
+    slab_len: usize,
+    slabs_per_chunk: usize,
+    // keyed by the address of the chunk
+    chunks: BTreeMap<usize, Chunk>,
+    // chunks having slabs to hand out, the lowest address is used first to keep slabs packed
+    available: BTreeSet<usize>,
+}
+
+impl SizeClass {
+    fn new(slab_len: usize) -> Self {
+        Self {
+            slab_len,
+            slabs_per_chunk: (CHUNK_BYTES / (slab_len * std::mem::size_of::<f32>())).max(1),
+            chunks: BTreeMap::new(),
+            available: BTreeSet::new(),
+        }
+    }
+
+    fn alloc(&mut self) -> NonNull<f32> {
+        let base = match self.available.iter().next() {
+            Some(base) => *base,
+            None => self.grow(),
+        };
+        let chunk = self.chunks.get_mut(&base).unwrap();
+        let idx = match chunk.free.pop() {
+            Some(idx) => idx as usize,
+            None => {
+                chunk.num_used += 1;
+                chunk.num_used - 1
+            }
+        };
+        chunk.num_live += 1;
+        if chunk.num_live == self.slabs_per_chunk {
+            self.available.remove(&base);
+        }
+        unsafe { NonNull::new_unchecked(chunk.slabs.as_mut_ptr().add(idx * self.slab_len)) }
+    }
+
+    // returns the slab to its chunk, a chunk left without live slabs is released unless it is
+    // the last one having room
+    fn release(&mut self, ptr: NonNull<f32>) {
+        let addr = ptr.as_ptr() as usize;
+        let (base, chunk) = self
+            .chunks
+            .range_mut(..=addr)
+            .next_back()
+            .map(|(base, chunk)| (*base, chunk))
+            .expect("slab released to a size class it is not allocated from");
+        let idx = (addr - base) / (self.slab_len * std::mem::size_of::<f32>());
+        chunk.free.push(idx as u32);
+        chunk.num_live -= 1;
+
+        let num_other_available = self.available.len() - self.available.contains(&base) as usize;
+        if chunk.num_live == 0 && num_other_available > 0 {
+            self.chunks.remove(&base);
+            self.available.remove(&base);
+        } else {
+            self.available.insert(base);
+        }
+    }
+
+    fn grow(&mut self) -> usize {
+        let slabs = vec![0f32; self.slab_len * self.slabs_per_chunk].into_boxed_slice();
+        let base = slabs.as_ptr() as usize;
+        self.chunks.insert(
+            base,
+            Chunk {
+                slabs,
+                num_used: 0,
+                free: Vec::new(),
+                num_live: 0,
+            },
+        );
+        self.available.insert(base);
+        base
+    }
+
+    fn reserved_bytes(&self) -> usize {
+        self.chunks.len() * self.slabs_per_chunk * self.slab_len * std::mem::size_of::<f32>()
+    }
+
+    fn free_bytes(&self) -> usize {
+        self.chunks
+            .values()
+            .map(|chunk| (self.slabs_per_chunk - chunk.num_live) * self.slab_len)
+            .sum::<usize>()
+            * std::mem::size_of::<f32>()
+    }
+}
+
+/// Pool of fixed size slabs, one size class per slab length.
+///
+/// The pool is split into stripes so that threads allocating at the same time rarely wait for
+/// each other. A slab is returned to the stripe it is allocated from, and chunks are released
+/// once all of their slabs are returned.
+pub struct SlabPool {
+    stripes: Vec<Mutex<HashMap<usize, SizeClass>>>,
+}
+
+impl SlabPool {
+    pub fn get() -> &'static Self {
+        SLAB_POOL.get_or_init(|| Self {
+            stripes: (0..NUM_STRIPES)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+        })
+    }
+
+    fn alloc(&self, slab_len: usize) -> (NonNull<f32>, u32) {
+        let stripe = current_stripe();
+        let ptr = self.stripes[stripe]
+            .lock()
+            .entry(slab_len)
+            .or_insert_with(|| SizeClass::new(slab_len))
+            .alloc();
+        (ptr, stripe as u32)
+    }
+
+    fn release(&self, stripe: u32, slab_len: usize, ptr: NonNull<f32>) {
+        self.stripes[stripe as usize]
+            .lock()
+            .get_mut(&slab_len)
+            .expect("slab released to a size class it is not allocated from")
+            .release(ptr);
+    }
+
+    /// Bytes reserved by the chunks of all size classes, including the free slabs.
+    pub fn reserved_bytes(&self) -> usize {
+        self.stripes
+            .iter()
+            .map(|stripe| {
+                stripe
+                    .lock()
+                    .values()
+                    .map(|class| class.reserved_bytes())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Bytes of the slabs which are reserved but not handed out.
+    pub fn free_bytes(&self) -> usize {
+        self.stripes
+            .iter()
+            .map(|stripe| {
+                stripe
+                    .lock()
+                    .values()
+                    .map(|class| class.free_bytes())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+/// A fixed length `[f32]` allocated from the `SlabPool`, the slab is recycled on drop.
+pub struct Slab {
+    ptr: NonNull<f32>,
+    len: u32,
+    // stripe of the pool the slab is allocated from
+    stripe: u32,
+}
+
+// a slab is exclusively owned like a Box<[f32]>
+unsafe impl Send for Slab {}
+unsafe impl Sync for Slab {}
+
+impl Slab {
+    pub fn zeroed(len: usize) -> Self {
+        let mut slab = Self::uninit(len);
+        slab.iter_mut().for_each(|x| *x = 0.0);
+        slab
+    }
+
+    pub fn from_slice(src: &[f32]) -> Self {
+        let mut slab = Self::uninit(src.len());
+        slab.copy_from_slice(src);
+        slab
+    }
+
+    // the content of a recycled slab is left over from its previous owner
+    fn uninit(len: usize) -> Self {
+        let (ptr, stripe) = match len {
+            0 => (NonNull::dangling(), 0),
+            _ => SlabPool::get().alloc(len),
+        };
+        Self {
+            ptr,
+            len: len as u32,
+            stripe,
+        }
+    }
+}
+
+impl Drop for Slab {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            SlabPool::get().release(self.stripe, self.len as usize, self.ptr);
+        }
+    }
+}
+
+impl Deref for Slab {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len as usize) }
+    }
+}
+
+impl DerefMut for Slab {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len as usize) }
+    }
+}
+
+impl Clone for Slab {
+    fn clone(&self) -> Self {
+        Self::from_slice(self)
+    }
+}
+
+impl fmt::Debug for Slab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+// (de)serialized the same way as a Vec<f32>
+impl Serialize for Slab {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Slab {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let v: Vec<f32> = Deserialize::deserialize(deserializer)?;
+        Ok(Self::from_slice(&v))
+    }
+}
+
+impl<'a, C: Context> Readable<'a, C> for Slab {
+    #[inline]
+    fn read_from<R: persia_speedy::Reader<'a, C>>(reader: &mut R) -> Result<Self, C::Error> {
+        let v: Vec<f32> = reader.read_value()?;
+        Ok(Self::from_slice(&v))
+    }
+
+    #[inline]
+    fn minimum_bytes_needed() -> usize {
+        <Vec<f32> as Readable<'a, C>>::minimum_bytes_needed()
+    }
+}
+
+impl<C: Context> Writable<C> for Slab {
+    #[inline]
+    fn write_to<W: ?Sized + persia_speedy::Writer<C>>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), C::Error> {
+        self.deref().write_to(writer)
+    }
+}
+
+#[cfg(test)]
+mod slab_tests {
+    use super::*;
+
+    #[test]
+    fn test_recycle() {
+        // an unusual length keeps this size class away from the other tests
+        let slab = Slab::from_slice(&[1.0; 37]);
+        let ptr = slab.ptr;
+        drop(slab);
+
+        let slab = Slab::zeroed(37);
+        assert_eq!(slab.ptr, ptr);
+        assert_eq!(slab.iter().all(|x| *x == 0.0), true);
+
+        let other = Slab::zeroed(37);
+        assert_ne!(other.ptr, ptr);
+        assert_eq!(SlabPool::get().reserved_bytes() > 0, true);
+    }
+
+    #[test]
+    fn test_release_chunks() {
+        let mut class = SizeClass::new(1021);
+        let slabs: Vec<_> = (0..class.slabs_per_chunk * 3)
+            .map(|_| class.alloc())
+            .collect();
+        assert_eq!(class.chunks.len(), 3);
+        assert_eq!(class.free_bytes(), 0);
+
+        // chunks without live slabs are released except the last one with room
+        slabs.iter().for_each(|ptr| class.release(*ptr));
+        assert_eq!(class.chunks.len(), 1);
+        assert_eq!(class.free_bytes(), class.reserved_bytes());
+
+        // the slabs of the kept chunk are handed out again
+        let ptr = class.alloc();
+        assert_eq!(slabs.contains(&ptr), true);
+        assert_eq!(class.chunks.len(), 1);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let slab = Slab::from_slice(&[1.0, 2.0, 3.0]);
+        let bytes = slab.write_to_vec().unwrap();
+        assert_eq!(bytes, vec![1.0f32, 2.0, 3.0].write_to_vec().unwrap());
+
+        let read = Slab::read_from_buffer(&bytes).unwrap();
+        assert_eq!(read.deref(), &[1.0, 2.0, 3.0]);
+    }
+}
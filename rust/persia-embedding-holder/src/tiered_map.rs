@@ -0,0 +1,259 @@
+use std::borrow::Cow;
+
+use persia_libs::tracing;
+
+use crate::backend::{EmbeddingShardBackend, EvictionMeta};
+use crate::disk_log::DiskLog;
+use crate::emb_entry::HashMapEmbeddingEntry;
+use crate::eviction_map::EvictionMap;
+use crate::MetricsHolder;
+
+/// An `EvictionMap` backed by a `DiskLog`. Entries evicted from memory are spilled to disk
+/// instead of being dropped, and a memory miss falls back to disk.
+pub struct TieredEvictionMap {
+    pub memory: EvictionMap<u64, HashMapEmbeddingEntry>,
+    pub disk: DiskLog,
+}
+
+impl TieredEvictionMap {
+    pub fn new(memory: EvictionMap<u64, HashMapEmbeddingEntry>, disk: DiskLog) -> Self {
+        Self { memory, disk }
+    }
+
+    // moves the entry of `sign` from disk to memory if it is not in memory
+    fn promote(&mut self, sign: &u64) {
+        if self.memory.hashmap.contains_key(sign) {
+            return;
+        }
+        let entry = match self.disk.take(sign) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::error!("failed to read sign {} from disk, {:?}", sign, e);
+                None
+            }
+        };
+        record_disk_lookup(entry.is_some());
+        if let Some(entry) = entry {
+            let (_, evicted) = self.memory.insert(*sign, entry);
+            self.spill(evicted);
+        }
+    }
+
+    // writes the evicted entries to disk, returns the entries failed to be written
+    fn spill(&mut self, evicted: Vec<HashMapEmbeddingEntry>) -> Vec<HashMapEmbeddingEntry> {
+        let disk = &mut self.disk;
+        let spilled_at = self.memory.expiry_clock().now();
+        let num_evicted = evicted.len();
+        let dropped: Vec<_> = evicted
+            .into_iter()
+            .filter(|entry| match disk.put(entry, spilled_at) {
+                Ok(_) => false,
+                Err(e) => {
+                    tracing::error!("failed to spill sign {} to disk, {:?}", entry.sign(), e);
+                    true
+                }
+            })
+            .collect();
+        if let Ok(m) = MetricsHolder::get() {
+            m.spilled_entry_count
+                .inc_by((num_evicted - dropped.len()) as u64);
+        }
+        dropped
+    }
+}
+
+impl EmbeddingShardBackend for TieredEvictionMap {
+    /// Entries on disk are returned as owned copies without moving them to memory.
+    fn get(&self, sign: &u64) -> Option<Cow<'_, HashMapEmbeddingEntry>> {
+        if let Some(entry) = self.memory.get(sign) {
+            return Some(Cow::Borrowed(entry));
+        }
+        let entry = match self.disk.get(sign) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::error!("failed to read sign {} from disk, {:?}", sign, e);
+                None
+            }
+        };
+        record_disk_lookup(entry.is_some());
+        entry.map(Cow::Owned)
+    }
+
+    fn get_mut(&mut self, sign: &u64) -> Option<&mut HashMapEmbeddingEntry> {
+        self.promote(sign);
+        self.memory.get_mut(sign)
+    }
+
+    fn get_refresh(&mut self, sign: &u64) -> Option<&HashMapEmbeddingEntry> {
+        self.promote(sign);
+        self.memory.get_refresh(sign)
+    }
+
+    /// Evicted entries are only dropped when they can not be spilled to disk.
+    fn insert(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+    ) -> (Option<HashMapEmbeddingEntry>, Vec<HashMapEmbeddingEntry>) {
+        // the record on disk is stale from now on
+        self.disk.remove(&sign);
+        let (old, evicted) = self.memory.insert(sign, entry);
+        (old, self.spill(evicted))
+    }
+
+    fn remove(&mut self, sign: &u64) -> Option<HashMapEmbeddingEntry> {
+        if let Some(entry) = self.memory.remove(sign) {
+            return Some(entry);
+        }
+        match self.disk.take(sign) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::error!("failed to read sign {} from disk, {:?}", sign, e);
+                None
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, HashMapEmbeddingEntry>> + '_> {
+        let disk_entries = self.disk.entries().filter_map(|entry| match entry {
+            Ok(entry) => Some(Cow::Owned(entry)),
+            Err(e) => {
+                tracing::error!("failed to read entry from disk, {:?}", e);
+                None
+            }
+        });
+        Box::new(
+            self.memory
+                .linkedlist
+                .iter()
+                .map(Cow::Borrowed)
+                .chain(disk_entries),
+        )
+    }
+
+    fn signs(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        Box::new(self.memory.hashmap.keys().copied().chain(self.disk.signs()))
+    }
+
+    fn len(&self) -> usize {
+        self.memory.len() + self.disk.len()
+    }
+
+    fn clear(&mut self) {
+        self.memory.clear();
+        if let Err(e) = self.disk.clear() {
+            tracing::error!("failed to clear disk log, {:?}", e);
+        }
+    }
+
+    /// Entries on disk come after the ones in memory and keep no eviction bookkeeping.
+    fn drain_with_meta(&mut self) -> Vec<(HashMapEmbeddingEntry, Option<EvictionMeta>)> {
+        let mut entries = EmbeddingShardBackend::drain_with_meta(&mut self.memory);
+        entries.extend(self.disk.entries().filter_map(|entry| match entry {
+            Ok(entry) => Some((entry, None)),
+            Err(e) => {
+                tracing::error!("failed to read entry from disk, {:?}", e);
+                None
+            }
+        }));
+        if let Err(e) = self.disk.clear() {
+            tracing::error!("failed to clear disk log, {:?}", e);
+        }
+        entries
+    }
+
+    fn insert_with_meta(
+        &mut self,
+        sign: u64,
+        entry: HashMapEmbeddingEntry,
+        meta: Option<EvictionMeta>,
+    ) -> Vec<HashMapEmbeddingEntry> {
+        self.disk.remove(&sign);
+        let evicted = EmbeddingShardBackend::insert_with_meta(&mut self.memory, sign, entry, meta);
+        self.spill(evicted)
+    }
+
+    fn record_sighting(&mut self, sign: &u64) -> u8 {
+        self.memory.record_sighting(sign)
+    }
+
+    /// Entries on disk expire by the time they were spilled.
+    fn evict_expired(&mut self, ttl: u64, max_scanned: usize) -> usize {
+        let num_expired = self.memory.evict_expired(ttl, max_scanned).len();
+        let deadline = self.memory.expiry_clock().now().saturating_sub(ttl);
+        num_expired + self.disk.evict_spilled_before(deadline)
+    }
+
+    fn compact_disk(&mut self, max_records: usize) -> bool {
+        match self.disk.compact_step(max_records) {
+            Ok(in_progress) => in_progress,
+            Err(e) => {
+                tracing::error!("failed to compact disk log, {:?}", e);
+                false
+            }
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.capacity()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.memory.memory_bytes()
+    }
+
+    fn disk_bytes(&self) -> u64 {
+        self.disk.file_bytes()
+    }
+}
+
+fn record_disk_lookup(hit: bool) {
+    if let Ok(m) = MetricsHolder::get() {
+        match hit {
+            true => m.disk_hit_count.inc(),
+            false => m.disk_miss_count.inc(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tiered_map_tests {
+    use super::*;
+    use persia_embedding_config::InitializationMethod;
+
+    #[test]
+    fn test_spill_and_promote() {
+        let path = std::env::temp_dir().join(format!("tiered_map_test_{}.log", std::process::id()));
+        let mut map =
+            TieredEvictionMap::new(EvictionMap::with_capacity(5), DiskLog::open(path).unwrap());
+        let initialization = InitializationMethod::default();
+
+        for i in 0..10 {
+            let entry = HashMapEmbeddingEntry::new(&initialization, 8, 16, i, i);
+            let (_, dropped) = map.insert(i, entry);
+            assert_eq!(dropped.is_empty(), true);
+        }
+
+        assert_eq!(map.memory.len(), 5);
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.memory.get(&0).is_none(), true);
+
+        let expected = HashMapEmbeddingEntry::new(&initialization, 8, 16, 0, 0);
+        assert_eq!(map.get(&0).unwrap().emb(), expected.emb());
+        assert_eq!(map.memory.get(&0).is_none(), true);
+
+        assert_eq!(map.get_refresh(&0).unwrap().emb(), expected.emb());
+        assert_eq!(map.memory.get(&0).is_some(), true);
+        assert_eq!(map.memory.len(), 5);
+        assert_eq!(map.len(), 10);
+
+        assert_eq!(map.remove(&1).is_some(), true);
+        assert_eq!(map.get(&1).is_none(), true);
+        assert_eq!(map.len(), 9);
+
+        assert_eq!(map.iter().count(), 9);
+
+        map.clear();
+        assert_eq!(map.len(), 0);
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+
+use structopt::StructOpt;
+
+use persia_embedding_config::{
+    ConfigOverride, EmbeddingConfig, PersiaGlobalConfig, PersiaGlobalConfigError,
+};
+
+/// Validates the persia config files without launching a job, exits with a non-zero code if any
+/// of them is invalid.
+#[derive(Debug, StructOpt, Clone)]
+#[structopt()]
+struct Cli {
+    #[structopt(long, env = "PERSIA_GLOBAL_CONFIG")]
+    global_config: Option<PathBuf>,
+    #[structopt(long, env = "PERSIA_EMBEDDING_CONFIG")]
+    embedding_config: Option<PathBuf>,
+    /// Overrides a global config value the same way as the `--set` flag of the embedding
+    /// servers and embedding workers.
+    #[structopt(long = "set", value_name = "KEY=VALUE", number_of_values = 1)]
+    global_config_overrides: Vec<ConfigOverride>,
+}
+
+fn report<T>(file_path: &Path, result: Result<T, PersiaGlobalConfigError>) -> bool {
+    match result {
+        Ok(_) => {
+            println!("{}: ok", file_path.display());
+            true
+        }
+        Err(PersiaGlobalConfigError::InvalidConfigError(problems)) => {
+            for problem in problems {
+                println!("{}: {}", file_path.display(), problem);
+            }
+            false
+        }
+        Err(e) => {
+            println!("{}: {}", file_path.display(), e);
+            false
+        }
+    }
+}
+
+fn main() {
+    let args: Cli = Cli::from_args();
+    if args.global_config.is_none() && args.embedding_config.is_none() {
+        eprintln!("no config file given, see --help");
+        std::process::exit(2);
+    }
+
+    let mut valid = true;
+    if let Some(global_config) = args.global_config.as_ref() {
+        valid &= report(
+            global_config,
+            PersiaGlobalConfig::load_with_overrides(global_config, &args.global_config_overrides),
+        );
+    }
+    if let Some(embedding_config) = args.embedding_config.as_ref() {
+        valid &= report(embedding_config, EmbeddingConfig::load(embedding_config));
+    }
+
+    if !valid {
+        std::process::exit(1);
+    }
+}
@@ -5,13 +5,13 @@ extern crate shadow_rs;
 
 use std::{path::PathBuf, sync::Arc};
 
-use persia_libs::{anyhow::Result, color_eyre, hyper, tracing, tracing_subscriber};
+use persia_libs::{anyhow::Result, color_eyre, hyper, serde_yaml, tracing, tracing_subscriber};
 use structopt::StructOpt;
 
 use persia_common::utils::start_deadlock_detection_thread;
 use persia_embedding_config::{
-    EmbeddingConfig, EmbeddingParameterServerConfig, PerisaJobType, PersiaCommonConfig,
-    PersiaGlobalConfig,
+    ConfigOverride, EmbeddingConfig, EmbeddingParameterServerConfig, PerisaJobType,
+    PersiaCommonConfig, PersiaGlobalConfig,
 };
 use persia_embedding_holder::PersiaEmbeddingHolder;
 use persia_embedding_server::embedding_parameter_service::{
@@ -34,6 +34,11 @@ struct Cli {
     global_config: PathBuf,
     #[structopt(long, env = "PERSIA_EMBEDDING_CONFIG")]
     embedding_config: PathBuf,
+    /// Overrides a global config value, e.g.
+    /// `--set embedding_parameter_server_config.capacity=1000000`, after the overrides of the
+    /// `PERSIA__` prefixed environment variables.
+    #[structopt(long = "set", value_name = "KEY=VALUE", number_of_values = 1)]
+    global_config_overrides: Vec<ConfigOverride>,
 }
 
 #[tokio::main]
@@ -53,23 +58,25 @@ async fn main() -> Result<()> {
     eprintln!("build_os: {}", build::BUILD_OS);
     eprintln!("rust_version: {}", build::RUST_VERSION);
     eprintln!("build_time: {}", build::BUILD_TIME);
+    eprintln!("simd_level: {:?}", persia_simd::simd_level());
     let args: Cli = Cli::from_args();
 
     start_deadlock_detection_thread();
 
-    PersiaGlobalConfig::set_configures(
+    let global_config = PersiaGlobalConfig::load_with_overrides(
         &args.global_config,
-        args.port,
-        args.replica_index,
-        args.replica_size,
+        &args.global_config_overrides,
     )?;
+    eprintln!("global_config:\n{}", serde_yaml::to_string(&global_config)?);
+    global_config.set_configures(args.port, args.replica_index, args.replica_size)?;
 
     EmbeddingConfig::set(&args.embedding_config)?;
 
     let embedding_config = EmbeddingConfig::get()?;
     let common_config = PersiaCommonConfig::get()?;
     let server_config = EmbeddingParameterServerConfig::get()?;
-    let embedding_holder = PersiaEmbeddingHolder::get()?;
+    let embedding_holder =
+        PersiaEmbeddingHolder::set(&server_config, Some(embedding_config.as_ref()))?;
     let inc_update_manager = PerisaIncrementalUpdateManager::get()?;
     let embedding_model_manager = EmbeddingModelManager::get()?;
     let (tx, rx) = tokio::sync::oneshot::channel::<()>();
@@ -83,6 +90,7 @@ async fn main() -> Result<()> {
         embedding_model_manager,
         args.replica_index,
     ));
+    inner.spawn_gradient_flusher();
 
     let service = EmbeddingParameterService {
         inner: inner.clone(),
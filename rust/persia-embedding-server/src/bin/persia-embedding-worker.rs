@@ -5,14 +5,16 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use persia_libs::{
-    anyhow::Result, color_eyre, hashbrown::HashMap, hyper, rand, tracing, tracing_subscriber,
+    anyhow::Result, color_eyre, hashbrown::HashMap, hyper, rand, serde_yaml, tracing,
+    tracing_subscriber,
 };
 
 use structopt::StructOpt;
 
 use persia_common::utils::start_deadlock_detection_thread;
 use persia_embedding_config::{
-    EmbeddingConfig, EmbeddingWorkerConfig, PerisaJobType, PersiaCommonConfig, PersiaGlobalConfig,
+    ConfigOverride, EmbeddingConfig, EmbeddingWorkerConfig, PerisaJobType, PersiaCommonConfig,
+    PersiaGlobalConfig,
 };
 use persia_embedding_server::embedding_parameter_service::EmbeddingParameterNatsServicePublisher;
 use persia_embedding_server::embedding_worker_service::{
@@ -34,6 +36,11 @@ struct Cli {
     global_config: PathBuf,
     #[structopt(long, env = "PERSIA_EMBEDDING_CONFIG")]
     embedding_config: PathBuf,
+    /// Overrides a global config value, e.g.
+    /// `--set embedding_worker_config.forward_buffer_size=1000`, after the overrides of the
+    /// `PERSIA__` prefixed environment variables.
+    #[structopt(long = "set", value_name = "KEY=VALUE", number_of_values = 1)]
+    global_config_overrides: Vec<ConfigOverride>,
 }
 
 #[tokio::main]
@@ -52,16 +59,17 @@ async fn main() -> Result<()> {
     eprintln!("build_os: {}", build::BUILD_OS);
     eprintln!("rust_version: {}", build::RUST_VERSION);
     eprintln!("build_time: {}", build::BUILD_TIME);
+    eprintln!("simd_level: {:?}", persia_simd::simd_level());
     let args: Cli = Cli::from_args();
 
     start_deadlock_detection_thread();
 
-    PersiaGlobalConfig::set_configures(
+    let global_config = PersiaGlobalConfig::load_with_overrides(
         &args.global_config,
-        args.port,
-        args.replica_index,
-        args.replica_size,
+        &args.global_config_overrides,
     )?;
+    eprintln!("global_config:\n{}", serde_yaml::to_string(&global_config)?);
+    global_config.set_configures(args.port, args.replica_index, args.replica_size)?;
 
     EmbeddingConfig::set(&args.embedding_config)?;
 
@@ -91,7 +99,7 @@ async fn main() -> Result<()> {
         cannot_forward_batched_time: crossbeam::atomic::AtomicCell::new(
             std::time::SystemTime::now(),
         ),
-        embedding_config,
+        embedding_config: persia_libs::parking_lot::RwLock::new(embedding_config),
         staleness: Default::default(),
         embedding_worker_config,
         embedding_model_manager,
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use persia_libs::{hashbrown::HashMap, parking_lot::Mutex};
+
+use persia_embedding_config::{GradientAccumulationConfig, GradientReduction};
+use persia_embedding_holder::sharded::get_index;
+
+const NUM_ACCUMULATOR_SHARDS: usize = 64;
+
+struct AccumulatedGradient {
+    gradient: Vec<f32>,
+    num_updates: usize,
+}
+
+/// Buffers the gradient updates of each sign until they are applied in one optimizer step,
+/// see `GradientAccumulationConfig`.
+///
+/// The buffer is sharded by sign apart from the embedding holder, so the updates of a hot sign
+/// do not take the write lock of its embedding shard until they are applied.
+pub struct GradientAccumulator {
+    shards: Vec<Mutex<HashMap<u64, AccumulatedGradient>>>,
+    flush_interval: Duration,
+    max_accumulated_updates: usize,
+    reduction: GradientReduction,
+}
+
+impl GradientAccumulator {
+    pub fn new(config: &GradientAccumulationConfig) -> Self {
+        Self {
+            shards: (0..NUM_ACCUMULATOR_SHARDS)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+            flush_interval: Duration::from_millis(config.flush_interval_ms.max(1) as u64),
+            max_accumulated_updates: config.max_accumulated_updates.max(1),
+            reduction: config.reduction,
+        }
+    }
+
+    pub fn flush_interval(&self) -> Duration {
+        self.flush_interval
+    }
+
+    /// Dim of the gradient buffered for a sign.
+    pub fn buffered_dim(&self, sign: u64) -> Option<usize> {
+        self.shard(sign)
+            .lock()
+            .get(&sign)
+            .map(|accumulated| accumulated.gradient.len())
+    }
+
+    /// Adds a gradient of a sign to the buffer. Returns the reduced gradient of the sign, taken
+    /// out of the buffer, once `max_accumulated_updates` of its updates are buffered.
+    pub fn accumulate(&self, sign: u64, gradient: &[f32]) -> Option<Vec<f32>> {
+        let mut shard = self.shard(sign).lock();
+        let accumulated = shard.entry(sign).or_insert_with(|| AccumulatedGradient {
+            gradient: vec![0.0; gradient.len()],
+            num_updates: 0,
+        });
+        persia_simd::add_assign(accumulated.gradient.as_mut_slice(), gradient);
+        accumulated.num_updates += 1;
+
+        if accumulated.num_updates >= self.max_accumulated_updates {
+            shard
+                .remove(&sign)
+                .map(|accumulated| self.reduce(accumulated))
+        } else {
+            None
+        }
+    }
+
+    /// Takes all the buffered gradients out of the buffer, returns the signs, the dims and the
+    /// concatenated reduced gradients of them.
+    pub fn drain(&self) -> (Vec<u64>, Vec<usize>, Vec<f32>) {
+        let mut signs = Vec::new();
+        let mut dims = Vec::new();
+        let mut gradients = Vec::new();
+        for shard in self.shards.iter() {
+            let accumulated_gradients = std::mem::take(&mut *shard.lock());
+            for (sign, accumulated) in accumulated_gradients {
+                let gradient = self.reduce(accumulated);
+                signs.push(sign);
+                dims.push(gradient.len());
+                gradients.extend_from_slice(gradient.as_slice());
+            }
+        }
+        (signs, dims, gradients)
+    }
+
+    /// Drops all the buffered gradients without applying them.
+    pub fn clear(&self) {
+        self.shards.iter().for_each(|shard| shard.lock().clear());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.lock().is_empty())
+    }
+
+    fn shard(&self, sign: u64) -> &Mutex<HashMap<u64, AccumulatedGradient>> {
+        &self.shards[get_index(&sign, self.shards.len())]
+    }
+
+    fn reduce(&self, accumulated: AccumulatedGradient) -> Vec<f32> {
+        let mut gradient = accumulated.gradient;
+        if self.reduction == GradientReduction::Mean && accumulated.num_updates > 1 {
+            let scale = (accumulated.num_updates as f32).recip();
+            gradient.iter_mut().for_each(|x| *x *= scale);
+        }
+        gradient
+    }
+}
+
+#[cfg(test)]
+mod gradient_accumulator_tests {
+    use super::*;
+
+    fn accumulator(
+        max_accumulated_updates: usize,
+        reduction: GradientReduction,
+    ) -> GradientAccumulator {
+        GradientAccumulator::new(&GradientAccumulationConfig {
+            flush_interval_ms: 10,
+            max_accumulated_updates,
+            reduction,
+        })
+    }
+
+    #[test]
+    fn test_accumulate_until_max_updates() {
+        let accumulator = accumulator(3, GradientReduction::Sum);
+
+        assert_eq!(accumulator.accumulate(1, &[1.0, 2.0]), None);
+        assert_eq!(accumulator.accumulate(2, &[1.0]), None);
+        assert_eq!(accumulator.buffered_dim(1), Some(2));
+        assert_eq!(accumulator.accumulate(1, &[1.0, 2.0]), None);
+        assert_eq!(accumulator.accumulate(1, &[1.0, 2.0]), Some(vec![3.0, 6.0]));
+
+        assert_eq!(accumulator.buffered_dim(1), None);
+        assert_eq!(accumulator.buffered_dim(2), Some(1));
+    }
+
+    #[test]
+    fn test_drain_mean() {
+        let accumulator = accumulator(10, GradientReduction::Mean);
+
+        accumulator.accumulate(1, &[1.0, 2.0]);
+        accumulator.accumulate(1, &[3.0, 4.0]);
+        accumulator.accumulate(2, &[5.0]);
+
+        let (signs, dims, gradients) = accumulator.drain();
+        let mut drained: Vec<(u64, usize)> = signs.into_iter().zip(dims).collect();
+        let mut offset = 0;
+        let mut gradient_of = HashMap::new();
+        for (sign, dim) in drained.iter() {
+            gradient_of.insert(*sign, gradients[offset..offset + dim].to_vec());
+            offset += dim;
+        }
+        drained.sort();
+
+        assert_eq!(drained, vec![(1, 2), (2, 1)]);
+        assert_eq!(gradient_of[&1], vec![2.0, 3.0]);
+        assert_eq!(gradient_of[&2], vec![5.0]);
+        assert!(accumulator.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let accumulator = accumulator(10, GradientReduction::Sum);
+
+        accumulator.accumulate(1, &[1.0, 2.0]);
+        accumulator.accumulate(2, &[5.0]);
+        accumulator.clear();
+
+        assert!(accumulator.is_empty());
+        assert_eq!(accumulator.buffered_dim(1), None);
+        assert_eq!(accumulator.drain(), (vec![], vec![], vec![]));
+    }
+}
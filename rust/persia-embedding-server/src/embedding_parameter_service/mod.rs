@@ -1,3 +1,6 @@
+pub mod gradient_accumulator;
+
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -6,22 +9,28 @@ use persia_libs::{
 };
 use snafu::ResultExt;
 
-use persia_common::optim::{Optimizable, Optimizer, OptimizerConfig};
+use persia_common::optim::{FeatureGroupOptimizers, LearningRateUpdate, OptimizerConfig};
 use persia_embedding_config::{
-    EmbeddingConfig, EmbeddingParameterServerConfig, InstanceInfo, PerisaJobType,
-    PersiaCommonConfig, PersiaEmbeddingModelHyperparameters, PersiaGlobalConfigError,
-    PersiaReplicaInfo,
+    EmbeddingConfig, EmbeddingParameterServerConfig, FeatureGroupHyperparameters, InstanceInfo,
+    PerisaJobType, PersiaCommonConfig, PersiaEmbeddingModelHyperparameters,
+    PersiaGlobalConfigError, PersiaReplicaInfo,
+};
+use persia_embedding_holder::{
+    emb_entry::HashMapEmbeddingEntry, PersiaEmbeddingHolder, PersiaEmbeddingHolderError,
 };
-use persia_embedding_holder::{emb_entry::HashMapEmbeddingEntry, PersiaEmbeddingHolder};
 use persia_incremental_update_manager::PerisaIncrementalUpdateManager;
 
-use persia_metrics::{Gauge, IntCounter, PersiaMetricsManager, PersiaMetricsManagerError};
+use persia_metrics::{
+    Gauge, IntCounter, IntCounterVec, PersiaMetricsManager, PersiaMetricsManagerError,
+};
 use persia_model_manager::{
     EmbeddingModelManager, EmbeddingModelManagerError, EmbeddingModelManagerStatus,
 };
 use persia_nats_client::{NatsClient, NatsError};
 use persia_speedy::{Readable, Writable};
 
+use gradient_accumulator::GradientAccumulator;
+
 static METRICS_HOLDER: once_cell::sync::OnceCell<MetricsHolder> = once_cell::sync::OnceCell::new();
 
 struct MetricsHolder {
@@ -33,6 +42,8 @@ struct MetricsHolder {
     pub lookup_inference_batch_time_cost_sec: Gauge,
     pub lookup_hashmap_time_cost_sec: Gauge,
     pub gradient_id_miss_count: IntCounter,
+    pub non_finite_gradient_count: IntCounterVec,
+    pub opt_space_reset_count: IntCounter,
 }
 
 impl MetricsHolder {
@@ -72,6 +83,14 @@ impl MetricsHolder {
                     "gradient_id_miss_count",
                     "num of embedding not found when update corresponding gradient in a batch",
                 )?,
+                non_finite_gradient_count: m.create_counter_vec(
+                    "non_finite_gradient_count",
+                    "num of embedding gradients rejected for containing nan or inf, by feature group",
+                )?,
+                opt_space_reset_count: m.create_counter(
+                    "opt_space_reset_count",
+                    "num of embedding optimizer states reset for not matching the registered optimizer",
+                )?,
             };
             Ok(holder)
         })
@@ -94,24 +113,34 @@ pub enum EmbeddingParameterServerError {
     NatsError(#[from] NatsError),
     #[error("optimizer not found error")]
     OptimizerNotFoundError,
+    #[error("feature groups not found error: {0:?}")]
+    FeatureGroupNotFoundError(Vec<String>),
     #[error("global config error: {0}")]
     PersiaGlobalConfigError(#[from] PersiaGlobalConfigError),
     #[error("embedding dim not match")]
     EmbeddingDimNotMatch,
+    #[error("embedding holder error: {0}")]
+    PersiaEmbeddingHolderError(#[from] PersiaEmbeddingHolderError),
 }
 
 pub struct EmbeddingParameterServiceInner {
     pub embedding: PersiaEmbeddingHolder,
-    pub optimizer: persia_libs::async_lock::RwLock<Option<Arc<Box<dyn Optimizable + Send + Sync>>>>,
+    pub optimizers: persia_libs::async_lock::RwLock<Arc<FeatureGroupOptimizers>>,
     pub hyperparameter_config:
         persia_libs::async_lock::RwLock<Option<Arc<PersiaEmbeddingModelHyperparameters>>>,
     pub hyperparameter_configured: persia_libs::async_lock::Mutex<bool>,
+    // admit frequency of the configured feature groups, keyed by feature group index prefix
+    pub admit_frequency: persia_libs::async_lock::RwLock<Arc<HashMap<u64, u8>>>,
+    // hyperparameter overrides of the feature groups, keyed by feature group index prefix
+    pub feature_group_hyperparameters:
+        persia_libs::async_lock::RwLock<Arc<HashMap<u64, FeatureGroupHyperparameters>>>,
     pub server_config: Arc<EmbeddingParameterServerConfig>,
     pub common_config: Arc<PersiaCommonConfig>,
-    pub embedding_config: Arc<EmbeddingConfig>,
+    pub embedding_config: persia_libs::parking_lot::RwLock<Arc<EmbeddingConfig>>,
     pub inc_update_manager: Arc<PerisaIncrementalUpdateManager>,
     pub embedding_model_manager: Arc<EmbeddingModelManager>,
     pub replica_index: usize,
+    pub gradient_accumulator: Option<GradientAccumulator>,
 }
 
 impl EmbeddingParameterServiceInner {
@@ -124,17 +153,28 @@ impl EmbeddingParameterServiceInner {
         embedding_model_manager: Arc<EmbeddingModelManager>,
         replica_index: usize,
     ) -> Self {
+        let gradient_accumulator = server_config
+            .gradient_accumulation
+            .as_ref()
+            .map(GradientAccumulator::new);
         Self {
             embedding,
-            optimizer: persia_libs::async_lock::RwLock::new(None),
+            optimizers: persia_libs::async_lock::RwLock::new(Arc::new(
+                FeatureGroupOptimizers::new(embedding_config.feature_index_prefix_mask()),
+            )),
             hyperparameter_config: persia_libs::async_lock::RwLock::new(None),
             hyperparameter_configured: persia_libs::async_lock::Mutex::new(false),
+            admit_frequency: persia_libs::async_lock::RwLock::new(Arc::new(HashMap::new())),
+            feature_group_hyperparameters: persia_libs::async_lock::RwLock::new(Arc::new(
+                HashMap::new(),
+            )),
             server_config,
             common_config,
-            embedding_config,
+            embedding_config: persia_libs::parking_lot::RwLock::new(embedding_config),
             inc_update_manager,
             embedding_model_manager,
             replica_index,
+            gradient_accumulator,
         }
     }
 
@@ -142,6 +182,10 @@ impl EmbeddingParameterServiceInner {
         self.replica_index
     }
 
+    pub fn embedding_config(&self) -> Arc<EmbeddingConfig> {
+        self.embedding_config.read().clone()
+    }
+
     pub fn get_job_type(&self) -> Result<PerisaJobType, EmbeddingParameterServerError> {
         let job_type = self.common_config.job_type.clone();
         Ok(job_type)
@@ -174,32 +218,49 @@ impl EmbeddingParameterServiceInner {
             false => None,
         };
 
-        let optimizer = self.optimizer.read().await;
+        let optimizers = self.optimizers.read().await.clone();
+        let admit_frequency = self.admit_frequency.read().await.clone();
+        let feature_group_hyperparameters = self.feature_group_hyperparameters.read().await.clone();
+        let embedding_config = self.embedding_config();
+        let prefix_mask = embedding_config.feature_index_prefix_mask();
 
-        tokio::task::block_in_place(|| match is_training {
+        tokio::task::block_in_place(|| {
+            match is_training {
             true => {
-                if optimizer.is_none() {
-                    return Err(EmbeddingParameterServerError::OptimizerNotFoundError);
-                }
-                let optimizer = optimizer.as_ref().unwrap();
-
-                req.iter().for_each(|(sign, dim)| {
+                req.iter().try_for_each(|(sign, dim)| {
                         let conf = conf.as_ref().unwrap();
+                        let optimizer = optimizers
+                            .get(*sign)
+                            .ok_or(EmbeddingParameterServerError::OptimizerNotFoundError)?;
+                        let group_conf = feature_group_hyperparameters.get(&(sign & prefix_mask));
+                        let initialization_method = group_conf
+                            .and_then(|x| x.initialization_method.as_ref())
+                            .unwrap_or(&conf.initialization_method);
                         let mut shard = self.embedding.shard(sign).write();
                         let e = shard.get_refresh(&sign);
                         match e {
                             None => {
-                                if rand::thread_rng().gen_range(0f32..1f32) < conf.admit_probability {
-                                    let mut emb_entry = HashMapEmbeddingEntry::new(
-                                        &conf.initialization_method,
+                                let admitted = match admit_frequency.get(&(sign & prefix_mask)) {
+                                    Some(k) if *k > 1 => shard.record_sighting(sign) >= *k,
+                                    _ => true,
+                                };
+                                let admit_probability = group_conf
+                                    .and_then(|x| x.admit_probability)
+                                    .unwrap_or(conf.admit_probability);
+                                if admitted && rand::thread_rng().gen_range(0f32..1f32) < admit_probability {
+                                    let mut emb_entry = HashMapEmbeddingEntry::new_with_storage_dtype(
+                                        initialization_method,
                                         *dim,
                                         optimizer.require_space(*dim),
                                         *sign,
                                         *sign,
+                                        embedding_config.get_storage_dtype_by_sign(*sign),
                                     );
 
-                                    optimizer.state_initialization(emb_entry.as_mut_emb_entry_slice(), *dim);
-                                    embeddings.extend_from_slice(&emb_entry.as_emb_entry_slice()[..*dim]);
+                                    emb_entry.update_emb_and_opt(|emb_entry_slice| {
+                                        optimizer.state_initialization(emb_entry_slice, *dim)
+                                    });
+                                    embeddings.extend_from_slice(&emb_entry.emb());
                                     let _ = shard.insert(*sign, emb_entry);
 
                                     index_miss_count += 1;
@@ -211,22 +272,23 @@ impl EmbeddingParameterServiceInner {
                                 let entry_dim = entry.dim();
                                 if entry_dim != *dim {
                                     tracing::error!("dimension not match on sign {}. Expected dimension {}, got dimension {}.", sign, entry_dim, dim);
-                                    let entry = HashMapEmbeddingEntry::new(
-                                        &conf.initialization_method,
+                                    let entry = HashMapEmbeddingEntry::new_with_storage_dtype(
+                                        initialization_method,
                                         *dim,
                                         optimizer.require_space(*dim),
                                         *sign,
                                         *sign,
+                                        embedding_config.get_storage_dtype_by_sign(*sign),
                                     );
-                                    embeddings.extend_from_slice(entry.emb());
+                                    embeddings.extend_from_slice(&entry.emb());
                                     let _ = shard.insert(*sign, entry);
                                 } else {
-                                    embeddings.extend_from_slice(entry.emb());
+                                    embeddings.extend_from_slice(&entry.emb());
                                 }
                             }
                         }
-                    });
-                Ok(())
+                        Ok::<(), EmbeddingParameterServerError>(())
+                    })
             }
             false => {
                 req.iter().for_each(|(sign, dim)| {
@@ -239,7 +301,7 @@ impl EmbeddingParameterServiceInner {
                                     sign, entry_dim, dim);
                                 embeddings.extend_from_slice(vec![0f32; *dim].as_slice());
                             } else {
-                                embeddings.extend_from_slice(entry.emb());
+                                embeddings.extend_from_slice(&entry.emb());
                             }
                         }
                         None => {
@@ -250,6 +312,7 @@ impl EmbeddingParameterServiceInner {
                 });
                 Ok(())
             }
+        }
         })?;
 
         if let Ok(m) = MetricsHolder::get() {
@@ -290,12 +353,11 @@ impl EmbeddingParameterServiceInner {
     ) -> Result<(), EmbeddingParameterServerError> {
         let start_time = std::time::Instant::now();
 
+        let embedding_config = self.embedding_config();
         tokio::task::block_in_place(|| {
-            embeddings.into_iter().for_each(|entry| {
-                let id = entry.sign();
-                let mut shard = self.embedding.shard(&id).write();
-                let _ = shard.insert(id, entry);
-            });
+            embeddings
+                .into_iter()
+                .for_each(|entry| self.embedding.insert_restored(entry, &embedding_config));
         });
 
         if let Ok(m) = MetricsHolder::get() {
@@ -359,57 +421,220 @@ impl EmbeddingParameterServiceInner {
     pub async fn update_gradient_mixed(
         &self,
         req: (Vec<u64>, Vec<f32>),
+    ) -> Result<(), EmbeddingParameterServerError> {
+        let (signs, gradients) = req;
+        self.embedding.advance_step();
+        let result = match self.gradient_accumulator.as_ref() {
+            Some(accumulator) => {
+                let (signs, dims, gradients) = tokio::task::block_in_place(|| {
+                    self.accumulate_gradients(accumulator, signs, gradients)
+                });
+                self.apply_gradients(signs, gradients, Some(dims)).await
+            }
+            None => self.apply_gradients(signs, gradients, None).await,
+        };
+        // the learning rate schedule moves on after the update, so the first update is applied
+        // at step 0
+        self.optimizers.read().await.advance_step();
+        result
+    }
+
+    /// Applies the gradients buffered by the gradient accumulator.
+    pub async fn flush_accumulated_gradients(&self) -> Result<(), EmbeddingParameterServerError> {
+        if let Some(accumulator) = self.gradient_accumulator.as_ref() {
+            if accumulator.is_empty() {
+                return Ok(());
+            }
+            let (signs, dims, gradients) = tokio::task::block_in_place(|| accumulator.drain());
+            self.apply_gradients(signs, gradients, Some(dims)).await?;
+        }
+        Ok(())
+    }
+
+    /// Spawns the task applying the buffered gradients every flush interval of the gradient
+    /// accumulation.
+    pub fn spawn_gradient_flusher(self: &Arc<Self>) {
+        let flush_interval = match self.gradient_accumulator.as_ref() {
+            Some(accumulator) => accumulator.flush_interval(),
+            None => return,
+        };
+        let inner = self.clone();
+        tokio::spawn(async move {
+            tracing::info!(
+                "background task for applying accumulated gradients start, interval: {:?}",
+                flush_interval
+            );
+            let mut interval = tokio::time::interval(flush_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = inner.flush_accumulated_gradients().await {
+                    tracing::error!("failed to apply accumulated gradients: {:?}", e);
+                }
+            }
+        });
+    }
+
+    // Buffers the gradients in the accumulator, returns the signs, the dims and the
+    // concatenated gradients of the signs ready to be applied.
+    fn accumulate_gradients(
+        &self,
+        accumulator: &GradientAccumulator,
+        signs: Vec<u64>,
+        gradients: Vec<f32>,
+    ) -> (Vec<u64>, Vec<usize>, Vec<f32>) {
+        let mut remaining_gradients = gradients.as_slice();
+        let mut ready_signs = Vec::new();
+        let mut ready_dims = Vec::new();
+        let mut ready_gradients = Vec::new();
+        let mut gradient_id_miss_count = 0;
+        let mut non_finite_gradient_count: HashMap<u64, u64> = HashMap::new();
+        let prefix_mask = self.embedding_config().feature_index_prefix_mask();
+
+        for (idx, sign) in signs.iter().copied().enumerate() {
+            let dim = accumulator.buffered_dim(sign).or_else(|| {
+                let shard = self.embedding.shard(&sign).read();
+                shard.get(&sign).map(|entry| entry.dim())
+            });
+            let dim = match dim {
+                Some(dim) => dim,
+                None => {
+                    // the request carries no dims, the gradients after an unknown one can not
+                    // be told apart
+                    tracing::error!(
+                        "dim of sign {} unknown, dropped the gradients of the remaining {} signs",
+                        sign,
+                        signs.len() - idx
+                    );
+                    gradient_id_miss_count += (signs.len() - idx) as u64;
+                    break;
+                }
+            };
+            let (grad, r) = remaining_gradients.split_at(dim);
+            remaining_gradients = r;
+            if !grad.iter().all(|x| x.is_finite()) {
+                *non_finite_gradient_count
+                    .entry(sign & prefix_mask)
+                    .or_insert(0) += 1;
+                continue;
+            }
+            if let Some(reduced) = accumulator.accumulate(sign, grad) {
+                ready_signs.push(sign);
+                ready_dims.push(dim);
+                ready_gradients.extend_from_slice(reduced.as_slice());
+            }
+        }
+
+        self.report_rejected_gradients(gradient_id_miss_count, non_finite_gradient_count);
+        (ready_signs, ready_dims, ready_gradients)
+    }
+
+    // Applies the concatenated gradients of the signs in one optimizer step each. The dims of
+    // the gradients are taken from the embedding entries if not given.
+    async fn apply_gradients(
+        &self,
+        signs: Vec<u64>,
+        mut gradients: Vec<f32>,
+        dims: Option<Vec<usize>>,
     ) -> Result<(), EmbeddingParameterServerError> {
         let conf = self.get_configuration().await?;
-        let (signs, remaining_gradients) = req;
-        let mut remaining_gradients = remaining_gradients.as_slice();
+        let mut remaining_gradients = gradients.as_mut_slice();
         let mut indices_to_commit = Vec::with_capacity(signs.len());
         let mut gradient_id_miss_count = 0;
+        // num of rejected non-finite gradients, keyed by feature group index prefix
+        let mut non_finite_gradient_count: HashMap<u64, u64> = HashMap::new();
+        let mut opt_space_reset_count = 0;
 
-        let optimizer = self.optimizer.read().await;
-        if optimizer.is_none() {
+        let optimizers = self.optimizers.read().await.clone();
+        if optimizers.is_empty() {
             return Err(EmbeddingParameterServerError::OptimizerNotFoundError);
         }
 
-        let optimizer = optimizer.as_ref().unwrap();
-        let batch_level_state = optimizer.get_batch_level_state(signs.as_slice());
+        let feature_group_hyperparameters = self.feature_group_hyperparameters.read().await.clone();
+        let prefix_mask = self.embedding_config().feature_index_prefix_mask();
+        let emb_opt_states = optimizers.get_emb_states(signs.as_slice());
 
         tokio::task::block_in_place(|| {
             for (idx, sign) in signs.iter().enumerate() {
                 let mut shard = self.embedding.shard(sign).write();
                 if let Some(entry) = shard.get_mut(sign) {
                     let entry_dim = entry.dim();
-                    let (grad, r) = remaining_gradients.split_at(entry_dim);
+                    let grad_dim = dims.as_ref().map_or(entry_dim, |dims| dims[idx]);
+                    let (grad, r) = std::mem::take(&mut remaining_gradients).split_at_mut(grad_dim);
                     remaining_gradients = r;
-                    let emb_opt_state = optimizer.get_emb_state(&batch_level_state, idx);
-
-                    {
-                        let emb_entry_slice = entry.as_mut_emb_entry_slice();
-                        optimizer.update(emb_entry_slice, grad, entry_dim, &emb_opt_state);
-
-                        if conf.enable_weight_bound {
-                            unsafe {
-                                persia_simd::weight_bound(
-                                    &mut emb_entry_slice[..entry_dim],
-                                    conf.weight_bound,
-                                );
-                            }
+                    if grad_dim != entry_dim {
+                        tracing::error!(
+                            "dimension not match on sign {}. Expected dimension {}, got dimension {}.",
+                            sign,
+                            entry_dim,
+                            grad_dim
+                        );
+                        gradient_id_miss_count += 1;
+                        continue;
+                    }
+                    if !grad.iter().all(|x| x.is_finite()) {
+                        *non_finite_gradient_count
+                            .entry(sign & prefix_mask)
+                            .or_insert(0) += 1;
+                        continue;
+                    }
+                    let optimizer = match optimizers.get(*sign) {
+                        Some(optimizer) => optimizer,
+                        None => {
+                            gradient_id_miss_count += 1;
+                            continue;
                         }
+                    };
+                    // the optimizer state of an entry created under another optimizer, e.g. an
+                    // entry restored from a checkpoint, is started over for this optimizer
+                    let require_space = optimizer.require_space(entry_dim);
+                    if entry.opt().len() != require_space {
+                        entry.reset_opt(require_space);
+                        entry.update_emb_and_opt(|emb_entry_slice| {
+                            optimizer.state_initialization(emb_entry_slice, entry_dim)
+                        });
+                        opt_space_reset_count += 1;
                     }
+                    let emb_opt_state = &emb_opt_states[idx];
+                    let group_conf = feature_group_hyperparameters.get(&(sign & prefix_mask));
+                    let weight_bound = group_conf
+                        .and_then(|x| x.weight_bound)
+                        .or_else(|| conf.enable_weight_bound.then(|| conf.weight_bound));
+                    if let Some(gradient_clipping) = group_conf.and_then(|x| x.gradient_clipping) {
+                        gradient_clipping.clip(grad);
+                    }
+
+                    entry.update_emb_and_opt(|emb_entry_slice| {
+                        optimizer.update(emb_entry_slice, grad, entry_dim, emb_opt_state);
+
+                        if let Some(weight_bound) = weight_bound {
+                            persia_simd::weight_bound(
+                                &mut emb_entry_slice[..entry_dim],
+                                weight_bound,
+                            );
+                        }
+                    });
 
                     indices_to_commit.push(*sign);
                 } else {
                     gradient_id_miss_count += 1;
+                    // skips the gradient of the missing entry when its dim is known
+                    if let Some(dims) = dims.as_ref() {
+                        remaining_gradients =
+                            &mut std::mem::take(&mut remaining_gradients)[dims[idx]..];
+                    }
                 }
             }
         });
 
-        tracing::debug!(
-            "Gradient update failed {} times due to embedding not found",
-            gradient_id_miss_count
-        );
-        if let Ok(m) = MetricsHolder::get() {
-            m.gradient_id_miss_count.inc_by(gradient_id_miss_count);
+        self.report_rejected_gradients(gradient_id_miss_count, non_finite_gradient_count);
+        if opt_space_reset_count > 0 {
+            tracing::warn!(
+                "reset the optimizer state of {} embeddings not matching the registered optimizer",
+                opt_space_reset_count
+            );
+            if let Ok(m) = MetricsHolder::get() {
+                m.opt_space_reset_count.inc_by(opt_space_reset_count);
+            }
         }
 
         if self.server_config.enable_incremental_update {
@@ -426,21 +651,136 @@ impl EmbeddingParameterServiceInner {
         Ok(())
     }
 
+    fn report_rejected_gradients(
+        &self,
+        gradient_id_miss_count: u64,
+        non_finite_gradient_count: HashMap<u64, u64>,
+    ) {
+        tracing::debug!(
+            "Gradient update failed {} times due to embedding not found",
+            gradient_id_miss_count
+        );
+        if let Ok(m) = MetricsHolder::get() {
+            m.gradient_id_miss_count.inc_by(gradient_id_miss_count);
+        }
+        let embedding_config = self.embedding_config();
+        for (prefix, count) in non_finite_gradient_count {
+            let feature_group_name = embedding_config
+                .get_feature_group_name_by_sign(prefix)
+                .unwrap_or("unknown");
+            tracing::warn!(
+                "rejected {} non-finite gradients of feature group {}",
+                count,
+                feature_group_name
+            );
+            if let Ok(m) = MetricsHolder::get() {
+                m.non_finite_gradient_count
+                    .with_label_values(&[feature_group_name])
+                    .inc_by(count);
+            }
+        }
+    }
+
+    /// Registers the optimizer of the given feature groups, or the default optimizer if no
+    /// feature group is given.
     pub async fn register_optimizer(
         &self,
-        optimizer: OptimizerConfig,
+        req: (Vec<String>, OptimizerConfig),
     ) -> Result<(), EmbeddingParameterServerError> {
-        {
-            let mut optimizer_ = self.optimizer.write().await;
-            *optimizer_ = Some(Arc::new(Optimizer::new(optimizer).to_optimizable()));
+        let (feature_groups, optimizer) = req;
+        let embedding_config = self.embedding_config();
+        let mut index_prefixes = Vec::with_capacity(feature_groups.len());
+        let mut unknown_feature_groups = Vec::new();
+        for feature_group_name in feature_groups.iter() {
+            match embedding_config.get_feature_group_index_prefix(feature_group_name) {
+                Some(prefix) => index_prefixes.push(prefix),
+                None => unknown_feature_groups.push(feature_group_name.clone()),
+            }
+        }
+        if !unknown_feature_groups.is_empty() {
+            return Err(EmbeddingParameterServerError::FeatureGroupNotFoundError(
+                unknown_feature_groups,
+            ));
         }
+
+        let mut optimizers_guard = self.optimizers.write().await;
+        let mut optimizers = FeatureGroupOptimizers::clone(&optimizers_guard);
+        if feature_groups.is_empty() {
+            optimizers.register(None, optimizer.clone());
+        }
+        for prefix in index_prefixes {
+            optimizers.register(Some(prefix), optimizer.clone());
+        }
+        *optimizers_guard = Arc::new(optimizers);
         Ok(())
     }
 
+    pub async fn set_learning_rate(
+        &self,
+        req: LearningRateUpdate,
+    ) -> Result<(), EmbeddingParameterServerError> {
+        let mut optimizers_guard = self.optimizers.write().await;
+        let mut optimizers = FeatureGroupOptimizers::clone(&optimizers_guard);
+        optimizers.update_learning_rate(req);
+        *optimizers_guard = Arc::new(optimizers);
+        Ok(())
+    }
+
+    // resolves the feature group names of the hyperparameters to the index prefixes of the
+    // embedding config
+    async fn configure_feature_groups(&self, config: &PersiaEmbeddingModelHyperparameters) {
+        let embedding_config = self.embedding_config();
+        {
+            let mut admit_frequency = HashMap::with_capacity(config.admit_frequency.len());
+            for (feature_group_name, k) in config.admit_frequency.iter() {
+                match embedding_config.get_feature_group_index_prefix(feature_group_name) {
+                    Some(prefix) => {
+                        admit_frequency.insert(prefix, (*k).min(u8::MAX as u32) as u8);
+                    }
+                    None => {
+                        tracing::warn!(
+                            "admit frequency set for unknown feature group {}",
+                            feature_group_name
+                        );
+                    }
+                }
+            }
+            *self.admit_frequency.write().await = Arc::new(admit_frequency);
+        }
+        {
+            // overrides set at runtime take precedence over the ones in the slot configs
+            let mut feature_group_hyperparameters =
+                embedding_config.get_slot_hyperparameters_by_index_prefix();
+            for (feature_group_name, hyperparameters) in config.feature_group_hyperparameters.iter()
+            {
+                match embedding_config.get_feature_group_index_prefix(feature_group_name) {
+                    Some(prefix) => {
+                        let merged = match feature_group_hyperparameters.get(&prefix) {
+                            Some(slot_hyperparameters) => {
+                                hyperparameters.clone().or(slot_hyperparameters)
+                            }
+                            None => hyperparameters.clone(),
+                        };
+                        feature_group_hyperparameters.insert(prefix, merged);
+                    }
+                    None => {
+                        tracing::warn!(
+                            "hyperparameters set for unknown feature group {}",
+                            feature_group_name
+                        );
+                    }
+                }
+            }
+            *self.feature_group_hyperparameters.write().await =
+                Arc::new(feature_group_hyperparameters);
+        }
+    }
+
     pub async fn configure(
         &self,
         config: PersiaEmbeddingModelHyperparameters,
     ) -> Result<(), EmbeddingParameterServerError> {
+        self.configure_feature_groups(&config).await;
         {
             let mut conf_guard = self.hyperparameter_config.write().await;
             *conf_guard = Some(Arc::new(config));
@@ -450,18 +790,66 @@ impl EmbeddingParameterServiceInner {
         Ok(())
     }
 
+    /// Reloads the embedding config from the given yaml content. Only new feature groups and
+    /// slots can be added, the existing ones keep their index prefixes so the stored embeddings
+    /// stay valid.
+    pub async fn reload_embedding_config(
+        &self,
+        content: String,
+    ) -> Result<(), EmbeddingParameterServerError> {
+        let embedding_config = EmbeddingConfig::reload(content.as_str())?;
+        *self.embedding_config.write() = embedding_config;
+
+        let config = self.hyperparameter_config.read().await.clone();
+        if let Some(config) = config {
+            self.configure_feature_groups(config.as_ref()).await;
+        }
+        tracing::info!("embedding config reloaded");
+        Ok(())
+    }
+
     pub async fn dump(&self, dir: String) -> Result<(), EmbeddingParameterServerError> {
+        self.flush_accumulated_gradients().await?;
         let dst_dir = PathBuf::from(dir);
-        self.embedding_model_manager
-            .dump_embedding(dst_dir, self.embedding.clone())?;
+        let optimizer_state = self.optimizers.read().await.get_state();
+        // waits for a running resharding to finish before the shards can be pinned
+        tokio::task::block_in_place(|| {
+            self.embedding_model_manager.dump_embedding(
+                dst_dir,
+                self.embedding.clone(),
+                optimizer_state,
+            )
+        })?;
         Ok(())
     }
 
     pub async fn load(&self, dir: String) -> Result<(), EmbeddingParameterServerError> {
         let dst_dir = PathBuf::from(dir);
         let shard_dir = self.embedding_model_manager.get_shard_dir(&dst_dir);
+        let optimizer_state = self
+            .embedding_model_manager
+            .load_optimizer_state(&shard_dir)?;
+        // the buffered gradients were computed against the embeddings being replaced
+        if let Some(accumulator) = self.gradient_accumulator.as_ref() {
+            accumulator.clear();
+        }
         self.embedding_model_manager
             .load_embedding_from_dir(shard_dir, self.embedding.clone())?;
+        if let Some(optimizer_state) = optimizer_state {
+            let mut optimizers_guard = self.optimizers.write().await;
+            let mut optimizers = FeatureGroupOptimizers::clone(&optimizers_guard);
+            optimizers.load_state(optimizer_state);
+            *optimizers_guard = Arc::new(optimizers);
+        }
+        Ok(())
+    }
+
+    pub async fn reshard(
+        &self,
+        num_internal_shards: usize,
+    ) -> Result<(), EmbeddingParameterServerError> {
+        self.flush_accumulated_gradients().await?;
+        self.embedding.reshard(num_internal_shards)?;
         Ok(())
     }
 
@@ -484,7 +872,11 @@ impl EmbeddingParameterServiceInner {
     }
 
     pub async fn clear_embeddings(&self) -> Result<(), EmbeddingParameterServerError> {
-        Ok(self.embedding.clear())
+        if let Some(accumulator) = self.gradient_accumulator.as_ref() {
+            accumulator.clear();
+        }
+        self.embedding.clear();
+        Ok(())
     }
 }
 
@@ -552,6 +944,10 @@ impl EmbeddingParameterService {
         self.inner.load(req).await
     }
 
+    pub async fn reshard(&self, req: usize) -> Result<(), EmbeddingParameterServerError> {
+        self.inner.reshard(req).await
+    }
+
     pub async fn get_embedding_size(
         &self,
         _req: (),
@@ -565,9 +961,23 @@ impl EmbeddingParameterService {
 
     pub async fn register_optimizer(
         &self,
-        optimizer: OptimizerConfig,
+        req: (Vec<String>, OptimizerConfig),
     ) -> Result<(), EmbeddingParameterServerError> {
-        self.inner.register_optimizer(optimizer).await
+        self.inner.register_optimizer(req).await
+    }
+
+    pub async fn set_learning_rate(
+        &self,
+        req: LearningRateUpdate,
+    ) -> Result<(), EmbeddingParameterServerError> {
+        self.inner.set_learning_rate(req).await
+    }
+
+    pub async fn reload_embedding_config(
+        &self,
+        req: String,
+    ) -> Result<(), EmbeddingParameterServerError> {
+        self.inner.reload_embedding_config(req).await
     }
 
     pub async fn shutdown(&self, _req: ()) -> Result<(), EmbeddingParameterServerError> {
@@ -626,6 +1036,10 @@ impl EmbeddingParameterNatsService {
         self.inner.load(req).await
     }
 
+    pub async fn reshard(&self, req: usize) -> Result<(), EmbeddingParameterServerError> {
+        self.inner.reshard(req).await
+    }
+
     pub async fn get_address(&self, _req: ()) -> Result<String, EmbeddingParameterServerError> {
         self.inner.get_address().await
     }
@@ -639,8 +1053,22 @@ impl EmbeddingParameterNatsService {
 
     pub async fn register_optimizer(
         &self,
-        optimizer: OptimizerConfig,
+        req: (Vec<String>, OptimizerConfig),
+    ) -> Result<(), EmbeddingParameterServerError> {
+        self.inner.register_optimizer(req).await
+    }
+
+    pub async fn set_learning_rate(
+        &self,
+        req: LearningRateUpdate,
+    ) -> Result<(), EmbeddingParameterServerError> {
+        self.inner.set_learning_rate(req).await
+    }
+
+    pub async fn reload_embedding_config(
+        &self,
+        req: String,
     ) -> Result<(), EmbeddingParameterServerError> {
-        self.inner.register_optimizer(optimizer).await
+        self.inner.reload_embedding_config(req).await
     }
 }
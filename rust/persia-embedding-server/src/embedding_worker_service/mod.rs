@@ -26,7 +26,7 @@ use snafu::ResultExt;
 use persia_common::{
     grad::{EmbeddingGradientBatch, Gradients, SkippableFeatureEmbeddingGradientBatch},
     ndarray_f16_to_f32, ndarray_f32_to_f16,
-    optim::OptimizerConfig,
+    optim::{LearningRateUpdate, OptimizerConfig},
     EmbeddingBatch, FeatureEmbeddingBatch, FeatureRawEmbeddingBatch, FeatureSumEmbeddingBatch,
     IDTypeFeatureBatch, IDTypeFeatureRemoteRef, SingleSignInFeatureBatch,
 };
@@ -555,9 +555,7 @@ pub fn lookup_batched_all_slots_postprocess<'a>(
                 for (batch_id, _) in &single_sign.in_which_batch_samples {
                     let mut row = result.result.row_mut(*batch_id as usize);
                     let row = row.as_slice_mut().unwrap();
-                    unsafe {
-                        persia_simd::add_assign_avx2(row, emb);
-                    }
+                    persia_simd::add_assign(row, emb);
                 }
             }
         }
@@ -638,12 +636,16 @@ pub struct EmbeddingWorkerInner {
         persia_libs::async_lock::RwLock<HashMap<usize, HashMap<u64, IDTypeFeatureBatch>>>,
     pub post_forward_buffer: persia_libs::async_lock::RwLock<HashMap<u64, IDTypeFeatureBatch>>,
     pub staleness: AtomicUsize,
-    pub embedding_config: Arc<EmbeddingConfig>,
+    pub embedding_config: persia_libs::parking_lot::RwLock<Arc<EmbeddingConfig>>,
     pub embedding_worker_config: Arc<EmbeddingWorkerConfig>,
     pub embedding_model_manager: Arc<EmbeddingModelManager>,
 }
 
 impl EmbeddingWorkerInner {
+    pub fn embedding_config(&self) -> Arc<EmbeddingConfig> {
+        self.embedding_config.read().clone()
+    }
+
     fn get_id(&self) -> u64 {
         self.forward_id.fetch_add(1, Ordering::AcqRel)
     }
@@ -706,6 +708,7 @@ impl EmbeddingWorkerInner {
         indices: IDTypeFeatureBatch,
     ) -> Result<(), EmbeddingWorkerError> {
         let start_time = std::time::Instant::now();
+        let embedding_config = self.embedding_config();
 
         let indices_kv: HashMap<_, _> = indices
             .batches
@@ -723,11 +726,12 @@ impl EmbeddingWorkerInner {
                     let feature_batch = indices_kv
                         .get(&feature_gradient.feature_name.as_str())
                         .unwrap();
-                    let slot_conf = self
-                        .embedding_config
+                    let slot_conf = embedding_config
                         .get_slot_by_feature_name(feature_batch.feature_name.as_str());
                     let raw_gradients = std::mem::take(&mut feature_gradient.gradients);
 
+                    // non-finite gradients are rejected per sign by the embedding server, so
+                    // the other signs of the feature are still updated
                     if tokio::task::block_in_place(|| match &raw_gradients {
                         Gradients::F16(f16_gradients) => {
                             f16_gradients.as_slice().unwrap().iter().any(|x| x.is_nan())
@@ -736,13 +740,12 @@ impl EmbeddingWorkerInner {
                             f32_gradients.as_slice().unwrap().iter().any(|x| x.is_nan())
                         }
                     }) {
-                        tracing::warn!("nan found in gradient update, skipping");
+                        tracing::warn!("nan found in gradient update");
                         if let Ok(m) = MetricsHolder::get() {
                             m.nan_count
                                 .with_label_values(&[feature_batch.feature_name.as_str()])
                                 .inc();
                         }
-                        continue;
                     }
                     let mut f32_gradients = tokio::task::block_in_place(|| match raw_gradients {
                         Gradients::F16(gradients_array) => ndarray_f16_to_f32(&gradients_array),
@@ -790,22 +793,18 @@ impl EmbeddingWorkerInner {
                             if !slot_conf.embedding_summation {
                                 let batch_idx =
                                     hashed2index_batch_idx.get(&single_sign.sign).unwrap();
-                                unsafe {
-                                    persia_simd::add_assign_avx2(
-                                        sign_grad,
-                                        f32_gradients.row(*batch_idx as usize).as_slice().unwrap(),
-                                    );
-                                }
+                                persia_simd::add_assign(
+                                    sign_grad,
+                                    f32_gradients.row(*batch_idx as usize).as_slice().unwrap(),
+                                );
                             } else {
                                 single_sign.in_which_batch_samples.iter().for_each(
                                     |(batch_id, _)| {
                                         let row_grad = f32_gradients.row(*batch_id as usize);
-                                        unsafe {
-                                            persia_simd::add_assign_avx2(
-                                                sign_grad,
-                                                row_grad.as_slice().unwrap(),
-                                            );
-                                        }
+                                        persia_simd::add_assign(
+                                            sign_grad,
+                                            row_grad.as_slice().unwrap(),
+                                        );
                                     },
                                 );
                             }
@@ -879,8 +878,9 @@ impl EmbeddingWorkerInner {
         let start_time_all = std::time::Instant::now();
         let start_time = std::time::Instant::now();
 
+        let embedding_config = self.embedding_config();
         let all_shards_ids = tokio::task::block_in_place(|| {
-            lookup_batched_all_slots_preprocess(indices, &self.embedding_config, self.replica_size)
+            lookup_batched_all_slots_preprocess(indices, &embedding_config, self.replica_size)
         });
 
         let futs = all_shards_ids
@@ -927,7 +927,7 @@ impl EmbeddingWorkerInner {
         let start_time = std::time::Instant::now();
 
         let batches = tokio::task::block_in_place(|| {
-            lookup_batched_all_slots_postprocess(indices, forwarded_groups, &self.embedding_config)
+            lookup_batched_all_slots_postprocess(indices, forwarded_groups, &embedding_config)
         });
 
         tracing::debug!("summation time cost {:?}", start_time.elapsed());
@@ -1285,27 +1285,81 @@ impl EmbeddingWorkerInner {
 
     pub async fn register_optimizer(
         &self,
-        optimizer: OptimizerConfig,
+        req: (Vec<String>, OptimizerConfig),
     ) -> Result<(), EmbeddingWorkerError> {
         let inner = self.clone();
         let futs = (0..inner.all_embedding_server_client.replica_size()).map(|client_idx| {
-            let optimizer = optimizer.clone();
+            let req = req.clone();
             async move {
                 let client = inner
                     .all_embedding_server_client
                     .get_client_by_index(client_idx)
                     .await;
                 client
-                    .register_optimizer(&optimizer)
+                    .register_optimizer(&req)
                     .await
                     .map_err(|e| EmbeddingWorkerError::RpcError(e.to_string()))??;
                 Ok(())
             }
         });
-        tracing::info!("register optimizer: {:?}", &optimizer);
+        tracing::info!("register optimizer: {:?}", &req);
         futures::future::try_join_all(futs).await.map(|_| ())
     }
 
+    pub async fn set_learning_rate(
+        &self,
+        req: LearningRateUpdate,
+    ) -> Result<(), EmbeddingWorkerError> {
+        let inner = self.clone();
+        let futs = (0..inner.all_embedding_server_client.replica_size()).map(|client_idx| {
+            let req = req.clone();
+            async move {
+                let client = inner
+                    .all_embedding_server_client
+                    .get_client_by_index(client_idx)
+                    .await;
+                client
+                    .set_learning_rate(&req)
+                    .await
+                    .map_err(|e| EmbeddingWorkerError::RpcError(e.to_string()))??;
+                Ok(())
+            }
+        });
+        tracing::debug!("set learning rate: {:?}", &req);
+        futures::future::try_join_all(futs).await.map(|_| ())
+    }
+
+    /// Reloads the embedding config from the given yaml content, the embedding servers are
+    /// reloaded first when `reload_servers` is set.
+    pub async fn reload_embedding_config(
+        &self,
+        req: (String, bool),
+    ) -> Result<(), EmbeddingWorkerError> {
+        let (content, reload_servers) = req;
+        if reload_servers {
+            let futs = (0..self.all_embedding_server_client.replica_size()).map(|client_idx| {
+                let content = content.clone();
+                async move {
+                    let client = self
+                        .all_embedding_server_client
+                        .get_client_by_index(client_idx)
+                        .await;
+                    client
+                        .reload_embedding_config(&content)
+                        .await
+                        .map_err(|e| EmbeddingWorkerError::RpcError(e.to_string()))??;
+                    Ok::<_, EmbeddingWorkerError>(())
+                }
+            });
+            futures::future::try_join_all(futs).await?;
+        }
+
+        let embedding_config = EmbeddingConfig::reload(content.as_str())?;
+        *self.embedding_config.write() = embedding_config;
+        tracing::info!("embedding config reloaded");
+        Ok(())
+    }
+
     pub async fn get_address(&self) -> Result<String, EmbeddingWorkerError> {
         let instance_info = InstanceInfo::get()?;
         let address = format!("{}:{}", instance_info.ip_address, instance_info.port);
@@ -1484,9 +1538,23 @@ impl EmbeddingWorker {
 
     pub async fn register_optimizer(
         &self,
-        optimizer: OptimizerConfig,
+        req: (Vec<String>, OptimizerConfig),
     ) -> Result<(), EmbeddingWorkerError> {
-        self.inner.register_optimizer(optimizer).await
+        self.inner.register_optimizer(req).await
+    }
+
+    pub async fn set_learning_rate(
+        &self,
+        req: LearningRateUpdate,
+    ) -> Result<(), EmbeddingWorkerError> {
+        self.inner.set_learning_rate(req).await
+    }
+
+    pub async fn reload_embedding_config(
+        &self,
+        req: (String, bool),
+    ) -> Result<(), EmbeddingWorkerError> {
+        self.inner.reload_embedding_config(req).await
     }
 }
 
@@ -1546,9 +1614,23 @@ impl EmbeddingWorkerNatsService {
 
     pub async fn register_optimizer(
         &self,
-        optimizer: OptimizerConfig,
+        req: (Vec<String>, OptimizerConfig),
+    ) -> Result<(), EmbeddingWorkerError> {
+        self.inner.register_optimizer(req).await
+    }
+
+    pub async fn set_learning_rate(
+        &self,
+        req: LearningRateUpdate,
+    ) -> Result<(), EmbeddingWorkerError> {
+        self.inner.set_learning_rate(req).await
+    }
+
+    pub async fn reload_embedding_config(
+        &self,
+        req: (String, bool),
     ) -> Result<(), EmbeddingWorkerError> {
-        self.inner.register_optimizer(optimizer).await
+        self.inner.reload_embedding_config(req).await
     }
 
     pub async fn get_address(&self, _req: ()) -> Result<String, EmbeddingWorkerError> {
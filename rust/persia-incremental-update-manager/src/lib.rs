@@ -17,8 +17,8 @@ use persia_libs::{
 
 use persia_common::utils::ChannelPair;
 use persia_embedding_config::{
-    EmbeddingParameterServerConfig, PerisaJobType, PersiaCommonConfig, PersiaGlobalConfigError,
-    PersiaReplicaInfo,
+    EmbeddingConfig, EmbeddingParameterServerConfig, PerisaJobType, PersiaCommonConfig,
+    PersiaGlobalConfigError, PersiaReplicaInfo,
 };
 use persia_embedding_holder::{
     emb_entry::HashMapEmbeddingEntry, PersiaEmbeddingHolder, PersiaEmbeddingHolderError,
@@ -183,13 +183,7 @@ impl PerisaIncrementalUpdateManager {
         num_dumped_signs: Arc<AtomicUsize>,
         num_total_signs: usize,
     ) -> () {
-        let mut entries = Vec::with_capacity(signs.len());
-        signs.iter().for_each(|sign| {
-            let shard = self.embedding_holder.shard(sign).read();
-            if let Some(entry) = shard.get(sign) {
-                entries.push(entry.clone());
-            }
-        });
+        let entries = self.embedding_holder.get_entries(&signs);
 
         let segment_len = entries.len();
         let file_name = PathBuf::from(format!("{}_{}.inc", self.replica_index, file_index));
@@ -229,10 +223,16 @@ impl PerisaIncrementalUpdateManager {
             m.inc_update_delay_sec.set(delay as f64);
         }
         tracing::debug!("loading inc packet, delay is {}s", delay);
+        let embedding_config = match EmbeddingConfig::get() {
+            Ok(embedding_config) => embedding_config,
+            Err(e) => {
+                tracing::error!("failed to load inc packet, {:?}", e);
+                return;
+            }
+        };
         packet.content.into_iter().for_each(|entry| {
-            let sign = entry.sign();
-            let mut shard = self.embedding_holder.shard(&sign).write();
-            shard.insert(sign, entry);
+            self.embedding_holder
+                .insert_restored(entry, &embedding_config)
         });
     }
 
@@ -21,6 +21,7 @@ pub use rand;
 pub use rayon;
 pub use serde;
 pub use serde_bytes;
+pub use serde_ignored;
 pub use serde_yaml;
 pub use smol;
 pub use thiserror;
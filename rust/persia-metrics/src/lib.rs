@@ -134,6 +134,20 @@ impl PersiaMetricsManager {
         Ok(metrics)
     }
 
+    pub fn create_gauge_vec_with_label(
+        &self,
+        name: &str,
+        help: &str,
+        label: &str,
+    ) -> Result<GaugeVec, PersiaMetricsManagerError> {
+        let opts = Opts::new(name, help);
+        let metrics = GaugeVec::new(opts, &[label]).unwrap();
+        prometheus::default_registry()
+            .register(Box::new(metrics.clone()))
+            .unwrap();
+        Ok(metrics)
+    }
+
     pub fn create_histogram(
         &self,
         name: &str,
@@ -15,10 +15,13 @@ use persia_libs::{
     serde_yaml, thiserror, tracing,
 };
 
-use persia_embedding_config::{PersiaCommonConfig, PersiaGlobalConfigError, PersiaReplicaInfo};
+use persia_common::optim::FeatureGroupOptimizersState;
+use persia_embedding_config::{
+    EmbeddingConfig, PersiaCommonConfig, PersiaGlobalConfigError, PersiaReplicaInfo,
+};
 use persia_embedding_holder::{
-    array_linked_list::ArrayLinkedList, emb_entry::HashMapEmbeddingEntry, PersiaEmbeddingHolder,
-    PersiaEmbeddingHolderError,
+    array_linked_list::ArrayLinkedList, cursor::PinnedShards, emb_entry::HashMapEmbeddingEntry,
+    PersiaEmbeddingHolder, PersiaEmbeddingHolderError,
 };
 use persia_speedy::{Readable, Writable};
 use persia_storage::{PersiaPath, PersiaPathImpl};
@@ -68,6 +71,9 @@ pub enum EmbeddingModelManagerStatus {
     Failed(EmbeddingModelManagerError),
 }
 
+// max number of entries in one dumped file, bounds the memory used by dumping a shard
+const DUMP_CHUNK_SIZE: usize = 1_000_000;
+
 static EMBEDDING_MODEL_MANAGER: OnceCell<Arc<EmbeddingModelManager>> = OnceCell::new();
 
 #[derive(Clone)]
@@ -149,6 +155,37 @@ impl EmbeddingModelManager {
         PathBuf::from(file_name)
     }
 
+    pub fn get_optimizer_state_filename(&self) -> PathBuf {
+        PathBuf::from(format!("replica_{}.opt", self.replica_index))
+    }
+
+    pub fn dump_optimizer_state(
+        &self,
+        dst_dir: &PathBuf,
+        optimizer_state: &FeatureGroupOptimizersState,
+    ) -> Result<(), EmbeddingModelManagerError> {
+        let file_name = self.get_optimizer_state_filename();
+        let state_path = PersiaPath::from_vec(vec![dst_dir, &file_name]);
+        state_path.write_all_speedy(optimizer_state)?;
+        Ok(())
+    }
+
+    /// Loads the optimizer state dumped with the embeddings, checkpoints without one have
+    /// been dumped before it was part of them.
+    pub fn load_optimizer_state(
+        &self,
+        dir: &PathBuf,
+    ) -> Result<Option<FeatureGroupOptimizersState>, EmbeddingModelManagerError> {
+        let file_name = self.get_optimizer_state_filename();
+        let state_path = PersiaPath::from_vec(vec![dir, &file_name]);
+        if !state_path.is_file()? {
+            tracing::warn!("optimizer state not found in {:?}", dir);
+            return Ok(None);
+        }
+        let optimizer_state: FeatureGroupOptimizersState = state_path.read_to_end_speedy()?;
+        Ok(Some(optimizer_state))
+    }
+
     pub fn get_emb_dump_done_file_name(&self) -> PathBuf {
         PathBuf::from("embedding_dump_done")
     }
@@ -243,17 +280,42 @@ impl EmbeddingModelManager {
         &self,
         internal_shard_idx: usize,
         dst_dir: PathBuf,
-        embedding_holder: PersiaEmbeddingHolder,
+        shards: PinnedShards,
     ) -> Result<(), EmbeddingModelManagerError> {
-        let shard = embedding_holder
-            .get_shard_by_index(internal_shard_idx)
-            .read();
-
-        let file_name = self.get_internam_shard_filename(internal_shard_idx);
-        let emb_path = PersiaPath::from_vec(vec![&dst_dir, &file_name]);
+        let mut num_chunks = 0;
+        // a checkpoint is a consistent view of each internal shard
+        for batch in shards.shard_snapshot_cursor(internal_shard_idx, DUMP_CHUNK_SIZE) {
+            let mut chunk = ArrayLinkedList::with_capacity(batch.entries.len() as u32);
+            for entry in batch.entries {
+                chunk.push_back(entry);
+            }
+            self.dump_chunk(internal_shard_idx, num_chunks, &dst_dir, &chunk)?;
+            num_chunks += 1;
+        }
+        // an empty shard is still dumped so that every shard has at least one file
+        if num_chunks == 0 {
+            self.dump_chunk(internal_shard_idx, 0, &dst_dir, &ArrayLinkedList::new())?;
+        }
 
-        emb_path.write_all_speedy(&shard.linkedlist)?;
+        Ok(())
+    }
 
+    fn dump_chunk(
+        &self,
+        internal_shard_idx: usize,
+        chunk_idx: usize,
+        dst_dir: &PathBuf,
+        chunk: &ArrayLinkedList<HashMapEmbeddingEntry>,
+    ) -> Result<(), EmbeddingModelManagerError> {
+        let file_name = match chunk_idx {
+            0 => self.get_internam_shard_filename(internal_shard_idx),
+            _ => PathBuf::from(format!(
+                "replica_{}_shard_{}_{}.emb",
+                self.replica_index, internal_shard_idx, chunk_idx
+            )),
+        };
+        let emb_path = PersiaPath::from_vec(vec![dst_dir, &file_name]);
+        emb_path.write_all_speedy(chunk)?;
         Ok(())
     }
 
@@ -263,12 +325,11 @@ impl EmbeddingModelManager {
         embedding_holder: PersiaEmbeddingHolder,
     ) -> Result<(), EmbeddingModelManagerError> {
         let decoded = self.load_array_linked_list(file_path)?;
+        let embedding_config = EmbeddingConfig::get()?;
 
-        decoded.into_iter().for_each(|entry| {
-            let sign = entry.sign();
-            let mut shard = embedding_holder.shard(&sign).write();
-            shard.insert(sign, entry);
-        });
+        decoded
+            .into_iter()
+            .for_each(|entry| embedding_holder.insert_restored(entry, &embedding_config));
 
         Ok(())
     }
@@ -287,27 +348,32 @@ impl EmbeddingModelManager {
         &self,
         dst_dir: PathBuf,
         embedding_holder: PersiaEmbeddingHolder,
+        optimizer_state: FeatureGroupOptimizersState,
     ) -> Result<(), EmbeddingModelManagerError> {
+        let shard_dir = self.get_shard_dir(&dst_dir);
+        self.dump_optimizer_state(&shard_dir, &optimizer_state)?;
+        let manager = Self::get()?;
+
         *self.status.write() = EmbeddingModelManagerStatus::Dumping(0.0);
         tracing::info!("start to dump embedding to {:?}", dst_dir);
 
-        let shard_dir = self.get_shard_dir(&dst_dir);
-        let num_internal_shards = embedding_holder.num_internal_shards();
+        // resharding waits for the dump, so that every entry is dumped exactly once
+        let shards = embedding_holder.pin_shards();
+        let num_internal_shards = shards.num_internal_shards();
         let num_dumped_shards = Arc::new(AtomicUsize::new(0));
-        let manager = Self::get()?;
 
         (0..num_internal_shards).for_each(|internal_shard_idx| {
             let dst_dir = shard_dir.clone();
             let num_dumped_shards = num_dumped_shards.clone();
             let manager = manager.clone();
-            let embedding_holder = embedding_holder.clone();
+            let shards = shards.clone();
 
             self.thread_pool.spawn(move || {
                 let closure = || -> Result<(), EmbeddingModelManagerError> {
                     manager.dump_internal_shard_embeddings(
                         internal_shard_idx,
                         dst_dir.clone(),
-                        embedding_holder,
+                        shards,
                     )?;
 
                     let dumped = num_dumped_shards.fetch_add(1, Ordering::AcqRel) + 1;
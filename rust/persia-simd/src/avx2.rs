@@ -0,0 +1,756 @@
+//! AVX2 kernels, callers have to make sure the CPU supports AVX2 and FMA.
+
+use std::arch::x86_64::*;
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn add_assign(a: &mut [f32], b: &[f32]) {
+    let length = a.len();
+    let end = (length as isize / 8) * 8;
+    let a_ptr = a.as_ptr();
+    let b_ptr = b.as_ptr();
+    for i in (0..end).step_by(8) {
+        let a_v = _mm256_loadu_ps(a_ptr.offset(i));
+        let b_v = _mm256_loadu_ps(b_ptr.offset(i));
+        let result_v = _mm256_add_ps(a_v, b_v);
+        _mm256_storeu_ps(a_ptr.offset(i) as *mut f32, result_v);
+    }
+    for i in end as usize..length {
+        a[i] += b[i];
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn decayed_adagrad(
+    adagrad: &mut [f32],
+    embedding: &mut [f32],
+    gradient: &[f32],
+    g_square_momentum: f32,
+    learning_rate: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = adagrad.len();
+    let end = (length / 8) * 8;
+    let adagrad_ptr = adagrad.as_ptr();
+    let embedding_ptr = embedding.as_ptr();
+    let gradient_ptr = gradient.as_ptr();
+    for i in (0..end as isize).step_by(8) {
+        let adagrad_v = _mm256_loadu_ps(adagrad_ptr.offset(i));
+        let embedding_v = _mm256_loadu_ps(embedding_ptr.offset(i));
+        // weight decay
+        let gradient_v = _mm256_fmadd_ps(
+            _mm256_set1_ps(weight_decay),
+            embedding_v,
+            _mm256_loadu_ps(gradient_ptr.offset(i)),
+        );
+
+        let squared_v = _mm256_mul_ps(gradient_v, gradient_v);
+
+        let scaled_gradient_v = _mm256_mul_ps(
+            gradient_v,
+            _mm256_rsqrt_ps(_mm256_add_ps(adagrad_v, _mm256_set1_ps(eps))),
+        );
+
+        let embedding_result_v = _mm256_fnmadd_ps(
+            _mm256_set1_ps(learning_rate),
+            scaled_gradient_v,
+            embedding_v,
+        );
+
+        _mm256_storeu_ps(embedding_ptr.offset(i) as *mut f32, embedding_result_v);
+
+        let updated_adagrad_v =
+            _mm256_fmadd_ps(adagrad_v, _mm256_set1_ps(g_square_momentum), squared_v);
+
+        _mm256_storeu_ps(adagrad_ptr.offset(i) as *mut f32, updated_adagrad_v);
+    }
+
+    for i in end..length {
+        let adagrad_v = &adagrad[i];
+        let embedding_v = &embedding[i];
+        let gradient_v = &(gradient[i] + weight_decay * embedding_v);
+
+        let squared_v = gradient_v * gradient_v;
+
+        let scaled_gradient_v = gradient_v * (adagrad_v + eps).sqrt().recip();
+
+        let embedding_result_v = -learning_rate * scaled_gradient_v + embedding_v;
+
+        embedding[i] = embedding_result_v;
+
+        let updated_adagrad_v = adagrad_v * g_square_momentum + squared_v;
+
+        adagrad[i] = updated_adagrad_v;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+/// remember to update adagrad with gradient squared after this, the function will not do this.
+/// Returns the sum of the squared weight decayed gradients for that.
+pub unsafe fn decayed_adagrad_vectorwise_shared(
+    adagrad: f32,
+    embedding: &mut [f32],
+    gradient: &[f32],
+    learning_rate: f32,
+    eps: f32,
+    weight_decay: f32,
+) -> f32 {
+    let length = embedding.len();
+    let end = (length / 8) * 8;
+    let embedding_ptr = embedding.as_ptr();
+    let gradient_ptr = gradient.as_ptr();
+    let adagrad_v = _mm256_set1_ps(adagrad);
+    let mut squared_sum_v = _mm256_setzero_ps();
+    for i in (0..end as isize).step_by(8) {
+        let embedding_v = _mm256_loadu_ps(embedding_ptr.offset(i));
+        // weight decay
+        let gradient_v = _mm256_fmadd_ps(
+            _mm256_set1_ps(weight_decay),
+            embedding_v,
+            _mm256_loadu_ps(gradient_ptr.offset(i)),
+        );
+        squared_sum_v = _mm256_fmadd_ps(gradient_v, gradient_v, squared_sum_v);
+
+        let scaled_gradient_v = _mm256_mul_ps(
+            gradient_v,
+            _mm256_rsqrt_ps(_mm256_add_ps(adagrad_v, _mm256_set1_ps(eps))),
+        );
+
+        let embedding_result_v = _mm256_fnmadd_ps(
+            _mm256_set1_ps(learning_rate),
+            scaled_gradient_v,
+            embedding_v,
+        );
+
+        _mm256_storeu_ps(embedding_ptr.offset(i) as *mut f32, embedding_result_v);
+    }
+
+    let mut squared_sum = horizontal_sum_avx2(squared_sum_v);
+    for i in end..length {
+        let embedding_v = &embedding[i];
+        let gradient_v = &(gradient[i] + weight_decay * embedding_v);
+        squared_sum += gradient_v * gradient_v;
+
+        let scaled_gradient_v = gradient_v * (adagrad + eps).sqrt().recip();
+
+        let embedding_result_v = -learning_rate * scaled_gradient_v + embedding_v;
+
+        embedding[i] = embedding_result_v;
+    }
+    squared_sum
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn decayed_sgd(emb: &mut [f32], grad: &[f32], wd: f32, lr: f32) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    for i in (0..end as isize).step_by(8) {
+        let grad_v = _mm256_loadu_ps(grad_ptr.offset(i));
+        let emb_v = _mm256_loadu_ps(emb_ptr.offset(i));
+
+        // weight decay
+        let decay_grad_v = _mm256_fmadd_ps(_mm256_set1_ps(wd), emb_v, grad_v);
+        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), decay_grad_v, emb_v);
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let decay_grad_v = grad[i] + emb[i] * wd;
+        emb[i] = emb[i] - lr * decay_grad_v;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn adam(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let adam_m_ptr = adam_m.as_ptr();
+    let adam_v_ptr = adam_v.as_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    // precomputed the constant value
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+    let one_minus_beta1 = 1.0_f32 - beta1;
+    let one_minus_beta2 = 1.0_f32 - beta2;
+
+    for i in (0..end as isize).step_by(8) {
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        // L2 weight decay
+        let grad_vec = _mm256_fmadd_ps(
+            _mm256_set1_ps(weight_decay),
+            emb_vec,
+            _mm256_loadu_ps(grad_ptr.offset(i)),
+        );
+        let adam_v_vec = _mm256_loadu_ps(adam_v_ptr.offset(i));
+        let adam_m_vec = _mm256_loadu_ps(adam_m_ptr.offset(i));
+
+        let updated_m = _mm256_fmadd_ps(
+            _mm256_set1_ps(beta1),
+            adam_m_vec,
+            _mm256_mul_ps(_mm256_set1_ps(one_minus_beta1), grad_vec),
+        );
+
+        let updated_v = _mm256_fmadd_ps(
+            _mm256_set1_ps(beta2),
+            adam_v_vec,
+            _mm256_mul_ps(
+                _mm256_set1_ps(one_minus_beta2),
+                _mm256_mul_ps(grad_vec, grad_vec),
+            ),
+        );
+
+        let m_bias_corr = _mm256_mul_ps(updated_m, _mm256_set1_ps(one_minus_beta1_power_recip));
+        let v_bias_corr = _mm256_mul_ps(updated_v, _mm256_set1_ps(one_minus_beta2_power_recip));
+
+        let descent = _mm256_div_ps(
+            m_bias_corr,
+            _mm256_add_ps(_mm256_set1_ps(eps), _mm256_sqrt_ps(v_bias_corr)),
+        );
+
+        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), descent, emb_vec);
+
+        _mm256_storeu_ps(adam_m_ptr.offset(i) as *mut f32, updated_m);
+        _mm256_storeu_ps(adam_v_ptr.offset(i) as *mut f32, updated_v);
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let emb_val = &emb[i];
+        let grad_val = &(grad[i] + weight_decay * emb_val);
+        let adam_v_val = &adam_v[i];
+        let adam_m_val = &adam_m[i];
+
+        let updated_m = beta1 * adam_m_val + (one_minus_beta1) * grad_val;
+        let updated_v = beta2 * adam_v_val + (one_minus_beta2) * grad_val * grad_val;
+
+        let m_bias_corr = updated_m * one_minus_beta1_power_recip;
+        let v_bias_corr = updated_v * one_minus_beta2_power_recip;
+
+        let descent = m_bias_corr / (eps + v_bias_corr.sqrt());
+
+        let updated_emb = emb_val - lr * descent;
+
+        adam_m[i] = updated_m;
+        adam_v[i] = updated_v;
+        emb[i] = updated_emb;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+/// Adam with weight decay decoupled from the gradient moments
+pub unsafe fn adamw(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let adam_m_ptr = adam_m.as_ptr();
+    let adam_v_ptr = adam_v.as_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    // precomputed the constant value
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+    let one_minus_beta1 = 1.0_f32 - beta1;
+    let one_minus_beta2 = 1.0_f32 - beta2;
+
+    for i in (0..end as isize).step_by(8) {
+        let grad_vec = _mm256_loadu_ps(grad_ptr.offset(i));
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        let adam_v_vec = _mm256_loadu_ps(adam_v_ptr.offset(i));
+        let adam_m_vec = _mm256_loadu_ps(adam_m_ptr.offset(i));
+
+        let updated_m = _mm256_fmadd_ps(
+            _mm256_set1_ps(beta1),
+            adam_m_vec,
+            _mm256_mul_ps(_mm256_set1_ps(one_minus_beta1), grad_vec),
+        );
+
+        let updated_v = _mm256_fmadd_ps(
+            _mm256_set1_ps(beta2),
+            adam_v_vec,
+            _mm256_mul_ps(
+                _mm256_set1_ps(one_minus_beta2),
+                _mm256_mul_ps(grad_vec, grad_vec),
+            ),
+        );
+
+        let m_bias_corr = _mm256_mul_ps(updated_m, _mm256_set1_ps(one_minus_beta1_power_recip));
+        let v_bias_corr = _mm256_mul_ps(updated_v, _mm256_set1_ps(one_minus_beta2_power_recip));
+
+        let descent = _mm256_div_ps(
+            m_bias_corr,
+            _mm256_add_ps(_mm256_set1_ps(eps), _mm256_sqrt_ps(v_bias_corr)),
+        );
+        let decayed_descent = _mm256_fmadd_ps(_mm256_set1_ps(weight_decay), emb_vec, descent);
+
+        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), decayed_descent, emb_vec);
+
+        _mm256_storeu_ps(adam_m_ptr.offset(i) as *mut f32, updated_m);
+        _mm256_storeu_ps(adam_v_ptr.offset(i) as *mut f32, updated_v);
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let grad_val = &grad[i];
+        let emb_val = &emb[i];
+        let adam_v_val = &adam_v[i];
+        let adam_m_val = &adam_m[i];
+
+        let updated_m = beta1 * adam_m_val + (one_minus_beta1) * grad_val;
+        let updated_v = beta2 * adam_v_val + (one_minus_beta2) * grad_val * grad_val;
+
+        let m_bias_corr = updated_m * one_minus_beta1_power_recip;
+        let v_bias_corr = updated_v * one_minus_beta2_power_recip;
+
+        let descent = m_bias_corr / (eps + v_bias_corr.sqrt());
+        let decayed_descent = descent + weight_decay * emb_val;
+
+        let updated_emb = emb_val - lr * decayed_descent;
+
+        adam_m[i] = updated_m;
+        adam_v[i] = updated_v;
+        emb[i] = updated_emb;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn rmsprop(
+    rms: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    alpha: f32,
+    eps: f32,
+) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let rms_ptr = rms.as_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    let one_minus_alpha = 1.0_f32 - alpha;
+
+    for i in (0..end as isize).step_by(8) {
+        let grad_vec = _mm256_loadu_ps(grad_ptr.offset(i));
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        let rms_vec = _mm256_loadu_ps(rms_ptr.offset(i));
+
+        let updated_rms = _mm256_fmadd_ps(
+            _mm256_set1_ps(alpha),
+            rms_vec,
+            _mm256_mul_ps(
+                _mm256_set1_ps(one_minus_alpha),
+                _mm256_mul_ps(grad_vec, grad_vec),
+            ),
+        );
+
+        let descent = _mm256_div_ps(
+            grad_vec,
+            _mm256_add_ps(_mm256_sqrt_ps(updated_rms), _mm256_set1_ps(eps)),
+        );
+        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), descent, emb_vec);
+
+        _mm256_storeu_ps(rms_ptr.offset(i) as *mut f32, updated_rms);
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let grad_val = grad[i];
+
+        let updated_rms = alpha * rms[i] + one_minus_alpha * grad_val * grad_val;
+        let descent = grad_val / (updated_rms.sqrt() + eps);
+
+        rms[i] = updated_rms;
+        emb[i] -= lr * descent;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+/// SGD with momentum, Nesterov momentum if `nesterov` is set
+pub unsafe fn momentum_sgd(
+    momentum_buffer: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    wd: f32,
+    momentum: f32,
+    nesterov: bool,
+) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let momentum_buffer_ptr = momentum_buffer.as_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    for i in (0..end as isize).step_by(8) {
+        let grad_vec = _mm256_loadu_ps(grad_ptr.offset(i));
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        let momentum_buffer_vec = _mm256_loadu_ps(momentum_buffer_ptr.offset(i));
+
+        // weight decay
+        let decay_grad_vec = _mm256_fmadd_ps(_mm256_set1_ps(wd), emb_vec, grad_vec);
+        let updated_buffer = _mm256_fmadd_ps(
+            _mm256_set1_ps(momentum),
+            momentum_buffer_vec,
+            decay_grad_vec,
+        );
+        let descent = match nesterov {
+            true => _mm256_fmadd_ps(_mm256_set1_ps(momentum), updated_buffer, decay_grad_vec),
+            false => updated_buffer,
+        };
+        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), descent, emb_vec);
+
+        _mm256_storeu_ps(momentum_buffer_ptr.offset(i) as *mut f32, updated_buffer);
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let decay_grad_val = grad[i] + emb[i] * wd;
+        let updated_buffer = momentum * momentum_buffer[i] + decay_grad_val;
+        let descent = match nesterov {
+            true => decay_grad_val + momentum * updated_buffer,
+            false => updated_buffer,
+        };
+
+        momentum_buffer[i] = updated_buffer;
+        emb[i] -= lr * descent;
+    }
+}
+
+#[inline]
+#[target_feature(enable = "avx")]
+unsafe fn horizontal_sum_avx2(v: __m256) -> f32 {
+    let mut lanes = [0.0_f32; 8];
+    _mm256_storeu_ps(lanes.as_mut_ptr(), v);
+    lanes.iter().sum()
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+/// LAMB, Adam with decoupled weight decay whose update is scaled by the trust ratio
+/// `||emb|| / ||update||` of the whole embedding
+pub unsafe fn lamb(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let (emb_norm, update_norm) = lamb_moments(
+        adam_m,
+        adam_v,
+        beta1_power,
+        beta2_power,
+        emb,
+        grad,
+        beta1,
+        beta2,
+        eps,
+        weight_decay,
+    );
+    let scaled_lr = lr * crate::lamb_trust_ratio(emb_norm, update_norm);
+    lamb_descent(
+        adam_m,
+        adam_v,
+        beta1_power,
+        beta2_power,
+        emb,
+        eps,
+        weight_decay,
+        scaled_lr,
+    );
+}
+
+/// First pass of LAMB, updates the moments and returns the squared norms of the embedding and
+/// its update
+#[target_feature(enable = "avx2,fma")]
+pub(crate) unsafe fn lamb_moments(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &[f32],
+    grad: &[f32],
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) -> (f32, f32) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let adam_m_ptr = adam_m.as_ptr();
+    let adam_v_ptr = adam_v.as_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    // precomputed the constant value
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+    let one_minus_beta1 = 1.0_f32 - beta1;
+    let one_minus_beta2 = 1.0_f32 - beta2;
+
+    let mut emb_norm_vec = _mm256_setzero_ps();
+    let mut update_norm_vec = _mm256_setzero_ps();
+    for i in (0..end as isize).step_by(8) {
+        let grad_vec = _mm256_loadu_ps(grad_ptr.offset(i));
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        let adam_v_vec = _mm256_loadu_ps(adam_v_ptr.offset(i));
+        let adam_m_vec = _mm256_loadu_ps(adam_m_ptr.offset(i));
+
+        let updated_m = _mm256_fmadd_ps(
+            _mm256_set1_ps(beta1),
+            adam_m_vec,
+            _mm256_mul_ps(_mm256_set1_ps(one_minus_beta1), grad_vec),
+        );
+
+        let updated_v = _mm256_fmadd_ps(
+            _mm256_set1_ps(beta2),
+            adam_v_vec,
+            _mm256_mul_ps(
+                _mm256_set1_ps(one_minus_beta2),
+                _mm256_mul_ps(grad_vec, grad_vec),
+            ),
+        );
+
+        let m_bias_corr = _mm256_mul_ps(updated_m, _mm256_set1_ps(one_minus_beta1_power_recip));
+        let v_bias_corr = _mm256_mul_ps(updated_v, _mm256_set1_ps(one_minus_beta2_power_recip));
+
+        let descent = _mm256_div_ps(
+            m_bias_corr,
+            _mm256_add_ps(_mm256_set1_ps(eps), _mm256_sqrt_ps(v_bias_corr)),
+        );
+        let decayed_descent = _mm256_fmadd_ps(_mm256_set1_ps(weight_decay), emb_vec, descent);
+
+        emb_norm_vec = _mm256_fmadd_ps(emb_vec, emb_vec, emb_norm_vec);
+        update_norm_vec = _mm256_fmadd_ps(decayed_descent, decayed_descent, update_norm_vec);
+
+        _mm256_storeu_ps(adam_m_ptr.offset(i) as *mut f32, updated_m);
+        _mm256_storeu_ps(adam_v_ptr.offset(i) as *mut f32, updated_v);
+    }
+
+    let mut emb_norm = horizontal_sum_avx2(emb_norm_vec);
+    let mut update_norm = horizontal_sum_avx2(update_norm_vec);
+    for i in end..length {
+        let grad_val = &grad[i];
+        let emb_val = &emb[i];
+
+        let updated_m = beta1 * adam_m[i] + (one_minus_beta1) * grad_val;
+        let updated_v = beta2 * adam_v[i] + (one_minus_beta2) * grad_val * grad_val;
+
+        let m_bias_corr = updated_m * one_minus_beta1_power_recip;
+        let v_bias_corr = updated_v * one_minus_beta2_power_recip;
+
+        let descent = m_bias_corr / (eps + v_bias_corr.sqrt());
+        let decayed_descent = descent + weight_decay * emb_val;
+
+        emb_norm += emb_val * emb_val;
+        update_norm += decayed_descent * decayed_descent;
+
+        adam_m[i] = updated_m;
+        adam_v[i] = updated_v;
+    }
+
+    (emb_norm, update_norm)
+}
+
+/// Second pass of LAMB, applies the update from the updated moments
+#[target_feature(enable = "avx2,fma")]
+pub(crate) unsafe fn lamb_descent(
+    adam_m: &[f32],
+    adam_v: &[f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    eps: f32,
+    weight_decay: f32,
+    scaled_lr: f32,
+) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let adam_m_ptr = adam_m.as_ptr();
+    let adam_v_ptr = adam_v.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+
+    for i in (0..end as isize).step_by(8) {
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        let adam_v_vec = _mm256_loadu_ps(adam_v_ptr.offset(i));
+        let adam_m_vec = _mm256_loadu_ps(adam_m_ptr.offset(i));
+
+        let m_bias_corr = _mm256_mul_ps(adam_m_vec, _mm256_set1_ps(one_minus_beta1_power_recip));
+        let v_bias_corr = _mm256_mul_ps(adam_v_vec, _mm256_set1_ps(one_minus_beta2_power_recip));
+
+        let descent = _mm256_div_ps(
+            m_bias_corr,
+            _mm256_add_ps(_mm256_set1_ps(eps), _mm256_sqrt_ps(v_bias_corr)),
+        );
+        let decayed_descent = _mm256_fmadd_ps(_mm256_set1_ps(weight_decay), emb_vec, descent);
+
+        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(scaled_lr), decayed_descent, emb_vec);
+
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let m_bias_corr = adam_m[i] * one_minus_beta1_power_recip;
+        let v_bias_corr = adam_v[i] * one_minus_beta2_power_recip;
+
+        let descent = m_bias_corr / (eps + v_bias_corr.sqrt());
+        let decayed_descent = descent + weight_decay * emb[i];
+
+        emb[i] -= scaled_lr * decayed_descent;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+/// FTRL-Proximal update, `ftrl_z` and `ftrl_n` are the per coordinate linear and squared
+/// gradient accumulators
+pub unsafe fn ftrl(
+    ftrl_z: &mut [f32],
+    ftrl_n: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta: f32,
+    lambda1: f32,
+    lambda2: f32,
+) {
+    let length = emb.len();
+    let end = (length / 8) * 8; // divide by simd step
+    let ftrl_z_ptr = ftrl_z.as_ptr();
+    let ftrl_n_ptr = ftrl_n.as_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_ptr();
+
+    let lr_recip = lr.recip();
+    let sign_mask_vec = _mm256_set1_ps(-0.0_f32);
+    let lambda1_vec = _mm256_set1_ps(lambda1);
+
+    for i in (0..end as isize).step_by(8) {
+        let grad_vec = _mm256_loadu_ps(grad_ptr.offset(i));
+        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
+        let ftrl_z_vec = _mm256_loadu_ps(ftrl_z_ptr.offset(i));
+        let ftrl_n_vec = _mm256_loadu_ps(ftrl_n_ptr.offset(i));
+
+        let updated_n = _mm256_add_ps(ftrl_n_vec, _mm256_mul_ps(grad_vec, grad_vec));
+        let updated_n_sqrt = _mm256_sqrt_ps(updated_n);
+        let sigma = _mm256_mul_ps(
+            _mm256_sub_ps(updated_n_sqrt, _mm256_sqrt_ps(ftrl_n_vec)),
+            _mm256_set1_ps(lr_recip),
+        );
+        let updated_z = _mm256_sub_ps(
+            _mm256_add_ps(ftrl_z_vec, grad_vec),
+            _mm256_mul_ps(sigma, emb_vec),
+        );
+
+        // -(z - sign(z) * lambda1) / ((beta + sqrt(n)) / lr + lambda2), zero if |z| <= lambda1
+        let signed_lambda1 = _mm256_or_ps(_mm256_and_ps(updated_z, sign_mask_vec), lambda1_vec);
+        let denominator = _mm256_add_ps(
+            _mm256_mul_ps(
+                _mm256_add_ps(_mm256_set1_ps(beta), updated_n_sqrt),
+                _mm256_set1_ps(lr_recip),
+            ),
+            _mm256_set1_ps(lambda2),
+        );
+        let updated_emb = _mm256_div_ps(_mm256_sub_ps(signed_lambda1, updated_z), denominator);
+        let sparse_mask = _mm256_cmp_ps(
+            _mm256_andnot_ps(sign_mask_vec, updated_z),
+            lambda1_vec,
+            _CMP_LE_OQ,
+        );
+        let updated_emb = _mm256_andnot_ps(sparse_mask, updated_emb);
+
+        _mm256_storeu_ps(ftrl_z_ptr.offset(i) as *mut f32, updated_z);
+        _mm256_storeu_ps(ftrl_n_ptr.offset(i) as *mut f32, updated_n);
+        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
+    }
+
+    for i in end..length {
+        let grad_val = grad[i];
+
+        let updated_n = ftrl_n[i] + grad_val * grad_val;
+        let updated_n_sqrt = updated_n.sqrt();
+        let sigma = (updated_n_sqrt - ftrl_n[i].sqrt()) * lr_recip;
+        let updated_z = (ftrl_z[i] + grad_val) - sigma * emb[i];
+
+        let updated_emb = if updated_z.abs() <= lambda1 {
+            0.0_f32
+        } else {
+            let denominator = (beta + updated_n_sqrt) * lr_recip + lambda2;
+            (lambda1.copysign(updated_z) - updated_z) / denominator
+        };
+
+        ftrl_z[i] = updated_z;
+        ftrl_n[i] = updated_n;
+        emb[i] = updated_emb;
+    }
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn weight_bound(embedding: &mut [f32], weight_bound: f32) {
+    let length = embedding.len();
+    let end = (length / 8) * 8;
+    let embedding_ptr = embedding.as_ptr();
+    let neg_weight_bound = -weight_bound;
+    for i in (0..end as isize).step_by(8) {
+        let embedding_v = _mm256_loadu_ps(embedding_ptr.offset(i));
+
+        let bounded_embedding_v = _mm256_min_ps(
+            _mm256_max_ps(embedding_v, _mm256_set1_ps(neg_weight_bound)),
+            _mm256_set1_ps(weight_bound),
+        );
+
+        _mm256_storeu_ps(embedding_ptr.offset(i) as *mut f32, bounded_embedding_v);
+    }
+
+    for embedding_v in embedding.iter_mut().skip(end) {
+        let bounded_embedding_v = embedding_v.max(neg_weight_bound).min(weight_bound);
+        *embedding_v = bounded_embedding_v;
+    }
+}
@@ -0,0 +1,631 @@
+//! AVX-512 kernels, callers have to make sure the CPU supports AVX-512F, AVX2 and FMA.
+//!
+//! Each kernel updates the 16 lanes blocks and hands the remaining elements to the AVX2
+//! kernel, so embeddings shorter than 16 are updated exactly like with AVX2.
+
+use std::arch::x86_64::*;
+
+use crate::avx2;
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+pub unsafe fn add_assign(a: &mut [f32], b: &[f32]) {
+    let length = a.len();
+    let end = (length / 16) * 16;
+    let a_ptr = a.as_mut_ptr();
+    let b_ptr = b.as_ptr();
+    for i in (0..end).step_by(16) {
+        let a_v = _mm512_loadu_ps(a_ptr.add(i));
+        let b_v = _mm512_loadu_ps(b_ptr.add(i));
+        _mm512_storeu_ps(a_ptr.add(i), _mm512_add_ps(a_v, b_v));
+    }
+    avx2::add_assign(&mut a[end..], &b[end..]);
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+pub unsafe fn decayed_adagrad(
+    adagrad: &mut [f32],
+    embedding: &mut [f32],
+    gradient: &[f32],
+    g_square_momentum: f32,
+    learning_rate: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = adagrad.len();
+    let end = (length / 16) * 16;
+    let adagrad_ptr = adagrad.as_mut_ptr();
+    let embedding_ptr = embedding.as_mut_ptr();
+    let gradient_ptr = gradient.as_ptr();
+    for i in (0..end).step_by(16) {
+        let adagrad_v = _mm512_loadu_ps(adagrad_ptr.add(i));
+        let embedding_v = _mm512_loadu_ps(embedding_ptr.add(i));
+        // weight decay
+        let gradient_v = _mm512_fmadd_ps(
+            _mm512_set1_ps(weight_decay),
+            embedding_v,
+            _mm512_loadu_ps(gradient_ptr.add(i)),
+        );
+
+        let squared_v = _mm512_mul_ps(gradient_v, gradient_v);
+
+        let scaled_gradient_v = _mm512_mul_ps(
+            gradient_v,
+            _mm512_rsqrt14_ps(_mm512_add_ps(adagrad_v, _mm512_set1_ps(eps))),
+        );
+
+        let embedding_result_v = _mm512_fnmadd_ps(
+            _mm512_set1_ps(learning_rate),
+            scaled_gradient_v,
+            embedding_v,
+        );
+
+        _mm512_storeu_ps(embedding_ptr.add(i), embedding_result_v);
+
+        let updated_adagrad_v =
+            _mm512_fmadd_ps(adagrad_v, _mm512_set1_ps(g_square_momentum), squared_v);
+
+        _mm512_storeu_ps(adagrad_ptr.add(i), updated_adagrad_v);
+    }
+
+    avx2::decayed_adagrad(
+        &mut adagrad[end..],
+        &mut embedding[end..],
+        &gradient[end..],
+        g_square_momentum,
+        learning_rate,
+        eps,
+        weight_decay,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+/// remember to update adagrad with gradient squared after this, the function will not do this.
+/// Returns the sum of the squared weight decayed gradients for that.
+pub unsafe fn decayed_adagrad_vectorwise_shared(
+    adagrad: f32,
+    embedding: &mut [f32],
+    gradient: &[f32],
+    learning_rate: f32,
+    eps: f32,
+    weight_decay: f32,
+) -> f32 {
+    let length = embedding.len();
+    let end = (length / 16) * 16;
+    let embedding_ptr = embedding.as_mut_ptr();
+    let gradient_ptr = gradient.as_ptr();
+    let adagrad_rsqrt_v = _mm512_rsqrt14_ps(_mm512_set1_ps(adagrad + eps));
+    let mut squared_sum_v = _mm512_setzero_ps();
+    for i in (0..end).step_by(16) {
+        let embedding_v = _mm512_loadu_ps(embedding_ptr.add(i));
+        // weight decay
+        let gradient_v = _mm512_fmadd_ps(
+            _mm512_set1_ps(weight_decay),
+            embedding_v,
+            _mm512_loadu_ps(gradient_ptr.add(i)),
+        );
+        squared_sum_v = _mm512_fmadd_ps(gradient_v, gradient_v, squared_sum_v);
+
+        let scaled_gradient_v = _mm512_mul_ps(gradient_v, adagrad_rsqrt_v);
+
+        let embedding_result_v = _mm512_fnmadd_ps(
+            _mm512_set1_ps(learning_rate),
+            scaled_gradient_v,
+            embedding_v,
+        );
+
+        _mm512_storeu_ps(embedding_ptr.add(i), embedding_result_v);
+    }
+
+    _mm512_reduce_add_ps(squared_sum_v)
+        + avx2::decayed_adagrad_vectorwise_shared(
+            adagrad,
+            &mut embedding[end..],
+            &gradient[end..],
+            learning_rate,
+            eps,
+            weight_decay,
+        )
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+pub unsafe fn decayed_sgd(emb: &mut [f32], grad: &[f32], wd: f32, lr: f32) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    for i in (0..end).step_by(16) {
+        let grad_v = _mm512_loadu_ps(grad_ptr.add(i));
+        let emb_v = _mm512_loadu_ps(emb_ptr.add(i));
+
+        // weight decay
+        let decay_grad_v = _mm512_fmadd_ps(_mm512_set1_ps(wd), emb_v, grad_v);
+        let updated_emb = _mm512_fnmadd_ps(_mm512_set1_ps(lr), decay_grad_v, emb_v);
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::decayed_sgd(&mut emb[end..], &grad[end..], wd, lr);
+}
+
+/// Adam descent `m_hat / (eps + sqrt(v_hat))` of the updated moments
+#[inline]
+#[target_feature(enable = "avx512f")]
+unsafe fn adam_descent(
+    updated_m: __m512,
+    updated_v: __m512,
+    one_minus_beta1_power_recip: f32,
+    one_minus_beta2_power_recip: f32,
+    eps: f32,
+) -> __m512 {
+    let m_bias_corr = _mm512_mul_ps(updated_m, _mm512_set1_ps(one_minus_beta1_power_recip));
+    let v_bias_corr = _mm512_mul_ps(updated_v, _mm512_set1_ps(one_minus_beta2_power_recip));
+
+    _mm512_div_ps(
+        m_bias_corr,
+        _mm512_add_ps(_mm512_set1_ps(eps), _mm512_sqrt_ps(v_bias_corr)),
+    )
+}
+
+/// Updated first and second moments of Adam
+#[inline]
+#[target_feature(enable = "avx512f")]
+unsafe fn adam_moments(
+    adam_m_vec: __m512,
+    adam_v_vec: __m512,
+    grad_vec: __m512,
+    beta1: f32,
+    beta2: f32,
+) -> (__m512, __m512) {
+    let updated_m = _mm512_fmadd_ps(
+        _mm512_set1_ps(beta1),
+        adam_m_vec,
+        _mm512_mul_ps(_mm512_set1_ps(1.0_f32 - beta1), grad_vec),
+    );
+
+    let updated_v = _mm512_fmadd_ps(
+        _mm512_set1_ps(beta2),
+        adam_v_vec,
+        _mm512_mul_ps(
+            _mm512_set1_ps(1.0_f32 - beta2),
+            _mm512_mul_ps(grad_vec, grad_vec),
+        ),
+    );
+
+    (updated_m, updated_v)
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+pub unsafe fn adam(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let adam_m_ptr = adam_m.as_mut_ptr();
+    let adam_v_ptr = adam_v.as_mut_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+
+    for i in (0..end).step_by(16) {
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        // L2 weight decay
+        let grad_vec = _mm512_fmadd_ps(
+            _mm512_set1_ps(weight_decay),
+            emb_vec,
+            _mm512_loadu_ps(grad_ptr.add(i)),
+        );
+        let (updated_m, updated_v) = adam_moments(
+            _mm512_loadu_ps(adam_m_ptr.add(i)),
+            _mm512_loadu_ps(adam_v_ptr.add(i)),
+            grad_vec,
+            beta1,
+            beta2,
+        );
+        let descent = adam_descent(
+            updated_m,
+            updated_v,
+            one_minus_beta1_power_recip,
+            one_minus_beta2_power_recip,
+            eps,
+        );
+
+        let updated_emb = _mm512_fnmadd_ps(_mm512_set1_ps(lr), descent, emb_vec);
+
+        _mm512_storeu_ps(adam_m_ptr.add(i), updated_m);
+        _mm512_storeu_ps(adam_v_ptr.add(i), updated_v);
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::adam(
+        &mut adam_m[end..],
+        &mut adam_v[end..],
+        beta1_power,
+        beta2_power,
+        &mut emb[end..],
+        &grad[end..],
+        lr,
+        beta1,
+        beta2,
+        eps,
+        weight_decay,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+/// Adam with weight decay decoupled from the gradient moments
+pub unsafe fn adamw(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let adam_m_ptr = adam_m.as_mut_ptr();
+    let adam_v_ptr = adam_v.as_mut_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+
+    for i in (0..end).step_by(16) {
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        let (updated_m, updated_v) = adam_moments(
+            _mm512_loadu_ps(adam_m_ptr.add(i)),
+            _mm512_loadu_ps(adam_v_ptr.add(i)),
+            _mm512_loadu_ps(grad_ptr.add(i)),
+            beta1,
+            beta2,
+        );
+        let descent = adam_descent(
+            updated_m,
+            updated_v,
+            one_minus_beta1_power_recip,
+            one_minus_beta2_power_recip,
+            eps,
+        );
+        let decayed_descent = _mm512_fmadd_ps(_mm512_set1_ps(weight_decay), emb_vec, descent);
+
+        let updated_emb = _mm512_fnmadd_ps(_mm512_set1_ps(lr), decayed_descent, emb_vec);
+
+        _mm512_storeu_ps(adam_m_ptr.add(i), updated_m);
+        _mm512_storeu_ps(adam_v_ptr.add(i), updated_v);
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::adamw(
+        &mut adam_m[end..],
+        &mut adam_v[end..],
+        beta1_power,
+        beta2_power,
+        &mut emb[end..],
+        &grad[end..],
+        lr,
+        beta1,
+        beta2,
+        eps,
+        weight_decay,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+pub unsafe fn rmsprop(
+    rms: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    alpha: f32,
+    eps: f32,
+) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let rms_ptr = rms.as_mut_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    for i in (0..end).step_by(16) {
+        let grad_vec = _mm512_loadu_ps(grad_ptr.add(i));
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        let rms_vec = _mm512_loadu_ps(rms_ptr.add(i));
+
+        let updated_rms = _mm512_fmadd_ps(
+            _mm512_set1_ps(alpha),
+            rms_vec,
+            _mm512_mul_ps(
+                _mm512_set1_ps(1.0_f32 - alpha),
+                _mm512_mul_ps(grad_vec, grad_vec),
+            ),
+        );
+
+        let descent = _mm512_div_ps(
+            grad_vec,
+            _mm512_add_ps(_mm512_sqrt_ps(updated_rms), _mm512_set1_ps(eps)),
+        );
+        let updated_emb = _mm512_fnmadd_ps(_mm512_set1_ps(lr), descent, emb_vec);
+
+        _mm512_storeu_ps(rms_ptr.add(i), updated_rms);
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::rmsprop(
+        &mut rms[end..],
+        &mut emb[end..],
+        &grad[end..],
+        lr,
+        alpha,
+        eps,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+/// SGD with momentum, Nesterov momentum if `nesterov` is set
+pub unsafe fn momentum_sgd(
+    momentum_buffer: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    wd: f32,
+    momentum: f32,
+    nesterov: bool,
+) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let momentum_buffer_ptr = momentum_buffer.as_mut_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    for i in (0..end).step_by(16) {
+        let grad_vec = _mm512_loadu_ps(grad_ptr.add(i));
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        let momentum_buffer_vec = _mm512_loadu_ps(momentum_buffer_ptr.add(i));
+
+        // weight decay
+        let decay_grad_vec = _mm512_fmadd_ps(_mm512_set1_ps(wd), emb_vec, grad_vec);
+        let updated_buffer = _mm512_fmadd_ps(
+            _mm512_set1_ps(momentum),
+            momentum_buffer_vec,
+            decay_grad_vec,
+        );
+        let descent = match nesterov {
+            true => _mm512_fmadd_ps(_mm512_set1_ps(momentum), updated_buffer, decay_grad_vec),
+            false => updated_buffer,
+        };
+        let updated_emb = _mm512_fnmadd_ps(_mm512_set1_ps(lr), descent, emb_vec);
+
+        _mm512_storeu_ps(momentum_buffer_ptr.add(i), updated_buffer);
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::momentum_sgd(
+        &mut momentum_buffer[end..],
+        &mut emb[end..],
+        &grad[end..],
+        lr,
+        wd,
+        momentum,
+        nesterov,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+/// LAMB, Adam with decoupled weight decay whose update is scaled by the trust ratio
+/// `||emb|| / ||update||` of the whole embedding
+pub unsafe fn lamb(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let adam_m_ptr = adam_m.as_mut_ptr();
+    let adam_v_ptr = adam_v.as_mut_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+
+    // first pass updates the moments and computes the norms of the embedding and its update
+    let mut emb_norm_vec = _mm512_setzero_ps();
+    let mut update_norm_vec = _mm512_setzero_ps();
+    for i in (0..end).step_by(16) {
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        let (updated_m, updated_v) = adam_moments(
+            _mm512_loadu_ps(adam_m_ptr.add(i)),
+            _mm512_loadu_ps(adam_v_ptr.add(i)),
+            _mm512_loadu_ps(grad_ptr.add(i)),
+            beta1,
+            beta2,
+        );
+        let descent = adam_descent(
+            updated_m,
+            updated_v,
+            one_minus_beta1_power_recip,
+            one_minus_beta2_power_recip,
+            eps,
+        );
+        let decayed_descent = _mm512_fmadd_ps(_mm512_set1_ps(weight_decay), emb_vec, descent);
+
+        emb_norm_vec = _mm512_fmadd_ps(emb_vec, emb_vec, emb_norm_vec);
+        update_norm_vec = _mm512_fmadd_ps(decayed_descent, decayed_descent, update_norm_vec);
+
+        _mm512_storeu_ps(adam_m_ptr.add(i), updated_m);
+        _mm512_storeu_ps(adam_v_ptr.add(i), updated_v);
+    }
+
+    let (rest_emb_norm, rest_update_norm) = avx2::lamb_moments(
+        &mut adam_m[end..],
+        &mut adam_v[end..],
+        beta1_power,
+        beta2_power,
+        &emb[end..],
+        &grad[end..],
+        beta1,
+        beta2,
+        eps,
+        weight_decay,
+    );
+    let emb_norm = _mm512_reduce_add_ps(emb_norm_vec) + rest_emb_norm;
+    let update_norm = _mm512_reduce_add_ps(update_norm_vec) + rest_update_norm;
+    let scaled_lr = lr * crate::lamb_trust_ratio(emb_norm, update_norm);
+
+    // second pass applies the update from the updated moments
+    for i in (0..end).step_by(16) {
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        let descent = adam_descent(
+            _mm512_loadu_ps(adam_m_ptr.add(i)),
+            _mm512_loadu_ps(adam_v_ptr.add(i)),
+            one_minus_beta1_power_recip,
+            one_minus_beta2_power_recip,
+            eps,
+        );
+        let decayed_descent = _mm512_fmadd_ps(_mm512_set1_ps(weight_decay), emb_vec, descent);
+
+        let updated_emb = _mm512_fnmadd_ps(_mm512_set1_ps(scaled_lr), decayed_descent, emb_vec);
+
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::lamb_descent(
+        &adam_m[end..],
+        &adam_v[end..],
+        beta1_power,
+        beta2_power,
+        &mut emb[end..],
+        eps,
+        weight_decay,
+        scaled_lr,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+/// FTRL-Proximal update, `ftrl_z` and `ftrl_n` are the per coordinate linear and squared
+/// gradient accumulators
+pub unsafe fn ftrl(
+    ftrl_z: &mut [f32],
+    ftrl_n: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta: f32,
+    lambda1: f32,
+    lambda2: f32,
+) {
+    let length = emb.len();
+    let end = (length / 16) * 16; // divide by simd step
+    let ftrl_z_ptr = ftrl_z.as_mut_ptr();
+    let ftrl_n_ptr = ftrl_n.as_mut_ptr();
+    let grad_ptr = grad.as_ptr();
+    let emb_ptr = emb.as_mut_ptr();
+
+    let lr_recip = lr.recip();
+    let sign_mask_vec = _mm512_set1_epi32(i32::MIN);
+    let lambda1_vec = _mm512_set1_ps(lambda1);
+
+    for i in (0..end).step_by(16) {
+        let grad_vec = _mm512_loadu_ps(grad_ptr.add(i));
+        let emb_vec = _mm512_loadu_ps(emb_ptr.add(i));
+        let ftrl_z_vec = _mm512_loadu_ps(ftrl_z_ptr.add(i));
+        let ftrl_n_vec = _mm512_loadu_ps(ftrl_n_ptr.add(i));
+
+        let updated_n = _mm512_add_ps(ftrl_n_vec, _mm512_mul_ps(grad_vec, grad_vec));
+        let updated_n_sqrt = _mm512_sqrt_ps(updated_n);
+        let sigma = _mm512_mul_ps(
+            _mm512_sub_ps(updated_n_sqrt, _mm512_sqrt_ps(ftrl_n_vec)),
+            _mm512_set1_ps(lr_recip),
+        );
+        let updated_z = _mm512_sub_ps(
+            _mm512_add_ps(ftrl_z_vec, grad_vec),
+            _mm512_mul_ps(sigma, emb_vec),
+        );
+
+        // -(z - sign(z) * lambda1) / ((beta + sqrt(n)) / lr + lambda2), zero if |z| <= lambda1
+        let signed_lambda1 = _mm512_castsi512_ps(_mm512_or_si512(
+            _mm512_and_si512(_mm512_castps_si512(updated_z), sign_mask_vec),
+            _mm512_castps_si512(lambda1_vec),
+        ));
+        let denominator = _mm512_add_ps(
+            _mm512_mul_ps(
+                _mm512_add_ps(_mm512_set1_ps(beta), updated_n_sqrt),
+                _mm512_set1_ps(lr_recip),
+            ),
+            _mm512_set1_ps(lambda2),
+        );
+        let updated_emb = _mm512_div_ps(_mm512_sub_ps(signed_lambda1, updated_z), denominator);
+        let dense_mask = _mm512_cmp_ps_mask::<_CMP_NLE_UQ>(_mm512_abs_ps(updated_z), lambda1_vec);
+        let updated_emb = _mm512_maskz_mov_ps(dense_mask, updated_emb);
+
+        _mm512_storeu_ps(ftrl_z_ptr.add(i), updated_z);
+        _mm512_storeu_ps(ftrl_n_ptr.add(i), updated_n);
+        _mm512_storeu_ps(emb_ptr.add(i), updated_emb);
+    }
+
+    avx2::ftrl(
+        &mut ftrl_z[end..],
+        &mut ftrl_n[end..],
+        &mut emb[end..],
+        &grad[end..],
+        lr,
+        beta,
+        lambda1,
+        lambda2,
+    );
+}
+
+#[allow(clippy::missing_safety_doc)]
+#[target_feature(enable = "avx512f,avx2,fma")]
+pub unsafe fn weight_bound(embedding: &mut [f32], weight_bound: f32) {
+    let length = embedding.len();
+    let end = (length / 16) * 16;
+    let embedding_ptr = embedding.as_mut_ptr();
+    for i in (0..end).step_by(16) {
+        let embedding_v = _mm512_loadu_ps(embedding_ptr.add(i));
+
+        let bounded_embedding_v = _mm512_min_ps(
+            _mm512_max_ps(embedding_v, _mm512_set1_ps(-weight_bound)),
+            _mm512_set1_ps(weight_bound),
+        );
+
+        _mm512_storeu_ps(embedding_ptr.add(i), bounded_embedding_v);
+    }
+
+    avx2::weight_bound(&mut embedding[end..], weight_bound);
+}
@@ -1,150 +1,228 @@
-use std::arch::x86_64::*;
-
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn add_assign_avx2(a: &mut [f32], b: &[f32]) {
-    let length = a.len();
-    let end = (length as isize / 8) * 8;
-    let a_ptr = a.as_ptr();
-    let b_ptr = b.as_ptr();
-    for i in (0..end).step_by(8) {
-        let a_v = _mm256_loadu_ps(a_ptr.offset(i));
-        let b_v = _mm256_loadu_ps(b_ptr.offset(i));
-        let result_v = _mm256_add_ps(a_v, b_v);
-        _mm256_storeu_ps(a_ptr.offset(i) as *mut f32, result_v);
+//! Embedding update kernels. Every kernel has a scalar reference implementation and AVX2 and
+//! AVX-512 variants, the functions of this module run the variant of the best [`SimdLevel`]
+//! supported by the CPU.
+
+#![allow(clippy::too_many_arguments)]
+
+use std::sync::OnceLock;
+
+#[cfg(target_arch = "x86_64")]
+pub mod avx2;
+#[cfg(target_arch = "x86_64")]
+pub mod avx512;
+pub mod scalar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    Scalar,
+    Avx2,
+    Avx512,
+}
+
+static SIMD_LEVEL: OnceLock<SimdLevel> = OnceLock::new();
+
+/// Best SIMD level supported by the CPU, detected on the first call.
+pub fn simd_level() -> SimdLevel {
+    *SIMD_LEVEL.get_or_init(detect_simd_level)
+}
+
+fn detect_simd_level() -> SimdLevel {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // AVX-512 kernels update the remainder of the 16 lanes blocks with AVX2
+        let avx2 = is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma");
+        if avx2 && is_x86_feature_detected!("avx512f") {
+            return SimdLevel::Avx512;
+        }
+        if avx2 {
+            return SimdLevel::Avx2;
+        }
     }
-    for i in end as usize..length {
-        a[i] += b[i];
+    SimdLevel::Scalar
+}
+
+macro_rules! dispatch {
+    ($level:expr, $kernel:ident($($arg:expr),* $(,)?)) => {
+        match $level {
+            // safe since the level is supported by the CPU
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx512 => unsafe { avx512::$kernel($($arg),*) },
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx2 => unsafe { avx2::$kernel($($arg),*) },
+            _ => scalar::$kernel($($arg),*),
+        }
+    };
+}
+
+// the SIMD kernels read and write `length` elements of every slice without bounds checks
+fn check_len(length: usize, slices: &[usize]) {
+    assert!(
+        slices.iter().all(|slice_len| *slice_len >= length),
+        "slices shorter than {}: {:?}",
+        length,
+        slices
+    );
+}
+
+/// Trust ratio `||emb|| / ||update||` of LAMB from the squared norms.
+pub(crate) fn lamb_trust_ratio(emb_squared_norm: f32, update_squared_norm: f32) -> f32 {
+    let emb_norm = emb_squared_norm.sqrt();
+    let update_norm = update_squared_norm.sqrt();
+    if emb_norm > 0.0_f32 && update_norm > 0.0_f32 {
+        emb_norm / update_norm
+    } else {
+        1.0_f32
     }
 }
 
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn decayed_adagrad_avx2(
+pub fn add_assign(a: &mut [f32], b: &[f32]) {
+    check_len(a.len(), &[b.len()]);
+    dispatch!(simd_level(), add_assign(a, b))
+}
+
+pub fn decayed_adagrad(
     adagrad: &mut [f32],
     embedding: &mut [f32],
     gradient: &[f32],
     g_square_momentum: f32,
     learning_rate: f32,
     eps: f32,
+    weight_decay: f32,
 ) {
-    let length = adagrad.len();
-    let end = (length / 8) * 8;
-    let adagrad_ptr = adagrad.as_ptr();
-    let embedding_ptr = embedding.as_ptr();
-    let gradient_ptr = gradient.as_ptr();
-    for i in (0..end as isize).step_by(8) {
-        let adagrad_v = _mm256_loadu_ps(adagrad_ptr.offset(i));
-        let embedding_v = _mm256_loadu_ps(embedding_ptr.offset(i));
-        let gradient_v = _mm256_loadu_ps(gradient_ptr.offset(i));
-
-        let squared_v = _mm256_mul_ps(gradient_v, gradient_v);
-
-        let scaled_gradient_v = _mm256_mul_ps(
-            gradient_v,
-            _mm256_rsqrt_ps(_mm256_add_ps(adagrad_v, _mm256_set1_ps(eps))),
-        );
-
-        let embedding_result_v = _mm256_fnmadd_ps(
-            _mm256_set1_ps(learning_rate),
-            scaled_gradient_v,
-            embedding_v,
-        );
-
-        _mm256_storeu_ps(embedding_ptr.offset(i) as *mut f32, embedding_result_v);
-
-        let updated_adagrad_v =
-            _mm256_fmadd_ps(adagrad_v, _mm256_set1_ps(g_square_momentum), squared_v);
-
-        _mm256_storeu_ps(adagrad_ptr.offset(i) as *mut f32, updated_adagrad_v);
-    }
-
-    for i in end..length {
-        let adagrad_v = &adagrad[i];
-        let embedding_v = &embedding[i];
-        let gradient_v = &gradient[i];
-
-        let squared_v = gradient_v * gradient_v;
-
-        let scaled_gradient_v = gradient_v * (adagrad_v + eps).sqrt().recip();
-
-        let embedding_result_v = -learning_rate * scaled_gradient_v + embedding_v;
-
-        embedding[i] = embedding_result_v;
-
-        let updated_adagrad_v = adagrad_v * g_square_momentum + squared_v;
-
-        adagrad[i] = updated_adagrad_v;
-    }
+    check_len(adagrad.len(), &[embedding.len(), gradient.len()]);
+    dispatch!(
+        simd_level(),
+        decayed_adagrad(
+            adagrad,
+            embedding,
+            gradient,
+            g_square_momentum,
+            learning_rate,
+            eps,
+            weight_decay,
+        )
+    )
 }
 
-#[allow(clippy::missing_safety_doc)]
-/// remember to update adagrad with gradient squared after this, the function will not do this
-pub unsafe fn decayed_adagrad_vectorwise_shared_avx2(
+/// remember to update adagrad with gradient squared after this, the function will not do this.
+/// Returns the sum of the squared weight decayed gradients for that.
+pub fn decayed_adagrad_vectorwise_shared(
     adagrad: f32,
     embedding: &mut [f32],
     gradient: &[f32],
     learning_rate: f32,
     eps: f32,
-) {
-    let length = embedding.len();
-    let end = (length / 8) * 8;
-    let embedding_ptr = embedding.as_ptr();
-    let gradient_ptr = gradient.as_ptr();
-    let adagrad_v = _mm256_set1_ps(adagrad);
-    for i in (0..end as isize).step_by(8) {
-        let embedding_v = _mm256_loadu_ps(embedding_ptr.offset(i));
-        let gradient_v = _mm256_loadu_ps(gradient_ptr.offset(i));
-
-        let scaled_gradient_v = _mm256_mul_ps(
-            gradient_v,
-            _mm256_rsqrt_ps(_mm256_add_ps(adagrad_v, _mm256_set1_ps(eps))),
-        );
-
-        let embedding_result_v = _mm256_fnmadd_ps(
-            _mm256_set1_ps(learning_rate),
-            scaled_gradient_v,
-            embedding_v,
-        );
-
-        _mm256_storeu_ps(embedding_ptr.offset(i) as *mut f32, embedding_result_v);
-    }
-
-    for i in end..length {
-        let embedding_v = &embedding[i];
-        let gradient_v = &gradient[i];
-
-        let scaled_gradient_v = gradient_v * (adagrad + eps).sqrt().recip();
-
-        let embedding_result_v = -learning_rate * scaled_gradient_v + embedding_v;
+    weight_decay: f32,
+) -> f32 {
+    check_len(embedding.len(), &[gradient.len()]);
+    dispatch!(
+        simd_level(),
+        decayed_adagrad_vectorwise_shared(
+            adagrad,
+            embedding,
+            gradient,
+            learning_rate,
+            eps,
+            weight_decay,
+        )
+    )
+}
 
-        embedding[i] = embedding_result_v;
-    }
+pub fn decayed_sgd(emb: &mut [f32], grad: &[f32], wd: f32, lr: f32) {
+    check_len(emb.len(), &[grad.len()]);
+    dispatch!(simd_level(), decayed_sgd(emb, grad, wd, lr))
 }
 
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn decayed_sgd_avx2(emb: &mut [f32], grad: &[f32], wd: f32, lr: f32) {
-    let length = emb.len();
-    let end = (length / 8) * 8; // divide by simd step
-    let grad_ptr = grad.as_ptr();
-    let emb_ptr = emb.as_ptr();
+pub fn adam(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    check_len(emb.len(), &[adam_m.len(), adam_v.len(), grad.len()]);
+    dispatch!(
+        simd_level(),
+        adam(
+            adam_m,
+            adam_v,
+            beta1_power,
+            beta2_power,
+            emb,
+            grad,
+            lr,
+            beta1,
+            beta2,
+            eps,
+            weight_decay,
+        )
+    )
+}
 
-    for i in (0..end as isize).step_by(8) {
-        let grad_v = _mm256_loadu_ps(grad_ptr.offset(i));
-        let emb_v = _mm256_loadu_ps(emb_ptr.offset(i));
+/// Adam with weight decay decoupled from the gradient moments
+pub fn adamw(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    check_len(emb.len(), &[adam_m.len(), adam_v.len(), grad.len()]);
+    dispatch!(
+        simd_level(),
+        adamw(
+            adam_m,
+            adam_v,
+            beta1_power,
+            beta2_power,
+            emb,
+            grad,
+            lr,
+            beta1,
+            beta2,
+            eps,
+            weight_decay,
+        )
+    )
+}
 
-        // weight decay
-        let decay_grad_v = _mm256_fmadd_ps(_mm256_set1_ps(wd), emb_v, grad_v);
-        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), decay_grad_v, emb_v);
-        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
-    }
+pub fn rmsprop(rms: &mut [f32], emb: &mut [f32], grad: &[f32], lr: f32, alpha: f32, eps: f32) {
+    check_len(emb.len(), &[rms.len(), grad.len()]);
+    dispatch!(simd_level(), rmsprop(rms, emb, grad, lr, alpha, eps))
+}
 
-    for i in end..length {
-        let decay_grad_v = grad[i] + emb[i] * wd;
-        emb[i] = emb[i] - lr * decay_grad_v;
-    }
+/// SGD with momentum, Nesterov momentum if `nesterov` is set
+pub fn momentum_sgd(
+    momentum_buffer: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    wd: f32,
+    momentum: f32,
+    nesterov: bool,
+) {
+    check_len(emb.len(), &[momentum_buffer.len(), grad.len()]);
+    dispatch!(
+        simd_level(),
+        momentum_sgd(momentum_buffer, emb, grad, lr, wd, momentum, nesterov)
+    )
 }
 
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn adam_avx2(
+/// LAMB, Adam with decoupled weight decay whose update is scaled by the trust ratio
+/// `||emb|| / ||update||` of the whole embedding
+pub fn lamb(
     adam_m: &mut [f32],
     adam_v: &mut [f32],
     beta1_power: f32,
@@ -155,97 +233,312 @@ pub unsafe fn adam_avx2(
     beta1: f32,
     beta2: f32,
     eps: f32,
+    weight_decay: f32,
 ) {
-    let length = emb.len();
-    let end = (length / 8) * 8; // divide by simd step
-    let adam_m_ptr = adam_m.as_ptr();
-    let adam_v_ptr = adam_v.as_ptr();
-    let grad_ptr = grad.as_ptr();
-    let emb_ptr = emb.as_ptr();
-
-    // precomputed the constant value
-    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
-    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
-    let one_minus_beta1 = 1.0_f32 - beta1;
-    let one_minus_beta2 = 1.0_f32 - beta2;
-
-    for i in (0..end as isize).step_by(8) {
-        let grad_vec = _mm256_loadu_ps(grad_ptr.offset(i));
-        let emb_vec = _mm256_loadu_ps(emb_ptr.offset(i));
-        let adam_v_vec = _mm256_loadu_ps(adam_v_ptr.offset(i));
-        let adam_m_vec = _mm256_loadu_ps(adam_m_ptr.offset(i));
-
-        let updated_m = _mm256_fmadd_ps(
-            _mm256_set1_ps(beta1),
-            adam_m_vec,
-            _mm256_mul_ps(_mm256_set1_ps(one_minus_beta1), grad_vec),
-        );
-
-        let updated_v = _mm256_fmadd_ps(
-            _mm256_set1_ps(beta2),
-            adam_v_vec,
-            _mm256_mul_ps(
-                _mm256_set1_ps(one_minus_beta2),
-                _mm256_mul_ps(grad_vec, grad_vec),
-            ),
-        );
-
-        let m_bias_corr = _mm256_mul_ps(updated_m, _mm256_set1_ps(one_minus_beta1_power_recip));
-        let v_bias_corr = _mm256_mul_ps(updated_v, _mm256_set1_ps(one_minus_beta2_power_recip));
-
-        let descent = _mm256_div_ps(
-            m_bias_corr,
-            _mm256_add_ps(_mm256_set1_ps(eps), _mm256_sqrt_ps(v_bias_corr)),
-        );
-
-        let updated_emb = _mm256_fnmadd_ps(_mm256_set1_ps(lr), descent, emb_vec);
-
-        _mm256_storeu_ps(adam_m_ptr.offset(i) as *mut f32, updated_m);
-        _mm256_storeu_ps(adam_v_ptr.offset(i) as *mut f32, updated_v);
-        _mm256_storeu_ps(emb_ptr.offset(i) as *mut f32, updated_emb);
-    }
-
-    for i in end..length {
-        let grad_val = &grad[i];
-        let emb_val = &emb[i];
-        let adam_v_val = &adam_v[i];
-        let adam_m_val = &adam_m[i];
+    check_len(emb.len(), &[adam_m.len(), adam_v.len(), grad.len()]);
+    dispatch!(
+        simd_level(),
+        lamb(
+            adam_m,
+            adam_v,
+            beta1_power,
+            beta2_power,
+            emb,
+            grad,
+            lr,
+            beta1,
+            beta2,
+            eps,
+            weight_decay,
+        )
+    )
+}
 
-        let updated_m = beta1 * adam_m_val + (one_minus_beta1) * grad_val;
-        let updated_v = beta2 * adam_v_val + (one_minus_beta2) * grad_val * grad_val;
+/// FTRL-Proximal update, `ftrl_z` and `ftrl_n` are the per coordinate linear and squared
+/// gradient accumulators
+pub fn ftrl(
+    ftrl_z: &mut [f32],
+    ftrl_n: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta: f32,
+    lambda1: f32,
+    lambda2: f32,
+) {
+    check_len(emb.len(), &[ftrl_z.len(), ftrl_n.len(), grad.len()]);
+    dispatch!(
+        simd_level(),
+        ftrl(ftrl_z, ftrl_n, emb, grad, lr, beta, lambda1, lambda2)
+    )
+}
 
-        let m_bias_corr = updated_m * one_minus_beta1_power_recip;
-        let v_bias_corr = updated_v * one_minus_beta2_power_recip;
+pub fn weight_bound(embedding: &mut [f32], weight_bound: f32) {
+    dispatch!(simd_level(), weight_bound(embedding, weight_bound))
+}
 
-        let descent = m_bias_corr / (eps + v_bias_corr.sqrt());
+#[cfg(test)]
+mod simd_level_tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
 
-        let updated_emb = emb_val - lr * descent;
+    const MAX_LEN: usize = 72;
 
-        adam_m[i] = updated_m;
-        adam_v[i] = updated_v;
-        emb[i] = updated_emb;
+    fn supported_levels() -> Vec<SimdLevel> {
+        vec![SimdLevel::Scalar, SimdLevel::Avx2, SimdLevel::Avx512]
+            .into_iter()
+            .filter(|level| *level <= simd_level())
+            .collect()
     }
-}
 
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn weight_bound(embedding: &mut [f32], weight_bound: f32) {
-    let length = embedding.len();
-    let end = (length / 8) * 8;
-    let embedding_ptr = embedding.as_ptr();
-    let neg_weight_bound = -weight_bound;
-    for i in (0..end as isize).step_by(8) {
-        let embedding_v = _mm256_loadu_ps(embedding_ptr.offset(i));
+    // runs `kernel` with every supported level and checks the outputs agree with the scalar
+    // ones within `tolerance`, relative to the magnitude of the outputs
+    fn assert_levels_agree(kernel: impl Fn(SimdLevel) -> Vec<Vec<f32>>, tolerance: f32) {
+        let expected = kernel(SimdLevel::Scalar);
+        for level in supported_levels() {
+            let outputs = kernel(level);
+            expected
+                .iter()
+                .flatten()
+                .zip(outputs.iter().flatten())
+                .for_each(|(x, y)| {
+                    assert!(
+                        (x - y).abs() <= tolerance * x.abs().max(y.abs()).max(1.0),
+                        "{:?} output {} differs from scalar output {}",
+                        level,
+                        y,
+                        x
+                    );
+                });
+        }
+    }
 
-        let bounded_embedding_v = _mm256_min_ps(
-            _mm256_max_ps(embedding_v, _mm256_set1_ps(neg_weight_bound)),
-            _mm256_set1_ps(weight_bound),
-        );
+    fn values(low: f32, high: f32) -> impl Strategy<Value = Vec<f32>> {
+        vec(low..high, MAX_LEN)
+    }
 
-        _mm256_storeu_ps(embedding_ptr.offset(i) as *mut f32, bounded_embedding_v);
+    proptest! {
+        #[test]
+        fn test_add_assign(len in 0..MAX_LEN, a in values(-1.0, 1.0), b in values(-1.0, 1.0)) {
+            assert_levels_agree(
+                |level| {
+                    let mut a = a[..len].to_vec();
+                    dispatch!(level, add_assign(&mut a, &b));
+                    vec![a]
+                },
+                1e-6,
+            );
+        }
+
+        #[test]
+        fn test_decayed_adagrad(
+            len in 0..MAX_LEN,
+            adagrad in values(0.1, 1.0),
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+            weight_decay in 0.0_f32..0.1,
+        ) {
+            // AVX2 and AVX-512 approximate the reciprocal square root
+            assert_levels_agree(
+                |level| {
+                    let (mut adagrad, mut emb) = (adagrad[..len].to_vec(), emb.clone());
+                    dispatch!(
+                        level,
+                        decayed_adagrad(&mut adagrad, &mut emb, &grad, 0.9, 0.01, 1e-8, weight_decay)
+                    );
+                    vec![adagrad, emb]
+                },
+                1e-4,
+            );
+        }
+
+        #[test]
+        fn test_decayed_adagrad_vectorwise_shared(
+            len in 0..MAX_LEN,
+            adagrad in 0.1_f32..1.0,
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+            weight_decay in 0.0_f32..0.1,
+        ) {
+            assert_levels_agree(
+                |level| {
+                    let mut emb = emb[..len].to_vec();
+                    let squared_sum = dispatch!(
+                        level,
+                        decayed_adagrad_vectorwise_shared(
+                            adagrad,
+                            &mut emb,
+                            &grad,
+                            0.01,
+                            1e-8,
+                            weight_decay,
+                        )
+                    );
+                    vec![emb, vec![squared_sum]]
+                },
+                1e-4,
+            );
+        }
+
+        #[test]
+        fn test_decayed_sgd(
+            len in 0..MAX_LEN,
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+            wd in 0.0_f32..0.1,
+        ) {
+            assert_levels_agree(
+                |level| {
+                    let mut emb = emb[..len].to_vec();
+                    dispatch!(level, decayed_sgd(&mut emb, &grad, wd, 0.01));
+                    vec![emb]
+                },
+                1e-6,
+            );
+        }
+
+        #[test]
+        fn test_adam_and_adamw(
+            len in 0..MAX_LEN,
+            adam_m in values(-1.0, 1.0),
+            adam_v in values(0.0, 1.0),
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+            weight_decay in 0.0_f32..0.1,
+        ) {
+            let kernel = |level, decoupled_weight_decay| {
+                let (mut adam_m, mut adam_v) = (adam_m[..len].to_vec(), adam_v.clone());
+                let mut emb = emb[..len].to_vec();
+                let (m, v) = (&mut adam_m, &mut adam_v);
+                match decoupled_weight_decay {
+                    true => dispatch!(
+                        level,
+                        adamw(m, v, 0.81, 0.998, &mut emb, &grad, 0.01, 0.9, 0.999, 1e-8, weight_decay)
+                    ),
+                    false => dispatch!(
+                        level,
+                        adam(m, v, 0.81, 0.998, &mut emb, &grad, 0.01, 0.9, 0.999, 1e-8, weight_decay)
+                    ),
+                }
+                vec![adam_m, adam_v, emb]
+            };
+            assert_levels_agree(|level| kernel(level, false), 1e-5);
+            assert_levels_agree(|level| kernel(level, true), 1e-5);
+        }
+
+        #[test]
+        fn test_rmsprop(
+            len in 0..MAX_LEN,
+            rms in values(0.0, 1.0),
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+        ) {
+            assert_levels_agree(
+                |level| {
+                    let (mut rms, mut emb) = (rms.clone(), emb[..len].to_vec());
+                    dispatch!(level, rmsprop(&mut rms, &mut emb, &grad, 0.01, 0.99, 1e-8));
+                    vec![rms, emb]
+                },
+                1e-5,
+            );
+        }
+
+        #[test]
+        fn test_momentum_sgd(
+            len in 0..MAX_LEN,
+            buffer in values(-1.0, 1.0),
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+            nesterov in any::<bool>(),
+        ) {
+            assert_levels_agree(
+                |level| {
+                    let (mut buffer, mut emb) = (buffer.clone(), emb[..len].to_vec());
+                    dispatch!(
+                        level,
+                        momentum_sgd(&mut buffer, &mut emb, &grad, 0.01, 0.01, 0.9, nesterov)
+                    );
+                    vec![buffer, emb]
+                },
+                1e-5,
+            );
+        }
+
+        #[test]
+        fn test_lamb(
+            len in 0..MAX_LEN,
+            adam_m in values(-1.0, 1.0),
+            adam_v in values(0.0, 1.0),
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+            weight_decay in 0.0_f32..0.1,
+        ) {
+            assert_levels_agree(
+                |level| {
+                    let (mut adam_m, mut adam_v) = (adam_m.clone(), adam_v.clone());
+                    let mut emb = emb[..len].to_vec();
+                    dispatch!(
+                        level,
+                        lamb(
+                            &mut adam_m,
+                            &mut adam_v,
+                            0.81,
+                            0.998,
+                            &mut emb,
+                            &grad,
+                            0.01,
+                            0.9,
+                            0.999,
+                            1e-6,
+                            weight_decay,
+                        )
+                    );
+                    vec![adam_m, adam_v, emb]
+                },
+                1e-5,
+            );
+        }
+
+        #[test]
+        fn test_ftrl(
+            len in 0..MAX_LEN,
+            ftrl_z in values(-1.0, 1.0),
+            ftrl_n in values(0.0, 1.0),
+            emb in values(-1.0, 1.0),
+            grad in values(-1.0, 1.0),
+        ) {
+            assert_levels_agree(
+                |level| {
+                    let (mut ftrl_z, mut ftrl_n) = (ftrl_z.clone(), ftrl_n.clone());
+                    let mut emb = emb[..len].to_vec();
+                    dispatch!(
+                        level,
+                        ftrl(&mut ftrl_z, &mut ftrl_n, &mut emb, &grad, 0.05, 1.0, 0.1, 0.01)
+                    );
+                    vec![ftrl_z, ftrl_n, emb]
+                },
+                1e-5,
+            );
+        }
+
+        #[test]
+        fn test_weight_bound(len in 0..MAX_LEN, emb in values(-2.0, 2.0), bound in 0.0_f32..1.0) {
+            assert_levels_agree(
+                |level| {
+                    let mut emb = emb[..len].to_vec();
+                    dispatch!(level, weight_bound(&mut emb, bound));
+                    vec![emb]
+                },
+                0.0,
+            );
+        }
     }
 
-    for embedding_v in embedding.iter_mut().skip(end) {
-        let bounded_embedding_v = embedding_v.max(neg_weight_bound).min(weight_bound);
-        *embedding_v = bounded_embedding_v;
+    #[test]
+    #[should_panic]
+    fn test_short_slices() {
+        let mut emb = vec![0.0_f32; 16];
+        decayed_sgd(&mut emb, &[0.0_f32; 15], 0.0, 0.01);
     }
 }
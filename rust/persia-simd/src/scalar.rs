@@ -0,0 +1,226 @@
+//! Scalar reference kernels, used on CPUs without AVX2.
+
+pub fn add_assign(a: &mut [f32], b: &[f32]) {
+    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+}
+
+pub fn decayed_adagrad(
+    adagrad: &mut [f32],
+    embedding: &mut [f32],
+    gradient: &[f32],
+    g_square_momentum: f32,
+    learning_rate: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    for i in 0..adagrad.len() {
+        let gradient_val = gradient[i] + weight_decay * embedding[i];
+
+        let scaled_gradient_val = gradient_val * (adagrad[i] + eps).sqrt().recip();
+        embedding[i] -= learning_rate * scaled_gradient_val;
+
+        adagrad[i] = adagrad[i] * g_square_momentum + gradient_val * gradient_val;
+    }
+}
+
+/// remember to update adagrad with gradient squared after this, the function will not do this.
+/// Returns the sum of the squared weight decayed gradients for that.
+pub fn decayed_adagrad_vectorwise_shared(
+    adagrad: f32,
+    embedding: &mut [f32],
+    gradient: &[f32],
+    learning_rate: f32,
+    eps: f32,
+    weight_decay: f32,
+) -> f32 {
+    let adagrad_rsqrt = (adagrad + eps).sqrt().recip();
+    let mut squared_sum = 0.0_f32;
+    for i in 0..embedding.len() {
+        let gradient_val = gradient[i] + weight_decay * embedding[i];
+        squared_sum += gradient_val * gradient_val;
+
+        embedding[i] -= learning_rate * gradient_val * adagrad_rsqrt;
+    }
+    squared_sum
+}
+
+pub fn decayed_sgd(emb: &mut [f32], grad: &[f32], wd: f32, lr: f32) {
+    for i in 0..emb.len() {
+        let decay_grad_val = grad[i] + emb[i] * wd;
+        emb[i] -= lr * decay_grad_val;
+    }
+}
+
+pub fn adam(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+
+    for i in 0..emb.len() {
+        // L2 weight decay
+        let grad_val = grad[i] + weight_decay * emb[i];
+
+        adam_m[i] = beta1 * adam_m[i] + (1.0_f32 - beta1) * grad_val;
+        adam_v[i] = beta2 * adam_v[i] + (1.0_f32 - beta2) * grad_val * grad_val;
+
+        let m_bias_corr = adam_m[i] * one_minus_beta1_power_recip;
+        let v_bias_corr = adam_v[i] * one_minus_beta2_power_recip;
+
+        emb[i] -= lr * m_bias_corr / (eps + v_bias_corr.sqrt());
+    }
+}
+
+/// Adam with weight decay decoupled from the gradient moments
+pub fn adamw(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+
+    for i in 0..emb.len() {
+        let grad_val = grad[i];
+
+        adam_m[i] = beta1 * adam_m[i] + (1.0_f32 - beta1) * grad_val;
+        adam_v[i] = beta2 * adam_v[i] + (1.0_f32 - beta2) * grad_val * grad_val;
+
+        let m_bias_corr = adam_m[i] * one_minus_beta1_power_recip;
+        let v_bias_corr = adam_v[i] * one_minus_beta2_power_recip;
+
+        let descent = m_bias_corr / (eps + v_bias_corr.sqrt());
+        emb[i] -= lr * (descent + weight_decay * emb[i]);
+    }
+}
+
+pub fn rmsprop(rms: &mut [f32], emb: &mut [f32], grad: &[f32], lr: f32, alpha: f32, eps: f32) {
+    for i in 0..emb.len() {
+        let grad_val = grad[i];
+
+        rms[i] = alpha * rms[i] + (1.0_f32 - alpha) * grad_val * grad_val;
+        emb[i] -= lr * grad_val / (rms[i].sqrt() + eps);
+    }
+}
+
+/// SGD with momentum, Nesterov momentum if `nesterov` is set
+pub fn momentum_sgd(
+    momentum_buffer: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    wd: f32,
+    momentum: f32,
+    nesterov: bool,
+) {
+    for i in 0..emb.len() {
+        let decay_grad_val = grad[i] + emb[i] * wd;
+        momentum_buffer[i] = momentum * momentum_buffer[i] + decay_grad_val;
+        let descent = match nesterov {
+            true => decay_grad_val + momentum * momentum_buffer[i],
+            false => momentum_buffer[i],
+        };
+
+        emb[i] -= lr * descent;
+    }
+}
+
+/// LAMB, Adam with decoupled weight decay whose update is scaled by the trust ratio
+/// `||emb|| / ||update||` of the whole embedding
+pub fn lamb(
+    adam_m: &mut [f32],
+    adam_v: &mut [f32],
+    beta1_power: f32,
+    beta2_power: f32,
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta1: f32,
+    beta2: f32,
+    eps: f32,
+    weight_decay: f32,
+) {
+    let one_minus_beta1_power_recip = (1.0_f32 - beta1_power).recip();
+    let one_minus_beta2_power_recip = (1.0_f32 - beta2_power).recip();
+    let decayed_descent = |adam_m: f32, adam_v: f32, emb: f32| {
+        let m_bias_corr = adam_m * one_minus_beta1_power_recip;
+        let v_bias_corr = adam_v * one_minus_beta2_power_recip;
+        m_bias_corr / (eps + v_bias_corr.sqrt()) + weight_decay * emb
+    };
+
+    // first pass updates the moments and computes the norms of the embedding and its update
+    let mut emb_norm = 0.0_f32;
+    let mut update_norm = 0.0_f32;
+    for i in 0..emb.len() {
+        let grad_val = grad[i];
+
+        adam_m[i] = beta1 * adam_m[i] + (1.0_f32 - beta1) * grad_val;
+        adam_v[i] = beta2 * adam_v[i] + (1.0_f32 - beta2) * grad_val * grad_val;
+
+        let descent = decayed_descent(adam_m[i], adam_v[i], emb[i]);
+        emb_norm += emb[i] * emb[i];
+        update_norm += descent * descent;
+    }
+
+    // second pass applies the update from the updated moments
+    let scaled_lr = lr * crate::lamb_trust_ratio(emb_norm, update_norm);
+    for i in 0..emb.len() {
+        emb[i] -= scaled_lr * decayed_descent(adam_m[i], adam_v[i], emb[i]);
+    }
+}
+
+/// FTRL-Proximal update, `ftrl_z` and `ftrl_n` are the per coordinate linear and squared
+/// gradient accumulators
+pub fn ftrl(
+    ftrl_z: &mut [f32],
+    ftrl_n: &mut [f32],
+    emb: &mut [f32],
+    grad: &[f32],
+    lr: f32,
+    beta: f32,
+    lambda1: f32,
+    lambda2: f32,
+) {
+    let lr_recip = lr.recip();
+    for i in 0..emb.len() {
+        let grad_val = grad[i];
+
+        let updated_n = ftrl_n[i] + grad_val * grad_val;
+        let updated_n_sqrt = updated_n.sqrt();
+        let sigma = (updated_n_sqrt - ftrl_n[i].sqrt()) * lr_recip;
+        let updated_z = (ftrl_z[i] + grad_val) - sigma * emb[i];
+
+        emb[i] = if updated_z.abs() <= lambda1 {
+            0.0_f32
+        } else {
+            let denominator = (beta + updated_n_sqrt) * lr_recip + lambda2;
+            (lambda1.copysign(updated_z) - updated_z) / denominator
+        };
+        ftrl_z[i] = updated_z;
+        ftrl_n[i] = updated_n;
+    }
+}
+
+pub fn weight_bound(embedding: &mut [f32], weight_bound: f32) {
+    embedding.iter_mut().for_each(|embedding_val| {
+        *embedding_val = embedding_val.max(-weight_bound).min(weight_bound)
+    });
+}
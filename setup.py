@@ -28,6 +28,7 @@ if __name__ == "__main__":
             {
                 "persia-embedding-worker": "persia.persia-embedding-worker",
                 "persia-embedding-parameter-server": "persia.persia-embedding-parameter-server",
+                "persia-config-check": "persia.persia-config-check",
             },
             path="rust/persia-embedding-server/Cargo.toml",
             binding=Binding.Exec,
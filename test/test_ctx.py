@@ -140,6 +140,8 @@ def test_data_ctx(use_cuda: bool):
                 embedding_config.admit_probability,
                 embedding_config.weight_bound > 0,
                 embedding_config.weight_bound,
+                embedding_config.admit_frequency,
+                embedding_config.feature_group_hyperparameters(),
             )
             ctx.common_context.wait_servers_ready()
 